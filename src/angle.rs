@@ -0,0 +1,366 @@
+//! Exact angles: a rational multiple of pi plus a floating point remainder.
+//!
+//! `pi/4 + pi/4` evaluates to exactly `pi/2`, so Clifford and T rotations can be recognized
+//! without a tolerance. Operations which cannot be done exactly (`sin`, `^`, multiplying two
+//! multiples of pi, ...) move the value into the remainder. Literals such as
+//! `0.7853981633974483` are only exact after `Angle::snap`.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::ops::{Add, Neg, Sub};
+
+use parser::Expr;
+
+/// The largest denominator recognized when a number is read as a fraction.
+const MAX_DENOMINATOR: i64 = 64;
+
+/// Numbers within this distance of a multiple of pi with a small denominator are taken as that
+/// multiple by `Expr::snapped_angle`, e.g. the literal `0.7853981633974483` as `pi/4`.
+pub const TOLERANCE: f64 = 1e-9;
+
+/// `pi * num / den + rem`. `den` is positive and `num / den` is reduced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Angle {
+    num: i64,
+    den: i64,
+    rem: f64,
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
+/// Reduces `num / den`, or returns `None` if it does not fit in `i64`.
+fn reduce(num: i128, den: i128) -> Option<(i64, i64)> {
+    if den == 0 {
+        return None;
+    }
+    let (num, den) = if den < 0 { (-num, -den) } else { (num, den) };
+    let mut a = num.abs();
+    let mut b = den;
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    let g = if a == 0 { 1 } else { a };
+    let (num, den) = (num / g, den / g);
+    if num.abs() > i64::MAX as i128 || den > i64::MAX as i128 {
+        None
+    } else {
+        Some((num as i64, den as i64))
+    }
+}
+
+/// Reads `v` as `n / d` with a small `d`, if `v * d` is an integer in floating point, e.g. `0.1`
+/// as `1 / 10`.
+fn as_fraction(v: f64) -> Option<(i64, i64)> {
+    if !v.is_finite() || v.abs() >= 1e15 {
+        return None;
+    }
+    for d in 1..MAX_DENOMINATOR + 1 {
+        let n = v * d as f64;
+        if n.fract() == 0.0 {
+            let n = n as i64;
+            let g = gcd(n, d).max(1);
+            return Some((n / g, d / g));
+        }
+    }
+    None
+}
+
+fn boxed(e: Expr) -> Box<Expr> {
+    Box::new(e)
+}
+
+/// Writes a number, as an integer if possible. Negative numbers are written with `Expr::Neg`.
+fn number(v: f64) -> Expr {
+    if v < 0.0 {
+        Expr::Neg(boxed(number(-v)))
+    } else if v.fract() == 0.0 && v < 9007199254740992.0 {
+        Expr::NnInteger(v as usize)
+    } else {
+        Expr::Real(v)
+    }
+}
+
+impl Angle {
+    /// `pi * num / den`. Panics if `den` is zero.
+    pub fn new(num: i64, den: i64) -> Angle {
+        let (num, den) = reduce(num as i128, den as i128).expect("Invalid fraction.");
+        Angle { num, den, rem: 0.0 }
+    }
+
+    pub fn zero() -> Angle {
+        Angle { num: 0, den: 1, rem: 0.0 }
+    }
+
+    /// A number which is not known to be a multiple of pi.
+    pub fn from_f64(v: f64) -> Angle {
+        Angle { num: 0, den: 1, rem: v }
+    }
+
+    /// The coefficient of pi as `(numerator, denominator)`.
+    pub fn pi_fraction(&self) -> (i64, i64) {
+        (self.num, self.den)
+    }
+
+    /// The part which is not a rational multiple of pi.
+    pub fn remainder(&self) -> f64 {
+        self.rem
+    }
+
+    pub fn value(&self) -> f64 {
+        self.num as f64 / self.den as f64 * PI + self.rem
+    }
+
+    /// Whether the angle is exactly a rational multiple of pi.
+    pub fn is_exact(&self) -> bool {
+        self.rem == 0.0
+    }
+
+    /// Reads the value as a rational number when it is not a multiple of pi, e.g. `4` or `0.5`.
+    fn as_number(&self) -> Option<(i64, i64)> {
+        if self.num == 0 {
+            as_fraction(self.rem)
+        } else {
+            None
+        }
+    }
+
+    /// Reduces the exact part into `[0, 2*pi)`. The remainder is kept. Angles whose numerator
+    /// would not fit in `i64` afterwards, which needs a denominator above `i64::MAX / 2`, are
+    /// returned unchanged.
+    pub fn normalized(&self) -> Angle {
+        let num = (self.num as i128).rem_euclid(2 * self.den as i128);
+        match reduce(num, self.den as i128) {
+            Some((num, den)) => Angle { num, den, rem: self.rem },
+            None => *self,
+        }
+    }
+
+    /// Replaces the angle by an exact one if its value is within `tol` of `pi * n / d` for a
+    /// small `d`. Useful for literals such as `0.7853981633974483`.
+    pub fn snap(&self, tol: f64) -> Angle {
+        if self.is_exact() {
+            return *self;
+        }
+        let v = self.value() / PI;
+        for d in 1..MAX_DENOMINATOR + 1 {
+            let n = (v * d as f64).round();
+            if ((n / d as f64 - v) * PI).abs() <= tol && n.abs() < 1e15 {
+                return Angle::new(n as i64, d);
+            }
+        }
+        *self
+    }
+
+    /// `k` such that the angle is `k * pi / 4` modulo `2*pi`, if it is exactly one.
+    pub fn quarter_turns(&self) -> Option<u8> {
+        if !self.is_exact() || 4 % self.den != 0 {
+            return None;
+        }
+        let n = self.normalized();
+        Some((n.num * (4 / n.den)) as u8)
+    }
+
+    /// Whether the angle is exactly zero modulo `2*pi`.
+    pub fn is_zero(&self) -> bool {
+        self.quarter_turns() == Some(0)
+    }
+
+    /// Whether the angle is an exact multiple of `pi/2`, i.e. a phase or rotation by it is a
+    /// Clifford gate.
+    pub fn is_clifford(&self) -> bool {
+        self.quarter_turns().map(|k| k % 2 == 0).unwrap_or(false)
+    }
+
+    /// Whether the angle is an odd multiple of `pi/4`, i.e. it needs one T gate.
+    pub fn is_t(&self) -> bool {
+        self.quarter_turns().map(|k| k % 2 == 1).unwrap_or(false)
+    }
+
+    fn scale(&self, n: i64, d: i64) -> Angle {
+        match reduce(self.num as i128 * n as i128, self.den as i128 * d as i128) {
+            Some((num, den)) => Angle { num, den, rem: self.rem * n as f64 / d as f64 },
+            None => Angle::from_f64(self.value() * n as f64 / d as f64),
+        }
+    }
+
+    pub fn mul(&self, rhs: &Angle) -> Angle {
+        if let Some((n, d)) = rhs.as_number() {
+            self.scale(n, d)
+        } else if let Some((n, d)) = self.as_number() {
+            rhs.scale(n, d)
+        } else {
+            Angle::from_f64(self.value() * rhs.value())
+        }
+    }
+
+    pub fn div(&self, rhs: &Angle) -> Angle {
+        match rhs.as_number() {
+            Some((n, d)) if n != 0 => self.scale(d, n),
+            _ if rhs.is_exact() && self.is_exact() && rhs.num != 0 => {
+                // (pi * a) / (pi * b) is the rational number a / b.
+                match reduce(self.num as i128 * rhs.den as i128, self.den as i128 * rhs.num as i128) {
+                    Some((n, d)) => Angle::from_f64(n as f64 / d as f64),
+                    None => Angle::from_f64(self.value() / rhs.value()),
+                }
+            }
+            _ => Angle::from_f64(self.value() / rhs.value()),
+        }
+    }
+
+    /// Writes the angle as an expression, e.g. `3*pi/4`, `-pi/2` or `pi/4 + 0.1`.
+    pub fn to_expr(&self) -> Expr {
+        if self.num == 0 {
+            return number(self.rem);
+        }
+        let pi = pi_fraction_expr(self.num, self.den);
+        if self.rem == 0.0 {
+            pi
+        } else if self.rem < 0.0 {
+            Expr::Sub(boxed(pi), boxed(number(-self.rem)))
+        } else {
+            Expr::Add(boxed(pi), boxed(number(self.rem)))
+        }
+    }
+}
+
+/// Writes `pi * num / den` as `num*pi/den`, omitting ones.
+fn pi_fraction_expr(num: i64, den: i64) -> Expr {
+    if num < 0 {
+        return Expr::Neg(boxed(pi_fraction_expr(-num, den)));
+    }
+    let numer = if num == 1 {
+        Expr::Pi
+    } else {
+        Expr::Mul(boxed(Expr::NnInteger(num as usize)), boxed(Expr::Pi))
+    };
+    if den == 1 {
+        numer
+    } else {
+        Expr::Div(boxed(numer), boxed(Expr::NnInteger(den as usize)))
+    }
+}
+
+impl Add for Angle {
+    type Output = Angle;
+    fn add(self, rhs: Angle) -> Angle {
+        match reduce(self.num as i128 * rhs.den as i128 + rhs.num as i128 * self.den as i128,
+                     self.den as i128 * rhs.den as i128) {
+            Some((num, den)) => Angle { num, den, rem: self.rem + rhs.rem },
+            None => Angle::from_f64(self.value() + rhs.value()),
+        }
+    }
+}
+
+impl Sub for Angle {
+    type Output = Angle;
+    fn sub(self, rhs: Angle) -> Angle {
+        self + -rhs
+    }
+}
+
+impl Neg for Angle {
+    type Output = Angle;
+    fn neg(self) -> Angle {
+        Angle { num: -self.num, den: self.den, rem: -self.rem }
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression into an exact angle. Returns `None` if it has an identifier.
+    pub fn eval_angle(&self) -> Option<Angle> {
+        self.eval_angle_with(&HashMap::new())
+    }
+
+    /// Like `eval_angle`, with values within `TOLERANCE` of a multiple of pi taken as exact.
+    pub fn snapped_angle(&self) -> Option<Angle> {
+        self.eval_angle().map(|a| a.snap(TOLERANCE))
+    }
+
+    /// Like `eval_angle`, looking up identifiers in `values`.
+    pub fn eval_angle_with(&self, values: &HashMap<String, f64>) -> Option<Angle> {
+        Some(match *self {
+            Expr::Real(v) => Angle::from_f64(v),
+            Expr::NnInteger(v) => Angle::from_f64(v as f64),
+            Expr::Pi => Angle::new(1, 1),
+            Expr::Id(ref name) => Angle::from_f64(*values.get(name)?),
+            Expr::Add(ref lhs, ref rhs) => lhs.eval_angle_with(values)? + rhs.eval_angle_with(values)?,
+            Expr::Sub(ref lhs, ref rhs) => lhs.eval_angle_with(values)? - rhs.eval_angle_with(values)?,
+            Expr::Mul(ref lhs, ref rhs) => lhs.eval_angle_with(values)?.mul(&rhs.eval_angle_with(values)?),
+            Expr::Div(ref lhs, ref rhs) => lhs.eval_angle_with(values)?.div(&rhs.eval_angle_with(values)?),
+            Expr::Neg(ref v) => -v.eval_angle_with(values)?,
+            Expr::Pow(ref lhs, ref rhs) => {
+                Angle::from_f64(lhs.eval_angle_with(values)?.value().powf(rhs.eval_angle_with(values)?.value()))
+            }
+            Expr::UnaryOp(op, ref v) => Angle::from_f64(op.apply(v.eval_angle_with(values)?.value())),
+        })
+    }
+}
+
+/// The angle of `U(theta, phi, lambda)` as a phase gate, `phi + lambda`, if `theta` is zero.
+/// Returns `None` for other gates and parameters which are not constant.
+pub fn u_phase(params: &[Expr]) -> Option<Angle> {
+    match *params {
+        [ref theta, ref phi, ref lambda] if theta.snapped_angle()?.is_zero() => {
+            Some((phi.eval_angle()? + lambda.eval_angle()?).snap(TOLERANCE))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::expr;
+
+    fn angle(s: &str) -> Angle {
+        expr(s).eval_angle().unwrap()
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        assert_eq!(angle("pi/4 + pi/4"), angle("pi/2"));
+        assert_eq!(angle("0.25 * pi * 2"), Angle::new(1, 2));
+        assert_eq!(angle("3*pi/12 - pi"), Angle::new(-3, 4));
+        assert_eq!(angle("(pi/2) / (pi/4)"), Angle::from_f64(2.0));
+        assert!(!angle("pi * pi").is_exact());
+        assert!((angle("pi/3 + 1").value() - (PI / 3.0 + 1.0)).abs() < 1e-15);
+        assert_eq!(angle("pi * 0.1"), Angle::new(1, 10));
+        assert!(!angle("pi * 0.2500000000001").is_exact());
+    }
+
+    #[test]
+    fn classification() {
+        assert_eq!(angle("-pi/4").quarter_turns(), Some(7));
+        assert!(angle("pi/4 + pi/4").is_clifford());
+        assert!(angle("7*pi/4").is_t());
+        assert!(angle("4*pi").is_zero());
+        assert!(!angle("pi/8").is_clifford() && !angle("pi/8").is_t());
+        assert!(!angle("0.7853981633974483").is_t());
+        assert!(angle("0.7853981633974483").snap(1e-12).is_t());
+        assert!(expr("0.7853981633974483").snapped_angle().unwrap().is_t());
+        assert_eq!(u_phase(&[expr("2*pi"), expr("pi/8"), expr("0.39269908169872414")]), Some(Angle::new(1, 4)));
+        assert_eq!(u_phase(&[expr("0.1"), expr("0"), expr("0")]), None);
+        assert_eq!(Angle::new(-3, 4).normalized(), Angle::new(5, 4));
+        assert_eq!(Angle::new(-1, i64::MAX).normalized(), Angle::new(-1, i64::MAX));
+    }
+
+    #[test]
+    fn to_expr() {
+        assert_eq!(Angle::new(3, 4).to_expr(), Expr::Div(boxed(Expr::Mul(boxed(Expr::NnInteger(3)), boxed(Expr::Pi))),
+                                                         boxed(Expr::NnInteger(4))));
+        assert_eq!(Angle::new(-1, 1).to_expr(), Expr::Neg(boxed(Expr::Pi)));
+        assert_eq!(angle("pi/2 - 0.5").to_expr(), Expr::Sub(boxed(Expr::Div(boxed(Expr::Pi), boxed(Expr::NnInteger(2)))),
+                                                            boxed(Expr::Real(0.5))));
+        assert_eq!(Angle::zero().to_expr(), Expr::NnInteger(0));
+    }
+}
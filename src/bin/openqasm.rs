@@ -0,0 +1,658 @@
+extern crate openqasm;
+
+use std::{env, io, process};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use openqasm::diagnostic::{line_col, Diagnostic, Severity, Span};
+use openqasm::draw::{self, DrawOptions};
+use openqasm::equivalence::{self, EquivalenceOptions, Method};
+use openqasm::formatter::{self, FormatOptions};
+use openqasm::lint::{self, Level, LintConfig, LINTS};
+use openqasm::lower::Registers;
+use openqasm::noise::NoiseModel;
+use openqasm::parser::{self, Program};
+use openqasm::printer::{self, PrintOptions};
+use openqasm::route::{self, CouplingMap};
+use openqasm::translate::{self, EquivalenceLibrary};
+use openqasm::{clifford_t, density, export, inline, lexer, optimize, shots, simulator, stabilizer, stats};
+
+const USAGE: &str = "Usage: openqasm <command> [options] [file]
+
+Reads the file, or standard input if it is missing or '-'.
+
+Commands:
+    tokens                  Print the tokens with their line and column.
+    parse                   Print the syntax tree.
+    check                   Check the program and run the lints.
+        -A, --allow <lint>      Do not report the lint.
+        -W, --warn <lint>       Report the lint as a warning.
+        -D, --deny <lint>       Report the lint as an error.
+        --deny-warnings         Report all lints as errors.
+        --list                  List the lints and their default levels.
+    fmt                     Print the formatted source.
+        --check                 Print nothing; exit with 1 if the source is not formatted.
+        --indent <n>            Indent gate bodies with n spaces (default: 2).
+    expand                  Inline all gate applications down to U and CX.
+    optimize                Cancel inverse gates and merge rotations, also across commuting
+                            gates, and join runs of single-qubit gates; print the program and,
+                            on standard error, the number of removed gates.
+    route                   Map the qubits onto a coupling map and insert swaps (as three cx)
+                            so that two-qubit gates act on connected qubits; print the program
+                            and, on standard error, the final layout.
+        --coupling <file>       Read the coupling map from a JSON list of edges, e.g. [[0, 1]].
+        --line <n>              Use n qubits connected in a line instead.
+        --layout <list>         The physical qubit of each qubit, comma-separated (default:
+                                0, 1, 2, ...).
+    translate               Rewrite the program into a basis of gates, up to a global phase.
+        --basis <list>          The gates, comma-separated, e.g. rz,sx,x,cx (required).
+        --rules <file>          Add the gate declarations of the file as equivalences.
+    clifford-t              Approximate rz, u1 and p rotations which are not multiples of pi/4
+                            by sequences of h, s, t and tdg; print the program and, on standard
+                            error, the T-count and error of each replaced rotation. The
+                            Solovay-Kitaev sequences are far from optimal, so the T-counts are
+                            upper bounds.
+        --precision <x>         The largest error of a rotation (default: 0.001).
+    equiv <file> <file>     Check whether two circuits implement the same unitary, up to a
+                            global phase: exactly for few qubits, else on random input states.
+                            Print the first differing output amplitude if they do not.
+        --tolerance <x>         The largest difference of an amplitude (default: 1e-8).
+        --exact-qubits <n>      Compare unitaries up to n qubits (default: 8).
+        --states <n>            Number of random states for more qubits (default: 3).
+        --seed <n>              Seed of the random states (default: 0).
+        --with-phase            Do not ignore the global phase.
+    simulate                Run the program and print the counts of the measurement results.
+        --shots <n>             Number of runs (default: 1024).
+        --seed <n>              Seed of the random numbers.
+        --method <m>            statevector, density or stabilizer (default: statevector).
+        --state                 Run once and print the final state vector instead.
+    draw                    Draw the circuit as text.
+        --ascii                 Use ASCII rather than box-drawing characters.
+        --width <n>             Fold the diagram at n columns, 0 for never (default: $COLUMNS
+                                or 80).
+    stats                   Print sizes, gate counts, depths, the T-count and the critical path.
+        --json                  Print JSON rather than a table.
+    convert                 Print the program in another format.
+        --to <format>           qasm, svg, quantikz (a LaTeX environment) or latex (a
+                                standalone document) (default: qasm).
+        --no-comments           Leave out comments of qasm output.
+
+Exit status: 0 on success, 1 if 'check' finds errors, 'fmt --check' finds unformatted
+source or 'equiv' finds a difference, 2 on invalid usage, I/O errors and invalid programs.
+
+Options:
+    -h, --help              Print this help.";
+
+/// Writes to standard output. Write errors are ignored, so that piping into e.g. `head` does
+/// not end in a panic.
+fn output(text: &str) {
+    let stdout = io::stdout();
+    let _ = stdout.lock().write_all(text.as_bytes());
+}
+
+/// Why a command failed. The message is printed as it is.
+struct Failure(String);
+
+type CommandResult = Result<i32, Failure>;
+
+fn usage_error<T>(msg: &str) -> Result<T, Failure> {
+    Err(Failure(format!("openqasm: {}\n\n{}", msg, USAGE)))
+}
+
+fn number<T: ::std::str::FromStr>(arg: Option<String>, name: &str) -> Result<T, Failure> {
+    match arg.and_then(|s| s.parse().ok()) {
+        Some(n) => Ok(n),
+        None => usage_error(&format!("{} needs a non-negative integer.", name)),
+    }
+}
+
+/// The source code of the input and its name in messages.
+struct Input {
+    name: String,
+    code: String,
+}
+
+impl Input {
+    fn read(file: Option<String>) -> Result<Input, Failure> {
+        let mut code = String::new();
+        let name = match file {
+            Some(ref path) if path != "-" => {
+                File::open(path).and_then(|mut f| f.read_to_string(&mut code))
+                    .map_err(|e| Failure(format!("openqasm: {}: {}", path, e)))?;
+                path.clone()
+            }
+            _ => {
+                io::stdin().read_to_string(&mut code).map_err(|e| Failure(format!("openqasm: <stdin>: {}", e)))?;
+                "<stdin>".to_owned()
+            }
+        };
+        Ok(Input { name, code })
+    }
+
+    fn fail<T>(&self, diagnostic: &Diagnostic) -> Result<T, Failure> {
+        Err(Failure(diagnostic.render(&self.code, &self.name).trim_end().to_owned()))
+    }
+
+    fn parse(&self) -> Result<(Program, Vec<Span>), Failure> {
+        match parser::parse_str_with_spans(&self.code, Path::new(&self.name)) {
+            Ok(parsed) => Ok(parsed),
+            Err(e) => self.fail(&Diagnostic::from(&e)),
+        }
+    }
+
+    /// Turns an error found after parsing into a failure.
+    fn check<T, E: ToString>(&self, result: Result<T, E>) -> Result<T, Failure> {
+        result.or_else(|e| self.fail(&Diagnostic::error(e.to_string(), None)))
+    }
+}
+
+/// Collects the arguments of a command: its options are handled by `option`, which gets the
+/// option and the remaining arguments and returns whether it knows the option.
+fn command_args<F>(args: env::Args, option: F) -> Result<Option<String>, Failure>
+    where F: FnMut(&str, &mut env::Args) -> Result<bool, Failure>
+{
+    let mut files = command_files(args, option)?;
+    if files.len() > 1 {
+        return usage_error("Only one file can be given.");
+    }
+    Ok(files.pop())
+}
+
+/// Like `command_args`, but for commands which take several files.
+fn command_files<F>(args: env::Args, mut option: F) -> Result<Vec<String>, Failure>
+    where F: FnMut(&str, &mut env::Args) -> Result<bool, Failure>
+{
+    let mut args = args;
+    let mut files = vec![];
+    while let Some(arg) = args.next() {
+        if arg.starts_with('-') && arg != "-" {
+            if !option(&arg, &mut args)? {
+                return usage_error(&format!("Unknown option '{}'.", arg));
+            }
+        } else {
+            files.push(arg);
+        }
+    }
+    Ok(files)
+}
+
+fn no_options(_: &str, _: &mut env::Args) -> Result<bool, Failure> {
+    Ok(false)
+}
+
+fn tokens(args: env::Args) -> CommandResult {
+    let input = Input::read(command_args(args, no_options)?)?;
+    let (mut line, mut line_start, mut scanned) = (1, 0, 0);
+    let mut out = String::new();
+    for tok in lexer::tokenize(input.code.clone().into(), Path::new(&input.name).into()) {
+        let tok = match tok {
+            Ok(tok) => tok,
+            Err(e) => return input.fail(&Diagnostic::from(&e)),
+        };
+        for (i, c) in input.code[scanned..tok.pos].char_indices() {
+            if c == '\n' {
+                line += 1;
+                line_start = scanned + i + 1;
+            }
+        }
+        scanned = tok.pos;
+        let col = input.code[line_start..tok.pos].chars().count() + 1;
+        out.push_str(&format!("{}:{}\t{:?}\n", line, col, tok.token));
+    }
+    output(&out);
+    Ok(0)
+}
+
+fn parse(args: env::Args) -> CommandResult {
+    let input = Input::read(command_args(args, no_options)?)?;
+    let (program, _) = input.parse()?;
+    output(&format!("{:#?}\n", program));
+    Ok(0)
+}
+
+fn lint_name(name: Option<String>, option: &str) -> Result<String, Failure> {
+    match name {
+        Some(name) => Ok(name),
+        None => usage_error(&format!("{} needs the name of a lint.", option)),
+    }
+}
+
+fn check(args: env::Args) -> CommandResult {
+    let mut config = LintConfig::new();
+    let mut levels = vec![];
+    let mut list = false;
+    let file = command_args(args, |opt, args| {
+        let level = match opt {
+            "-A" | "--allow" => Level::Allow,
+            "-W" | "--warn" => Level::Warn,
+            "-D" | "--deny" => Level::Deny,
+            "--deny-warnings" => {
+                config.set_all(Level::Deny);
+                return Ok(true);
+            }
+            "--list" => {
+                list = true;
+                return Ok(true);
+            }
+            _ => return Ok(false),
+        };
+        levels.push((lint_name(args.next(), opt)?, level));
+        Ok(true)
+    })?;
+    if list {
+        for lint in LINTS {
+            output(&format!("{:<24} {:<6} {}\n", lint.id, lint.default_level.to_string(), lint.description));
+        }
+        return Ok(0);
+    }
+    for (name, level) in levels {
+        if let Err(e) = config.set(&name, level) {
+            return usage_error(&e.to_string());
+        }
+    }
+
+    let input = Input::read(file)?;
+    let (program, spans) = input.parse()?;
+    input.check(inline::inline_source(&program, Path::new(&input.name)))?;
+    let diagnostics = input.check(lint::lint(&program, Some(&spans), &config))?;
+    for d in &diagnostics {
+        eprint!("{}", d.render(&input.code, &input.name));
+    }
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    Ok(if errors > 0 { 1 } else { 0 })
+}
+
+fn fmt(args: env::Args) -> CommandResult {
+    let mut options = FormatOptions::default();
+    let mut check = false;
+    let file = command_args(args, |opt, args| {
+        match opt {
+            "--check" => check = true,
+            "--indent" => options.indent = number(args.next(), opt)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    let input = Input::read(file)?;
+    let formatted = match formatter::format_str(&input.code, Path::new(&input.name), &options) {
+        Ok(formatted) => formatted,
+        Err(e) => return input.fail(&Diagnostic::from(&e)),
+    };
+    if check {
+        if formatted != input.code {
+            output(&format!("{}\n", input.name));
+            return Ok(1);
+        }
+    } else {
+        output(&formatted);
+    }
+    Ok(0)
+}
+
+fn expand(args: env::Args) -> CommandResult {
+    let input = Input::read(command_args(args, no_options)?)?;
+    let (program, _) = input.parse()?;
+    let expanded = input.check(inline::inline_source(&program, Path::new(&input.name)))?;
+    output(&printer::to_qasm(&expanded));
+    Ok(0)
+}
+
+fn optimize(args: env::Args) -> CommandResult {
+    let input = Input::read(command_args(args, no_options)?)?;
+    let (program, _) = input.parse()?;
+    let (optimized, removed) = input.check(optimize::optimize(&program))?;
+    output(&printer::to_qasm(&optimized));
+    eprintln!("Removed {} gate{}.", removed, if removed == 1 { "" } else { "s" });
+    Ok(0)
+}
+
+fn route(args: env::Args) -> CommandResult {
+    let mut coupling = None;
+    let mut line = None;
+    let mut layout = None;
+    let file = command_args(args, |opt, args| {
+        match opt {
+            "--coupling" => coupling = Some(args.next().unwrap_or_default()),
+            "--line" => line = Some(number(args.next(), opt)?),
+            "--layout" => layout = Some(args.next().unwrap_or_default()),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    let coupling = match (coupling, line) {
+        (Some(path), None) => {
+            let mut json = String::new();
+            File::open(&path).and_then(|mut f| f.read_to_string(&mut json))
+                .map_err(|e| Failure(format!("openqasm: {}: {}", path, e)))?;
+            CouplingMap::from_json(&json).map_err(|e| Failure(format!("openqasm: {}: {}", path, e)))?
+        }
+        (None, Some(n)) => CouplingMap::line(n),
+        _ => return usage_error("route needs either --coupling or --line."),
+    };
+    let layout = match layout {
+        Some(list) => match list.split(',').map(|p| p.trim().parse()).collect::<Result<Vec<usize>, _>>() {
+            Ok(layout) => Some(layout),
+            Err(_) => return usage_error("--layout needs a comma-separated list of physical qubits."),
+        },
+        None => None,
+    };
+    let input = Input::read(file)?;
+    let (program, _) = input.parse()?;
+    let routed = input.check(route::route(&program, &coupling, layout.as_deref()))?;
+    let regs = input.check(Registers::from_program(&program))?;
+    output(&printer::to_qasm(&routed.program));
+    let final_layout: Vec<String> = routed.final_layout.iter().enumerate()
+        .map(|(i, p)| format!("{} -> {}", regs.qubit(i), p))
+        .collect();
+    eprintln!("Inserted {} swap{}.", routed.num_swaps, if routed.num_swaps == 1 { "" } else { "s" });
+    eprintln!("Final layout: {}", final_layout.join(", "));
+    Ok(0)
+}
+
+fn translate(args: env::Args) -> CommandResult {
+    let mut basis = None;
+    let mut rules = None;
+    let file = command_args(args, |opt, args| {
+        match opt {
+            "--basis" => basis = Some(args.next().unwrap_or_default()),
+            "--rules" => rules = Some(args.next().unwrap_or_default()),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    let basis = match basis {
+        Some(ref list) if !list.is_empty() => list.split(',').map(|g| g.trim()).collect::<Vec<&str>>(),
+        _ => return usage_error("translate needs --basis with a comma-separated list of gates."),
+    };
+    let mut library = EquivalenceLibrary::standard();
+    if let Some(path) = rules {
+        let mut source = String::new();
+        File::open(&path).and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(|e| Failure(format!("openqasm: {}: {}", path, e)))?;
+        library.add_qasm(&source).map_err(|e| Failure(format!("openqasm: {}: {}", path, e)))?;
+    }
+    let input = Input::read(file)?;
+    let (program, _) = input.parse()?;
+    let translated = input.check(translate::translate(&program, &basis, &library))?;
+    output(&printer::to_qasm(&translated));
+    Ok(0)
+}
+
+fn clifford_t(args: env::Args) -> CommandResult {
+    let mut precision = 1e-3;
+    let file = command_args(args, |opt, args| {
+        match opt {
+            "--precision" => match args.next().and_then(|s| s.parse::<f64>().ok()) {
+                Some(p) if p > 0.0 => precision = p,
+                _ => return usage_error("--precision needs a positive number."),
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    let input = Input::read(file)?;
+    let (program, _) = input.parse()?;
+    let result = input.check(clifford_t::approximate_rotations(&program, precision))?;
+    output(&printer::to_qasm(&result.program));
+    for r in &result.rotations {
+        eprintln!("rz({}) on {}: T-count {}, error {:.2e}", r.angle, r.qubit, r.t_count, r.error);
+    }
+    let rotations = result.rotations.len();
+    eprintln!("Replaced {} rotation{} with {} T gates.", rotations, if rotations == 1 { "" } else { "s" },
+              result.t_count());
+    if rotations > 0 {
+        eprintln!("These T-counts are upper bounds; optimal synthesis needs about {} T gates per rotation.",
+                  (3.0 * (1.0 / precision).log2()).ceil());
+    }
+    Ok(0)
+}
+
+/// A basis state as a ket, with `q[0]` as the rightmost bit.
+fn ket(index: usize, num_qubits: usize) -> String {
+    format!("|{:0width$b}>", index, width = num_qubits)
+}
+
+fn equiv(args: env::Args) -> CommandResult {
+    let mut options = EquivalenceOptions::default();
+    let files = command_files(args, |opt, args| {
+        match opt {
+            "--tolerance" => match args.next().and_then(|s| s.parse::<f64>().ok()) {
+                Some(t) if t >= 0.0 => options.tolerance = t,
+                _ => return usage_error("--tolerance needs a non-negative number."),
+            },
+            "--exact-qubits" => options.max_exact_qubits = number(args.next(), opt)?,
+            "--states" => options.num_states = number(args.next(), opt)?,
+            "--seed" => options.seed = number(args.next(), opt)?,
+            "--with-phase" => options.ignore_global_phase = false,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    if files.len() != 2 {
+        return usage_error("equiv needs two files.");
+    }
+    let (first, second) = (Input::read(Some(files[0].clone()))?, Input::read(Some(files[1].clone()))?);
+    let (a, _) = first.parse()?;
+    let (b, _) = second.parse()?;
+    let result = equivalence::equivalent_with(&a, &b, &options)
+        .map_err(|e| Failure(format!("openqasm: {}, {}: {}", first.name, second.name, e)))?;
+    let method = match result.method {
+        Method::Unitary => "the unitaries".to_owned(),
+        Method::RandomStates(n) => format!("{} random state{}", n, if n == 1 { "" } else { "s" }),
+    };
+    let difference = match result.difference {
+        None => {
+            output(&format!("Equivalent (compared {}).\n", method));
+            return Ok(0);
+        }
+        Some(difference) => difference,
+    };
+    let input = match difference.input {
+        equivalence::Input::Basis(col) => ket(col, result.num_qubits),
+        equivalence::Input::Random(k, _) => format!("random state {} (seed {})", k + 1, options.seed),
+    };
+    output(&format!("Not equivalent (compared {}).\nOn input {}, the amplitude of {} is {} in {} and {} in {}.\n",
+                    method, input, ket(difference.index, result.num_qubits), difference.first, first.name,
+                    difference.second, second.name));
+    Ok(1)
+}
+
+fn simulate(args: env::Args) -> CommandResult {
+    let mut shots = 1024;
+    let mut seed = None;
+    let mut method = "statevector".to_owned();
+    let mut state = false;
+    let file = command_args(args, |opt, args| {
+        match opt {
+            "--shots" => shots = number(args.next(), opt)?,
+            "--seed" => seed = Some(number(args.next(), opt)?),
+            "--method" => method = args.next().unwrap_or_default(),
+            "--state" => state = true,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    let input = Input::read(file)?;
+    let (program, _) = input.parse()?;
+    if state {
+        if method != "statevector" {
+            return usage_error("--state needs the statevector method.");
+        }
+        let sim = input.check(simulator::simulate(&program, seed))?;
+        let n = sim.state.num_qubits();
+        let mut out = String::new();
+        for (i, amp) in sim.state.amplitudes().iter().enumerate() {
+            if amp.norm_sqr() > 1e-12 {
+                let bits: String = (0..n).rev().map(|k| if i >> k & 1 == 1 { '1' } else { '0' }).collect();
+                out.push_str(&format!("|{}>\t{:+.6}{:+.6}i\t{:.6}\n", bits, amp.re, amp.im, amp.norm_sqr()));
+            }
+        }
+        output(&out);
+        return Ok(0);
+    }
+    let counts = match method.as_str() {
+        "statevector" => shots::sample(&program, shots, seed),
+        "density" => density::sample(&program, &NoiseModel::new(), shots, seed),
+        "stabilizer" => stabilizer::sample(&program, shots, seed),
+        _ => return usage_error("--method needs one of statevector, density or stabilizer."),
+    };
+    let out: String = input.check(counts)?.iter().map(|(bits, count)| format!("{}\t{}\n", bits, count)).collect();
+    output(&out);
+    Ok(0)
+}
+
+fn draw(args: env::Args) -> CommandResult {
+    let mut options = DrawOptions::default();
+    if let Some(width) = env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        options.width = width;
+    }
+    let file = command_args(args, |opt, args| {
+        match opt {
+            "--ascii" => options.unicode = false,
+            "--width" => options.width = number(args.next(), opt)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    let input = Input::read(file)?;
+    let (program, _) = input.parse()?;
+    output(&input.check(draw::draw(&program, &options))?);
+    Ok(0)
+}
+
+/// Quotes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = "\"".to_owned();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_counts(counts: &BTreeMap<String, usize>) -> String {
+    let entries: Vec<String> = counts.iter().map(|(name, n)| format!("{}: {}", json_string(name), n)).collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+fn stats(args: env::Args) -> CommandResult {
+    let mut json = false;
+    let file = command_args(args, |opt, _| {
+        match opt {
+            "--json" => json = true,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    let input = Input::read(file)?;
+    let (program, spans) = input.parse()?;
+    let stats = input.check(stats::stats(&program, &spans))?;
+    // The critical path as line, column and the first line of the statement.
+    let path: Vec<(usize, usize, &str)> = stats.critical_path.iter().map(|span| {
+        let (line, col) = line_col(&input.code, span.pos);
+        let text = input.code[span.pos..span.pos + span.len].lines().next().unwrap_or("");
+        (line, col, text)
+    }).collect();
+
+    let mut out = String::new();
+    if json {
+        let path: Vec<String> = path.iter().map(|&(line, col, text)| {
+            format!("{{\"line\": {}, \"column\": {}, \"text\": {}}}", line, col, json_string(text))
+        }).collect();
+        out.push_str(&format!("{{\n  \"qubits\": {},\n  \"clbits\": {},\n  \"operations\": {},\n  \
+                               \"gate_counts\": {},\n  \"inlined_operations\": {},\n  \"inlined_gate_counts\": {},\n  \
+                               \"depth\": {},\n  \"two_qubit_depth\": {},\n  \"t_count\": {},\n  \
+                               \"critical_path\": [{}]\n}}\n",
+                              stats.num_qubits, stats.num_clbits, stats.num_operations, json_counts(&stats.gate_counts),
+                              stats.num_inlined_operations, json_counts(&stats.inlined_gate_counts), stats.depth,
+                              stats.two_qubit_depth, stats.t_count, path.join(", ")));
+    } else {
+        let rows = [("qubits", stats.num_qubits), ("clbits", stats.num_clbits), ("operations", stats.num_operations),
+                    ("inlined operations", stats.num_inlined_operations), ("depth", stats.depth),
+                    ("two-qubit depth", stats.two_qubit_depth), ("T-count", stats.t_count)];
+        for &(name, value) in &rows {
+            out.push_str(&format!("{:<20}{}\n", name, value));
+        }
+        for &(title, counts) in &[("gates", &stats.gate_counts), ("inlined gates", &stats.inlined_gate_counts)] {
+            out.push_str(&format!("\n{}\n", title));
+            for (name, n) in counts {
+                out.push_str(&format!("  {:<18}{}\n", name, n));
+            }
+        }
+        out.push_str("\ncritical path\n");
+        for &(line, col, text) in &path {
+            out.push_str(&format!("  {}:{}\t{}\n", line, col, text));
+        }
+    }
+    output(&out);
+    Ok(0)
+}
+
+fn convert(args: env::Args) -> CommandResult {
+    let mut to = "qasm".to_owned();
+    let mut options = PrintOptions::default();
+    let file = command_args(args, |opt, args| {
+        match opt {
+            "--to" => to = args.next().unwrap_or_default(),
+            "--no-comments" => options.comments = false,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    if !["qasm", "svg", "quantikz", "latex"].contains(&to.as_str()) {
+        return usage_error("--to needs one of qasm, svg, quantikz or latex.");
+    }
+    let input = Input::read(file)?;
+    let (program, _) = input.parse()?;
+    let converted = match to.as_str() {
+        "svg" => input.check(export::to_svg(&program))?,
+        "quantikz" => input.check(export::to_quantikz(&program))?,
+        "latex" => input.check(export::to_latex(&program))?,
+        _ => printer::print_program(&program, &options),
+    };
+    output(&converted);
+    Ok(0)
+}
+
+fn main() {
+    let mut args = env::args();
+    args.next();
+    let result = match args.next().as_deref() {
+        Some("tokens") => tokens(args),
+        Some("parse") => parse(args),
+        Some("check") => check(args),
+        Some("fmt") => fmt(args),
+        Some("expand") => expand(args),
+        Some("optimize") => optimize(args),
+        Some("route") => route(args),
+        Some("translate") => translate(args),
+        Some("clifford-t") => clifford_t(args),
+        Some("equiv") => equiv(args),
+        Some("simulate") => simulate(args),
+        Some("convert") => convert(args),
+        Some("draw") => draw(args),
+        Some("stats") => stats(args),
+        Some("-h") | Some("--help") => {
+            output(&format!("{}\n", USAGE));
+            Ok(0)
+        }
+        Some(cmd) => usage_error(&format!("Unknown command '{}'.", cmd)),
+        None => usage_error("No command given."),
+    };
+    match result {
+        Ok(0) => {}
+        Ok(code) => process::exit(code),
+        Err(Failure(msg)) => {
+            eprintln!("{}", msg);
+            process::exit(2);
+        }
+    }
+}
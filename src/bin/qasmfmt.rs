@@ -0,0 +1,119 @@
+extern crate openqasm;
+
+use std::{env, io, process};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use openqasm::formatter::{self, FormatOptions, OperatorSpacing};
+
+const USAGE: &str = "Usage: qasmfmt [options] [files...]
+
+Formats OpenQASM files in place, or standard input to standard output if no files are given.
+
+Options:
+    --check                 Do not write anything; exit with 1 if a file is not formatted.
+    --indent <n>            Indent gate bodies with n spaces (default: 2).
+    --operators <style>     Spaces around binary operators: none, additive or all
+                            (default: additive).
+    --brace-on-new-line     Put the '{' of a gate body on a line of its own.
+    --max-blank-lines <n>   Keep at most n consecutive blank lines (default: 1).
+    -h, --help              Print this help.";
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("qasmfmt: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+fn number(arg: Option<String>, name: &str) -> usize {
+    match arg.and_then(|s| s.parse().ok()) {
+        Some(n) => n,
+        None => usage_error(&format!("{} needs a non-negative integer.", name)),
+    }
+}
+
+fn read(path: Option<&str>) -> io::Result<String> {
+    let mut code = String::new();
+    match path {
+        Some(path) => File::open(path)?.read_to_string(&mut code)?,
+        None => io::stdin().read_to_string(&mut code)?,
+    };
+    Ok(code)
+}
+
+fn main() {
+    let mut options = FormatOptions::default();
+    let mut check = false;
+    let mut files = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--indent" => options.indent = number(args.next(), "--indent"),
+            "--max-blank-lines" => options.max_blank_lines = number(args.next(), "--max-blank-lines"),
+            "--brace-on-new-line" => options.brace_on_new_line = true,
+            "--operators" => {
+                options.operator_spacing = match args.next().as_deref() {
+                    Some("none") => OperatorSpacing::None,
+                    Some("additive") => OperatorSpacing::Additive,
+                    Some("all") => OperatorSpacing::All,
+                    _ => usage_error("--operators needs one of none, additive or all."),
+                };
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') && arg != "-" => usage_error(&format!("Unknown option '{}'.", arg)),
+            _ => files.push(arg),
+        }
+    }
+
+    let targets: Vec<Option<&str>> = if files.is_empty() {
+        vec![None]
+    } else {
+        files.iter().map(|f| if f == "-" { None } else { Some(f.as_str()) }).collect()
+    };
+    let mut unformatted = false;
+    let mut failed = false;
+    for target in targets {
+        let name = target.unwrap_or("<stdin>");
+        let code = match read(target) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("qasmfmt: {}: {}", name, e);
+                failed = true;
+                continue;
+            }
+        };
+        let formatted = match formatter::format_str(&code, Path::new(name), &options) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("qasmfmt: {}:{}: {}", name, e.pos, e);
+                failed = true;
+                continue;
+            }
+        };
+        if check {
+            if formatted != code {
+                println!("{}", name);
+                unformatted = true;
+            }
+        } else if let Some(path) = target {
+            if formatted != code {
+                if let Err(e) = File::create(path).and_then(|mut f| f.write_all(formatted.as_bytes())) {
+                    eprintln!("qasmfmt: {}: {}", path, e);
+                    failed = true;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    if failed {
+        process::exit(2);
+    }
+    if unformatted {
+        process::exit(1);
+    }
+}
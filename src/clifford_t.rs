@@ -0,0 +1,459 @@
+//! Approximation of rotations by Clifford+T sequences with the Solovay-Kitaev algorithm.
+//!
+//! The algorithm (Dawson and Nielsen, 2005) starts from the closest of a net of short
+//! sequences of `h`, `s`, `t` and `tdg`, and at each level of recursion corrects the remaining
+//! error with a group commutator `V W V^-1 W^-1` of approximations from the level below. Each
+//! level makes the sequences about five times longer and the error about its power of 3/2.
+//!
+//! Errors are distances up to a global phase: the smallest operator norm of `U - e^(i a) V`
+//! over all phases `a`.
+//!
+//! The sequences are far from the shortest, so their T-counts are only upper bounds for
+//! resource estimates. The length grows like `log(1/eps)^3.97`: a rotation takes several hundred
+//! T gates at a precision of `1e-3` and thousands at `1e-5`, where the optimal number-theoretic
+//! synthesis of Ross and Selinger needs about `3 log2(1/eps)`, i.e. 30 at `1e-3`.
+
+use std::collections::HashSet;
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use complex::Complex;
+use errors::CircuitError;
+use gates::{self, dagger, matmul, Matrix2};
+use inline::GateTable;
+use lower::{self, LowerResult};
+use parser::{Program, QReg, Statement};
+
+/// The number of distinct unitaries in the net of basic approximations.
+const NET_SIZE: usize = 20000;
+/// The deepest level of recursion tried before giving up on a precision.
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Gate {
+    H,
+    S,
+    T,
+    Tdg,
+}
+
+impl Gate {
+    fn matrix(self) -> Matrix2 {
+        match self {
+            Gate::H => {
+                let h = Complex::real(FRAC_1_SQRT_2);
+                [[h, h], [h, -h]]
+            }
+            Gate::S => gates::phase_matrix(PI / 2.0),
+            Gate::T => gates::phase_matrix(PI / 4.0),
+            Gate::Tdg => gates::phase_matrix(-PI / 4.0),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Gate::H => "h",
+            Gate::S => "s",
+            Gate::T => "t",
+            Gate::Tdg => "tdg",
+        }
+    }
+}
+
+/// The distance of two unitaries up to a global phase.
+fn distance(a: &Matrix2, b: &Matrix2) -> f64 {
+    let product = matmul(&dagger(a), b);
+    let trace = (product[0][0] + product[1][1]).abs();
+    (2.0 - trace).max(0.0).sqrt()
+}
+
+/// The matrix of a sequence of gates, applied from first to last.
+fn sequence_matrix(sequence: &[Gate]) -> Matrix2 {
+    sequence.iter().fold(gates::IDENTITY, |acc, g| matmul(&g.matrix(), &acc))
+}
+
+/// The inverse of a sequence. `s` is inverted as three `s`.
+fn invert(sequence: &[Gate]) -> Vec<Gate> {
+    let mut inverse = vec![];
+    for &g in sequence.iter().rev() {
+        match g {
+            Gate::H => inverse.push(Gate::H),
+            Gate::S => inverse.extend(&[Gate::S, Gate::S, Gate::S]),
+            Gate::T => inverse.push(Gate::Tdg),
+            Gate::Tdg => inverse.push(Gate::T),
+        }
+    }
+    inverse
+}
+
+/// Cancels `h h` and writes each run of `s`, `t` and `tdg` with as few T gates as possible.
+fn simplify(sequence: &[Gate]) -> Vec<Gate> {
+    let mut out: Vec<Gate> = vec![];
+    // The phase of the current run of diagonal gates in multiples of pi/4.
+    let mut eighths = 0;
+    let flush = |out: &mut Vec<Gate>, eighths: &mut u8| {
+        let gates: &[Gate] = match *eighths % 8 {
+            0 => &[],
+            1 => &[Gate::T],
+            2 => &[Gate::S],
+            3 => &[Gate::S, Gate::T],
+            4 => &[Gate::S, Gate::S],
+            5 => &[Gate::S, Gate::S, Gate::T],
+            6 => &[Gate::S, Gate::S, Gate::S],
+            _ => &[Gate::Tdg],
+        };
+        out.extend(gates);
+        *eighths = 0;
+    };
+    for &g in sequence {
+        match g {
+            Gate::S => eighths += 2,
+            Gate::T => eighths += 1,
+            Gate::Tdg => eighths += 7,
+            Gate::H => {
+                eighths %= 8;
+                if eighths == 0 && out.last() == Some(&Gate::H) {
+                    out.pop();
+                    // The run before the cancelled pair continues.
+                    while let Some(&d) = out.last() {
+                        eighths += match d {
+                            Gate::S => 2,
+                            Gate::T => 1,
+                            Gate::Tdg => 7,
+                            Gate::H => break,
+                        };
+                        out.pop();
+                    }
+                } else {
+                    flush(&mut out, &mut eighths);
+                    out.push(Gate::H);
+                }
+            }
+        }
+        eighths %= 8;
+    }
+    flush(&mut out, &mut eighths);
+    out
+}
+
+/// The rounded entries of the matrix with the phase of its first nonzero entry removed, so that
+/// unitaries which are equal up to a phase have equal keys.
+fn phase_key(m: &Matrix2) -> [i64; 8] {
+    let pivot = if m[0][0].abs() > 1e-6 { m[0][0] } else { m[0][1] };
+    let phase = Complex::expi(-pivot.arg());
+    let mut key = [0; 8];
+    for (k, entry) in m.iter().flatten().enumerate() {
+        let v = *entry * phase;
+        key[2 * k] = (v.re * 1e6).round() as i64;
+        key[2 * k + 1] = (v.im * 1e6).round() as i64;
+    }
+    key
+}
+
+/// The axis and angle of a unitary as a rotation of the Bloch sphere.
+fn axis_angle(m: &Matrix2) -> (f64, [f64; 3]) {
+    // Remove the phase so that the determinant is 1: U = cos(a/2) I - i sin(a/2) n.sigma.
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    let phase = Complex::expi(-det.arg() / 2.0);
+    let [[a, b], [c, d]] = [[m[0][0] * phase, m[0][1] * phase], [m[1][0] * phase, m[1][1] * phase]];
+    let cos = (a.re + d.re) / 2.0;
+    let sin_n = [-(b.im + c.im) / 2.0, (c.re - b.re) / 2.0, (d.im - a.im) / 2.0];
+    let sin = sin_n.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if sin < 1e-15 {
+        return (0.0, [0.0, 0.0, 1.0]);
+    }
+    (2.0 * sin.atan2(cos), [sin_n[0] / sin, sin_n[1] / sin, sin_n[2] / sin])
+}
+
+/// The rotation by `angle` about the unit vector `n`.
+fn rotation(angle: f64, n: [f64; 3]) -> Matrix2 {
+    let (cos, sin) = ((angle / 2.0).cos(), (angle / 2.0).sin());
+    [[Complex::new(cos, -sin * n[2]), Complex::new(-sin * n[1], -sin * n[0])],
+     [Complex::new(sin * n[1], -sin * n[0]), Complex::new(cos, sin * n[2])]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// A rotation taking the unit vector `from` to `to`.
+fn rotation_between(from: [f64; 3], to: [f64; 3]) -> Matrix2 {
+    let axis = cross(from, to);
+    let norm = dot(axis, axis).sqrt();
+    if norm < 1e-12 {
+        if dot(from, to) > 0.0 {
+            return gates::IDENTITY;
+        }
+        // Opposite vectors: turn by pi about any axis perpendicular to them.
+        let other = if from[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+        let axis = cross(from, other);
+        let norm = dot(axis, axis).sqrt();
+        return rotation(PI, [axis[0] / norm, axis[1] / norm, axis[2] / norm]);
+    }
+    let angle = dot(from, to).clamp(-1.0, 1.0).acos();
+    rotation(angle, [axis[0] / norm, axis[1] / norm, axis[2] / norm])
+}
+
+/// Unitaries `V` and `W` such that `V W V^-1 W^-1` is `delta` up to a phase, each a rotation
+/// by about the square root of the angle of `delta`.
+fn group_commutator(delta: &Matrix2) -> (Matrix2, Matrix2) {
+    let (theta, n) = axis_angle(delta);
+    let s = (theta / 2.0).sin();
+    let phi = 2.0 * ((1.0 - (1.0 - s * s).max(0.0).sqrt()) / 2.0).powf(0.25).asin();
+    let v = rotation(phi, [1.0, 0.0, 0.0]);
+    let w = rotation(phi, [0.0, 1.0, 0.0]);
+    let commutator = matmul(&matmul(&v, &w), &matmul(&dagger(&v), &dagger(&w)));
+    let (_, m) = axis_angle(&commutator);
+    let s = rotation_between(m, n);
+    let conjugate = |x: &Matrix2| matmul(&matmul(&s, x), &dagger(&s));
+    (conjugate(&v), conjugate(&w))
+}
+
+/// Approximates single-qubit unitaries with sequences of `h`, `s`, `t` and `tdg`.
+pub struct SolovayKitaev {
+    net: Vec<(Vec<Gate>, Matrix2)>,
+}
+
+/// A sequence approximating a unitary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Approximation {
+    /// The gate names, in the order they are applied.
+    pub gates: Vec<&'static str>,
+    pub t_count: usize,
+    pub error: f64,
+}
+
+impl Default for SolovayKitaev {
+    fn default() -> SolovayKitaev {
+        SolovayKitaev::new()
+    }
+}
+
+impl SolovayKitaev {
+    /// Builds the net of basic approximations: the shortest sequences of the first `NET_SIZE`
+    /// distinct unitaries.
+    pub fn new() -> SolovayKitaev {
+        let generators = [Gate::H, Gate::S, Gate::T, Gate::Tdg];
+        let mut seen = HashSet::new();
+        seen.insert(phase_key(&gates::IDENTITY));
+        let mut net = vec![(vec![], gates::IDENTITY)];
+        let mut i = 0;
+        while i < net.len() && net.len() < NET_SIZE {
+            for &g in &generators {
+                let matrix = matmul(&g.matrix(), &net[i].1);
+                if seen.insert(phase_key(&matrix)) {
+                    let mut sequence = net[i].0.clone();
+                    sequence.push(g);
+                    net.push((sequence, matrix));
+                }
+            }
+            i += 1;
+        }
+        SolovayKitaev { net }
+    }
+
+    fn closest(&self, target: &Matrix2) -> (Vec<Gate>, Matrix2) {
+        let mut best = (f64::INFINITY, 0);
+        for (i, (_, m)) in self.net.iter().enumerate() {
+            let d = distance(target, m);
+            if d < best.0 {
+                best = (d, i);
+            }
+        }
+        self.net[best.1].clone()
+    }
+
+    fn approximate_at(&self, target: &Matrix2, depth: usize) -> (Vec<Gate>, Matrix2) {
+        if depth == 0 {
+            return self.closest(target);
+        }
+        let (u_seq, u) = self.approximate_at(target, depth - 1);
+        let (v, w) = group_commutator(&matmul(target, &dagger(&u)));
+        let (v_seq, _) = self.approximate_at(&v, depth - 1);
+        let (w_seq, _) = self.approximate_at(&w, depth - 1);
+        // Applied first to last: U, then W^-1, V^-1, W and V.
+        let mut sequence = u_seq;
+        sequence.extend(invert(&w_seq));
+        sequence.extend(invert(&v_seq));
+        sequence.extend(&w_seq);
+        sequence.extend(&v_seq);
+        let sequence = simplify(&sequence);
+        let matrix = sequence_matrix(&sequence);
+        (sequence, matrix)
+    }
+
+    /// Approximates a unitary within `precision`, deepening the recursion until the error is
+    /// small enough. Returns `None` if even the deepest level does not reach it.
+    pub fn approximate(&self, target: &Matrix2, precision: f64) -> Option<Approximation> {
+        for depth in 0..MAX_DEPTH + 1 {
+            let (sequence, matrix) = self.approximate_at(target, depth);
+            let error = distance(target, &matrix);
+            if error <= precision {
+                return Some(Approximation {
+                    gates: sequence.iter().map(|g| g.name()).collect(),
+                    t_count: sequence.iter().filter(|&&g| g == Gate::T || g == Gate::Tdg).count(),
+                    error,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// A rotation replaced by `approximate_rotations`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplacedRotation {
+    pub qubit: QReg,
+    /// The angle of the rotation as `rz(angle)`.
+    pub angle: f64,
+    /// The T gates of the sequence; an upper bound far above the optimum (see the module doc).
+    pub t_count: usize,
+    /// The distance of the sequence from the rotation, up to a global phase.
+    pub error: f64,
+}
+
+/// The result of `approximate_rotations`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliffordT {
+    pub program: Program,
+    /// The replaced rotations in program order.
+    pub rotations: Vec<ReplacedRotation>,
+}
+
+impl CliffordT {
+    /// The T gates added for the replaced rotations.
+    pub fn t_count(&self) -> usize {
+        self.rotations.iter().map(|r| r.t_count).sum()
+    }
+}
+
+fn approximate_statement(stmt: &Statement, gates: &GateTable, sk: &SolovayKitaev, precision: f64, out: &mut Program,
+                         rotations: &mut Vec<ReplacedRotation>) -> LowerResult<()> {
+    match *stmt {
+        Statement::Apply(ref name, ref params, ref qargs)
+            if matches!(name.as_str(), "rz" | "u1" | "p") && gates.is_standard(name) => {
+            gates.check_apply(name, params, qargs)?;
+            let angle = params[0].snapped_angle()
+                .ok_or_else(|| CircuitError::new(format!("Cannot approximate '{}' with a free parameter.", name)))?;
+            if angle.quarter_turns().is_some() {
+                out.push(stmt.clone());
+                return Ok(());
+            }
+            let approximation = sk.approximate(&gates::phase_matrix(angle.value()), precision).ok_or_else(|| {
+                CircuitError::new(format!("Cannot approximate {}({}) within {}.", name, angle.value(), precision))
+            })?;
+            out.extend(approximation.gates.iter().map(|g| Statement::Apply(g.to_string(), vec![], qargs.clone())));
+            rotations.push(ReplacedRotation {
+                qubit: qargs[0].clone(),
+                angle: angle.value(),
+                t_count: approximation.t_count,
+                error: approximation.error,
+            });
+        }
+        Statement::Apply(ref name, ref params, ref qargs) => {
+            gates.check_apply(name, params, qargs)?;
+            out.push(stmt.clone());
+        }
+        Statement::If(ref c, val, ref body) => {
+            let mut approximated = vec![];
+            for stmt in body {
+                approximate_statement(stmt, gates, sk, precision, &mut approximated, rotations)?;
+            }
+            out.extend(approximated.into_iter().map(|s| Statement::If(c.clone(), val, vec![s])));
+        }
+        _ => out.push(stmt.clone()),
+    }
+    Ok(())
+}
+
+/// Lowers a program and replaces each `rz`, `u1` and `p` rotation whose angle is not a multiple
+/// of `pi/4` by a sequence of `h`, `s`, `t` and `tdg` within `precision` of it, up to a global
+/// phase. Rotations by multiples of `pi/4` are kept, and so are gates which the program declares
+/// itself under these names. Other gates are not changed, so rotations
+/// about other axes should be translated into `rz` and Clifford gates first (see
+/// `translate::translate`).
+pub fn approximate_rotations(program: &Program, precision: f64) -> LowerResult<CliffordT> {
+    let gates = GateTable::from_program(program)?;
+    let sk = SolovayKitaev::new();
+    let mut out = vec![];
+    let mut rotations = vec![];
+    for stmt in &lower::lower(program)? {
+        approximate_statement(stmt, &gates, &sk, precision, &mut out, &mut rotations)?;
+    }
+    Ok(CliffordT { program: out, rotations })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+    use unitary::unitary;
+
+    #[test]
+    fn simplify_sequences() {
+        use self::Gate::*;
+        assert_eq!(simplify(&[T, T, H, H, T, S, Tdg]), [S, S]);
+        assert_eq!(simplify(&[H, T, Tdg, H, S]), [S]);
+        assert_eq!(simplify(&[T, H, S, S, S, S, H, T, T]), [S, T]);
+        assert_eq!(simplify(&invert(&[H, S, T])), [S, S, T, H]);
+        let seq = [H, T, S, H, Tdg, H];
+        assert!(distance(&matmul(&sequence_matrix(&seq), &sequence_matrix(&invert(&seq))), &gates::IDENTITY) < 1e-12);
+    }
+
+    #[test]
+    fn approximate_within_precision() {
+        let sk = SolovayKitaev::new();
+        for &(angle, precision) in &[(0.3, 1e-2), (1.0, 1e-3), (-2.5, 1e-4)] {
+            let target = gates::phase_matrix(angle);
+            let approximation = sk.approximate(&target, precision).unwrap();
+            assert!(approximation.error <= precision);
+            let sequence: Vec<Gate> = approximation.gates.iter().map(|&g| match g {
+                "h" => Gate::H,
+                "s" => Gate::S,
+                "t" => Gate::T,
+                _ => Gate::Tdg,
+            }).collect();
+            assert!((distance(&target, &sequence_matrix(&sequence)) - approximation.error).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn approximate_program() {
+        let code = "include \"qelib1.inc\";\nqreg q[2];\nh q;\nrz(pi/4) q[0];\ncx q[0], q[1];\nrz(0.7) q[1];\nu1(3*pi/2) q[1];\n\
+                    p(-1.2) q[0];";
+        let program = parse(code);
+        let result = approximate_rotations(&program, 1e-3).unwrap();
+        assert_eq!(result.rotations.len(), 2);
+        assert_eq!(result.rotations[0].qubit, QReg::Bit("q".to_owned(), 1));
+        assert!(result.rotations.iter().all(|r| r.error <= 1e-3 && r.t_count > 0));
+        let names: HashSet<&str> = result.program.iter().filter_map(|s| match *s {
+            Statement::Apply(ref name, ..) => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert!(names.is_subset(&["h", "s", "t", "tdg", "cx", "rz", "u1"].iter().cloned().collect()));
+        assert!(names.contains("rz") && names.contains("u1") && !names.contains("p"));
+        let (a, b) = (unitary(&program).unwrap(), unitary(&result.program).unwrap());
+        assert!(a.equiv_up_to_global_phase(&b, 1e-2));
+        assert!(!a.equiv_up_to_global_phase(&b, 1e-8));
+    }
+
+    #[test]
+    fn keep_declared_rotations() {
+        let code = "gate rz(theta) a {\n  U(theta, 0, 0) a;\n}\nqreg q[1];\nrz(0.7) q[0];";
+        let program = parse(code);
+        let result = approximate_rotations(&program, 1e-3).unwrap();
+        assert!(result.rotations.is_empty());
+        assert_eq!(result.program, program);
+    }
+
+    #[test]
+    fn malformed_applications() {
+        for body in &["rz q[0];", "cx q[0];", "creg c[1];\nif (c == 0) p(1, 2) q[0];"] {
+            let program = parse(&format!("include \"qelib1.inc\";\nqreg q[2];\n{}", body));
+            assert!(approximate_rotations(&program, 1e-3).is_err(), "{}", body);
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Directed acyclic graph representation of a circuit.
+//!
+//! Nodes are the operations of a lowered program (`Apply`, `Measure`, `Reset`, `Barrier` and
+//! `If`), and edges follow the qubit and classical bit wires. Each node keeps, for every wire it
+//! touches, the previous and the next node on that wire.
+
+use std::collections::BTreeSet;
+
+use errors::CircuitError;
+use lower::{self, LowerResult, Registers};
+use parser::{CReg, Program, QReg, Statement};
+
+pub type NodeId = usize;
+
+/// A wire of the circuit, identified by the global bit index (see `lower::Registers`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Wire {
+    Qubit(usize),
+    Clbit(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    stmt: Statement,
+    wires: Vec<Wire>,
+    preds: Vec<Option<NodeId>>,
+    succs: Vec<Option<NodeId>>,
+}
+
+impl Node {
+    /// The lowered statement of this node.
+    pub fn statement(&self) -> &Statement {
+        &self.stmt
+    }
+
+    /// The wires this node acts on. For `If`, these are the wires of the body followed by all
+    /// bits of the condition register.
+    pub fn wires(&self) -> &[Wire] {
+        &self.wires
+    }
+
+    fn wire_index(&self, wire: Wire) -> Option<usize> {
+        self.wires.iter().position(|&w| w == wire)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DagCircuit {
+    header: Program,
+    regs: Registers,
+    nodes: Vec<Option<Node>>,
+    qubit_ends: Vec<(Option<NodeId>, Option<NodeId>)>,
+    clbit_ends: Vec<(Option<NodeId>, Option<NodeId>)>,
+}
+
+/// The wires of a lowered operation. Bits which are not declared in `regs` are an error.
+fn statement_wires(stmt: &Statement, regs: &Registers) -> LowerResult<Vec<Wire>> {
+    let mut wires = vec![];
+    match *stmt {
+        Statement::Apply(_, _, ref qargs) | Statement::Barrier(ref qargs) => {
+            for q in qargs {
+                wires.push(Wire::Qubit(regs.qubits(q)?.start));
+            }
+        }
+        Statement::Reset(ref q) => wires.push(Wire::Qubit(regs.qubits(q)?.start)),
+        Statement::Measure(ref q, ref c) => {
+            wires.push(Wire::Qubit(regs.qubits(q)?.start));
+            wires.push(Wire::Clbit(regs.clbits(c)?.start));
+        }
+        Statement::If(ref c, _, ref body) => {
+            for stmt in body {
+                for w in statement_wires(stmt, regs)? {
+                    if !wires.contains(&w) {
+                        wires.push(w);
+                    }
+                }
+            }
+            for i in regs.clbits(c)? {
+                if !wires.contains(&Wire::Clbit(i)) {
+                    wires.push(Wire::Clbit(i));
+                }
+            }
+        }
+        _ => return Err(CircuitError::new("Not an operation.".to_owned())),
+    }
+    Ok(wires)
+}
+
+fn is_operation(stmt: &Statement) -> bool {
+    matches!(*stmt,
+             Statement::Apply(..) | Statement::Barrier(..) | Statement::Reset(..) |
+             Statement::Measure(..) | Statement::If(..))
+}
+
+/// Checks that all arguments of a lowered operation are single bits.
+fn check_lowered(stmt: &Statement) -> LowerResult<()> {
+    let ok = match *stmt {
+        Statement::Apply(_, _, ref qargs) | Statement::Barrier(ref qargs) => {
+            qargs.iter().all(|q| matches!(*q, QReg::Bit(..)))
+        }
+        Statement::Reset(ref q) => matches!(*q, QReg::Bit(..)),
+        Statement::Measure(ref q, ref c) => matches!((q, c), (&QReg::Bit(..), &CReg::Bit(..))),
+        Statement::If(_, _, ref body) => return body.iter().try_for_each(check_lowered),
+        _ => true,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(CircuitError::new("The operation is not lowered.".to_owned()))
+    }
+}
+
+impl DagCircuit {
+    /// Lowers a program and builds its DAG. Comments in the body of the program are dropped.
+    pub fn from_program(program: &Program) -> LowerResult<DagCircuit> {
+        let lowered = lower::lower(program)?;
+        let regs = Registers::from_program(&lowered)?;
+        let mut dag = DagCircuit {
+            header: vec![],
+            qubit_ends: vec![(None, None); regs.num_qubits()],
+            clbit_ends: vec![(None, None); regs.num_clbits()],
+            regs,
+            nodes: vec![],
+        };
+        for stmt in lowered {
+            match stmt {
+                Statement::Comment(_) => {}
+                _ if is_operation(&stmt) => {
+                    dag.push(stmt)?;
+                }
+                _ => dag.header.push(stmt),
+            }
+        }
+        Ok(dag)
+    }
+
+    /// The non-operation statements: version, includes, declarations.
+    pub fn header(&self) -> &Program {
+        &self.header
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.regs
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        self.nodes[id].as_ref().expect("The node is removed.")
+    }
+
+    /// Whether the node is in the graph, i.e. it has not been removed or substituted.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.nodes.get(id).map(|n| n.is_some()).unwrap_or(false)
+    }
+
+    /// Ids of all nodes in the graph, in insertion order.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        (0..self.nodes.len()).filter(|&i| self.nodes[i].is_some()).collect()
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.iter().filter(|n| n.is_some()).count()
+    }
+
+    fn ends(&mut self, wire: Wire) -> &mut (Option<NodeId>, Option<NodeId>) {
+        match wire {
+            Wire::Qubit(i) => &mut self.qubit_ends[i],
+            Wire::Clbit(i) => &mut self.clbit_ends[i],
+        }
+    }
+
+    fn wire_ends(&self, wire: Wire) -> (Option<NodeId>, Option<NodeId>) {
+        match wire {
+            Wire::Qubit(i) => self.qubit_ends[i],
+            Wire::Clbit(i) => self.clbit_ends[i],
+        }
+    }
+
+    /// The first node on a wire.
+    pub fn first(&self, wire: Wire) -> Option<NodeId> {
+        self.wire_ends(wire).0
+    }
+
+    /// The last node on a wire.
+    pub fn last(&self, wire: Wire) -> Option<NodeId> {
+        self.wire_ends(wire).1
+    }
+
+    /// Appends a lowered operation at the end of the circuit.
+    pub fn push(&mut self, stmt: Statement) -> LowerResult<NodeId> {
+        check_lowered(&stmt)?;
+        let wires = statement_wires(&stmt, &self.regs)?;
+        let id = self.nodes.len();
+        let mut preds = Vec::with_capacity(wires.len());
+        for &w in &wires {
+            let last = self.wire_ends(w).1;
+            if let Some(p) = last {
+                self.set_succ(p, w, Some(id));
+            } else {
+                self.ends(w).0 = Some(id);
+            }
+            self.ends(w).1 = Some(id);
+            preds.push(last);
+        }
+        let succs = vec![None; wires.len()];
+        self.nodes.push(Some(Node { stmt, wires, preds, succs }));
+        Ok(id)
+    }
+
+    fn set_succ(&mut self, id: NodeId, wire: Wire, succ: Option<NodeId>) {
+        let node = self.nodes[id].as_mut().unwrap();
+        let k = node.wire_index(wire).unwrap();
+        node.succs[k] = succ;
+    }
+
+    fn set_pred(&mut self, id: NodeId, wire: Wire, pred: Option<NodeId>) {
+        let node = self.nodes[id].as_mut().unwrap();
+        let k = node.wire_index(wire).unwrap();
+        node.preds[k] = pred;
+    }
+
+    /// Connects `pred` and `succ` on `wire`. `None` means the start or the end of the wire.
+    fn link(&mut self, wire: Wire, pred: Option<NodeId>, succ: Option<NodeId>) {
+        match pred {
+            Some(p) => self.set_succ(p, wire, succ),
+            None => self.ends(wire).0 = succ,
+        }
+        match succ {
+            Some(s) => self.set_pred(s, wire, pred),
+            None => self.ends(wire).1 = pred,
+        }
+    }
+
+    /// The previous node on `wire`, or `None` if `id` is the first node on it.
+    pub fn predecessor(&self, id: NodeId, wire: Wire) -> Option<NodeId> {
+        let node = self.node(id);
+        node.wire_index(wire).and_then(|k| node.preds[k])
+    }
+
+    /// The next node on `wire`, or `None` if `id` is the last node on it.
+    pub fn successor(&self, id: NodeId, wire: Wire) -> Option<NodeId> {
+        let node = self.node(id);
+        node.wire_index(wire).and_then(|k| node.succs[k])
+    }
+
+    /// Distinct predecessors over all wires of the node.
+    pub fn predecessors(&self, id: NodeId) -> Vec<NodeId> {
+        dedup(self.node(id).preds.iter().filter_map(|&p| p))
+    }
+
+    /// Distinct successors over all wires of the node.
+    pub fn successors(&self, id: NodeId) -> Vec<NodeId> {
+        dedup(self.node(id).succs.iter().filter_map(|&s| s))
+    }
+
+    /// Removes a node and reconnects its wires.
+    pub fn remove_node(&mut self, id: NodeId) -> Statement {
+        let node = self.nodes[id].take().expect("The node is removed.");
+        for (k, &w) in node.wires.iter().enumerate() {
+            self.link(w, node.preds[k], node.succs[k]);
+        }
+        node.stmt
+    }
+
+    /// Replaces a node with a subcircuit of lowered operations, which may only act on the wires
+    /// of the node. Returns the ids of the inserted nodes.
+    pub fn substitute_node(&mut self, id: NodeId, subcircuit: Program) -> LowerResult<Vec<NodeId>> {
+        let (wires, preds, succs) = {
+            let node = self.node(id);
+            (node.wires.clone(), node.preds.clone(), node.succs.clone())
+        };
+        let mut new_nodes = vec![];
+        for stmt in subcircuit {
+            if let Statement::Comment(_) = stmt {
+                continue;
+            }
+            if !is_operation(&stmt) {
+                return Err(CircuitError::new("Only operations can be substituted.".to_owned()));
+            }
+            check_lowered(&stmt)?;
+            let sub_wires = statement_wires(&stmt, &self.regs)?;
+            if let Some(w) = sub_wires.iter().find(|w| !wires.contains(w)) {
+                return Err(CircuitError::new(format!("The subcircuit acts on {:?}, which the node does not.", w)));
+            }
+            new_nodes.push((stmt, sub_wires));
+        }
+
+        self.nodes[id] = None;
+        let mut tails = preds;
+        let mut ids = vec![];
+        for (stmt, sub_wires) in new_nodes {
+            let new_id = self.nodes.len();
+            let mut node_preds = vec![];
+            for &w in &sub_wires {
+                let k = wires.iter().position(|&x| x == w).unwrap();
+                node_preds.push(tails[k]);
+            }
+            let n = sub_wires.len();
+            self.nodes.push(Some(Node { stmt, wires: sub_wires.clone(), preds: node_preds.clone(), succs: vec![None; n] }));
+            for (&w, &p) in sub_wires.iter().zip(node_preds.iter()) {
+                match p {
+                    Some(p) => self.set_succ(p, w, Some(new_id)),
+                    None => self.ends(w).0 = Some(new_id),
+                }
+                let k = wires.iter().position(|&x| x == w).unwrap();
+                tails[k] = Some(new_id);
+            }
+            ids.push(new_id);
+        }
+        for (k, &w) in wires.iter().enumerate() {
+            self.link(w, tails[k], succs[k]);
+        }
+        Ok(ids)
+    }
+
+    /// Node ids in a topological order. Among nodes whose predecessors are all visited, the one
+    /// with the smallest id comes first, so an unmodified DAG keeps the program order.
+    pub fn topological(&self) -> Vec<NodeId> {
+        let mut indegree = vec![0; self.nodes.len()];
+        let mut ready = BTreeSet::new();
+        for id in self.node_ids() {
+            indegree[id] = self.predecessors(id).len();
+            if indegree[id] == 0 {
+                ready.insert(id);
+            }
+        }
+        let mut order = Vec::with_capacity(self.num_nodes());
+        while let Some(&id) = ready.iter().next() {
+            ready.remove(&id);
+            order.push(id);
+            for s in self.successors(id) {
+                indegree[s] -= 1;
+                if indegree[s] == 0 {
+                    ready.insert(s);
+                }
+            }
+        }
+        order
+    }
+
+    /// Nodes grouped into layers (moments). Each node is placed in the earliest layer after all
+    /// of its predecessors, so nodes in one layer act on disjoint wires.
+    pub fn layers(&self) -> Vec<Vec<NodeId>> {
+        let mut depth = vec![0; self.nodes.len()];
+        let mut layers: Vec<Vec<NodeId>> = vec![];
+        for id in self.topological() {
+            let d = self.predecessors(id).iter().map(|&p| depth[p] + 1).max().unwrap_or(0);
+            depth[id] = d;
+            if layers.len() <= d {
+                layers.push(vec![]);
+            }
+            layers[d].push(id);
+        }
+        layers
+    }
+
+    /// Converts back into a program: the header followed by the operations in topological
+    /// order.
+    pub fn to_program(&self) -> Program {
+        let mut program = self.header.clone();
+        program.extend(self.topological().into_iter().map(|id| self.node(id).stmt.clone()));
+        program
+    }
+}
+
+fn dedup<I: Iterator<Item=NodeId>>(ids: I) -> Vec<NodeId> {
+    let mut v = vec![];
+    for id in ids {
+        if !v.contains(&id) {
+            v.push(id);
+        }
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    fn dag(code: &str) -> DagCircuit {
+        DagCircuit::from_program(&parse(code)).unwrap()
+    }
+
+    #[test]
+    fn wires_and_layers() {
+        let dag = dag("qreg q[3]; creg c[1];\n\
+                       h q; cx q[0], q[1]; x q[2]; measure q[1] -> c[0]; if (c == 1) z q[2];");
+        // h q[0], h q[1], h q[2], cx, x, measure, if
+        assert_eq!(dag.num_nodes(), 7);
+        assert_eq!(dag.predecessor(3, Wire::Qubit(1)), Some(1));
+        assert_eq!(dag.predecessors(3), vec![0, 1]);
+        assert_eq!(dag.successor(5, Wire::Clbit(0)), Some(6));
+        assert_eq!(dag.predecessors(6), vec![4, 5]);
+        assert_eq!(dag.layers(), vec![vec![0, 1, 2], vec![3, 4], vec![5], vec![6]]);
+        assert_eq!(dag.first(Wire::Qubit(2)), Some(2));
+        assert_eq!(dag.last(Wire::Qubit(2)), Some(6));
+    }
+
+    #[test]
+    fn roundtrip_program() {
+        let code = "OPENQASM 2.0; qreg q[2]; creg c[2]; h q[0]; cx q[0], q[1]; measure q[0] -> c[0];";
+        let prog = parse(code);
+        assert_eq!(dag(code).to_program(), prog);
+    }
+
+    #[test]
+    fn substitute_and_remove() {
+        let mut dag = dag("qreg q[2]; h q[0]; cx q[0], q[1]; h q[1];");
+        let sub = parse("h q[1]; cz q[0], q[1]; h q[1];");
+        let ids = dag.substitute_node(1, sub).unwrap();
+        assert_eq!(ids, vec![3, 4, 5]);
+        assert_eq!(dag.predecessors(4), vec![0, 3]);
+        assert_eq!(dag.successor(5, Wire::Qubit(1)), Some(2));
+        dag.remove_node(5);
+        dag.remove_node(2);
+        assert_eq!(dag.last(Wire::Qubit(1)), Some(4));
+        assert_eq!(dag.topological(), vec![0, 3, 4]);
+
+        let bad = parse("h q[1];");
+        assert!(dag.substitute_node(0, bad).is_err());
+    }
+
+    #[test]
+    fn undeclared_bits() {
+        let mut dag = dag("qreg q[1]; creg c[1];");
+        let stmt = |code: &str| parse(code).remove(0);
+        assert!(dag.push(stmt("h r[0];")).is_err());
+        assert!(dag.push(stmt("h q[1];")).is_err());
+        assert!(dag.push(stmt("measure q[0] -> d[0];")).is_err());
+        let id = dag.push(stmt("h q[0];")).unwrap();
+        assert!(dag.substitute_node(id, vec![stmt("x r[0];")]).is_err());
+        assert_eq!(dag.num_nodes(), 1);
+    }
+}
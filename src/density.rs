@@ -0,0 +1,238 @@
+//! Density-matrix simulator with noise.
+//!
+//! Runs the same lowered circuits as the state-vector simulator and uses the same qubit
+//! ordering, with gate and readout errors from a `NoiseModel`.
+
+use complex::{Complex, ZERO};
+use errors::CircuitError;
+use gates::{GateMatrix, Matrix2};
+use noise::{Channel, NoiseModel};
+use parser::Program;
+use rng::Rng;
+use shots::{self, Counts};
+use simulator::{self, Executor, QuantumState, SimulationResult};
+
+/// The largest number of qubits the density-matrix simulator accepts.
+pub const MAX_DENSITY_QUBITS: usize = 12;
+
+/// A density matrix on `n` qubits with a noise model applied while running circuits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityMatrix {
+    num_qubits: usize,
+    /// Row-major entries.
+    rho: Vec<Complex>,
+    noise: NoiseModel,
+}
+
+impl DensityMatrix {
+    /// `|0...0><0...0|` without noise.
+    pub fn new(num_qubits: usize) -> DensityMatrix {
+        DensityMatrix::with_noise(num_qubits, NoiseModel::new())
+    }
+
+    /// `|0...0><0...0|` with a noise model.
+    pub fn with_noise(num_qubits: usize, noise: NoiseModel) -> DensityMatrix {
+        let mut rho = vec![ZERO; 1 << (2 * num_qubits)];
+        rho[0] = Complex::real(1.0);
+        DensityMatrix { num_qubits, rho, noise }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    fn dim(&self) -> usize {
+        1 << self.num_qubits
+    }
+
+    /// The entry at `row` and `col`.
+    pub fn get(&self, row: usize, col: usize) -> Complex {
+        self.rho[row * self.dim() + col]
+    }
+
+    /// Probabilities of the basis states, i.e. the diagonal.
+    pub fn probabilities(&self) -> Vec<f64> {
+        (0..self.dim()).map(|i| self.get(i, i).re).collect()
+    }
+
+    /// The purity `tr(rho^2)`, which is 1 for pure states.
+    pub fn purity(&self) -> f64 {
+        self.rho.iter().map(|a| a.norm_sqr()).sum()
+    }
+
+    /// `gate rho gate^dagger`.
+    fn conjugated(&self, gate: &GateMatrix, qubits: &[usize]) -> Vec<Complex> {
+        let dim = self.dim();
+        let mut rho = self.rho.clone();
+        let mut col = vec![ZERO; dim];
+        for j in 0..dim {
+            for i in 0..dim {
+                col[i] = rho[i * dim + j];
+            }
+            simulator::apply_gate(&mut col, gate, qubits);
+            for i in 0..dim {
+                rho[i * dim + j] = col[i];
+            }
+        }
+        // Multiplying a row by gate^dagger from the right is conjugating, applying and
+        // conjugating again.
+        for row in rho.chunks_mut(dim) {
+            for a in row.iter_mut() {
+                *a = a.conj();
+            }
+            simulator::apply_gate(row, gate, qubits);
+            for a in row.iter_mut() {
+                *a = a.conj();
+            }
+        }
+        rho
+    }
+
+    /// Applies a channel given by Kraus operators to one qubit.
+    fn apply_kraus(&mut self, kraus: &[Matrix2], qubit: usize) {
+        let mut sum = vec![ZERO; self.rho.len()];
+        for k in kraus {
+            let term = self.conjugated(&GateMatrix::Unitary { controls: 0, matrix: *k }, &[qubit]);
+            for (s, t) in sum.iter_mut().zip(term) {
+                *s += t;
+            }
+        }
+        self.rho = sum;
+    }
+
+    /// Applies a noise channel to one qubit.
+    pub fn apply_channel(&mut self, channel: &Channel, qubit: usize) {
+        self.apply_kraus(&channel.kraus(), qubit);
+    }
+}
+
+impl QuantumState for DensityMatrix {
+    fn apply(&mut self, gate: &GateMatrix, qubits: &[usize]) {
+        self.rho = self.conjugated(gate, qubits);
+    }
+
+    fn measure(&mut self, qubit: usize, rng: &mut Rng) -> bool {
+        let dim = self.dim();
+        let mask = 1 << qubit;
+        let p1: f64 = (0..dim).filter(|i| i & mask != 0).map(|i| self.get(i, i).re).sum();
+        let outcome = rng.next_f64() < p1;
+        let norm = 1.0 / if outcome { p1 } else { 1.0 - p1 };
+        for i in 0..dim {
+            for j in 0..dim {
+                let a = &mut self.rho[i * dim + j];
+                if (i & mask != 0) == outcome && (j & mask != 0) == outcome {
+                    *a = a.scale(norm);
+                } else {
+                    *a = ZERO;
+                }
+            }
+        }
+        match self.noise.readout_error(qubit) {
+            Some(error) if rng.next_f64() < error.flip_probability(outcome) => !outcome,
+            _ => outcome,
+        }
+    }
+
+    fn reset(&mut self, qubit: usize, _rng: &mut Rng) {
+        let (zero, one) = (ZERO, Complex::real(1.0));
+        self.apply_kraus(&[[[one, zero], [zero, zero]], [[zero, one], [zero, zero]]], qubit);
+    }
+
+    fn gate_applied(&mut self, name: &str, qubits: &[usize]) {
+        for &q in qubits {
+            let channels = self.noise.gate_errors(name, q).to_vec();
+            for channel in &channels {
+                self.apply_channel(channel, q);
+            }
+        }
+    }
+}
+
+fn check_size(executor: &Executor) -> SimulationResult<usize> {
+    let n = executor.registers().num_qubits();
+    if n > MAX_DENSITY_QUBITS {
+        return Err(CircuitError::new(format!("{} qubits are too many to simulate a density matrix.", n)));
+    }
+    Ok(n)
+}
+
+/// Runs a program once on a density matrix with noise and returns the final state and the
+/// classical bits. Measurements collapse the state as in the state-vector simulator.
+pub fn simulate(program: &Program, noise: &NoiseModel, seed: Option<u64>)
+                -> SimulationResult<(DensityMatrix, Vec<bool>)> {
+    let executor = Executor::new(program)?;
+    let n = check_size(&executor)?;
+    let mut rng = seed.map(Rng::from_seed).unwrap_or_else(Rng::from_time);
+    let mut state = DensityMatrix::with_noise(n, noise.clone());
+    let clbits = executor.run(&mut state, &mut rng)?;
+    Ok((state, clbits))
+}
+
+/// Runs a program `shots` times with noise and counts the measured bitstrings, keyed as in
+/// `shots::sample`.
+pub fn sample(program: &Program, noise: &NoiseModel, shots: usize, seed: Option<u64>) -> SimulationResult<Counts> {
+    let executor = Executor::new(program)?;
+    check_size(&executor)?;
+    let mut rng = seed.map(Rng::from_seed).unwrap_or_else(Rng::from_time);
+    shots::sample_executor(&executor, shots, &mut rng, |n| DensityMatrix::with_noise(n, noise.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use noise::ReadoutError;
+    use test_util::parse;
+
+    #[test]
+    fn noiseless_matches_state_vector() {
+        let prog = parse("include \"qelib1.inc\"; qreg q[3]; h q[0]; cx q[0], q[1]; rx(0.4) q[2];\n\
+                          ccx q[2], q[1], q[0]; cu3(1, 2, 3) q[0], q[2];");
+        let (rho, _) = simulate(&prog, &NoiseModel::new(), Some(0)).unwrap();
+        let psi = simulator::simulate(&prog, Some(0)).unwrap().state;
+        let amps = psi.amplitudes();
+        for i in 0..8 {
+            for j in 0..8 {
+                assert!(rho.get(i, j).approx_eq(amps[i] * amps[j].conj(), 1e-12));
+            }
+        }
+        assert!((rho.purity() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gate_errors() {
+        let prog = parse("include \"qelib1.inc\"; qreg q[2]; x q[0]; x q[1]; h q[0];");
+        let mut noise = NoiseModel::new();
+        noise.add_gate_error("x", Channel::AmplitudeDamping(0.25)).unwrap();
+        noise.add_qubit_gate_error("x", 1, Channel::AmplitudeDamping(1.0)).unwrap();
+        noise.add_gate_error("h", Channel::Depolarizing(1.0)).unwrap();
+        let (rho, _) = simulate(&prog, &noise, Some(0)).unwrap();
+        // q[1] decays fully to |0>; q[0] is completely depolarized.
+        let p = rho.probabilities();
+        for (i, &expected) in [0.5, 0.5, 0.0, 0.0].iter().enumerate() {
+            assert!((p[i] - expected).abs() < 1e-12, "{:?}", p);
+        }
+        assert!((rho.purity() - 0.5).abs() < 1e-12);
+
+        let mut dephasing = NoiseModel::new();
+        dephasing.add_gate_error("h", Channel::PhaseDamping(1.0)).unwrap();
+        let (rho, _) = simulate(&parse("include \"qelib1.inc\"; qreg q[1]; h q[0];"), &dephasing, None).unwrap();
+        assert!(rho.get(0, 1).approx_eq(ZERO, 1e-12));
+        assert!((rho.probabilities()[1] - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn readout_errors_and_reset() {
+        let prog = parse("include \"qelib1.inc\"; qreg q[2]; creg c[2]; x q[1]; measure q -> c;");
+        let mut noise = NoiseModel::new();
+        noise.set_readout_error(ReadoutError::new(0.0, 0.2).unwrap());
+        noise.set_qubit_readout_error(0, ReadoutError::new(1.0, 0.0).unwrap());
+        let counts = sample(&prog, &noise, 1000, Some(5)).unwrap();
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec!["01", "11"]);
+        assert!(counts["01"] > 120 && counts["01"] < 280, "{:?}", counts);
+
+        let prog = parse("include \"qelib1.inc\"; qreg q[1]; h q[0]; reset q[0];");
+        let (rho, _) = simulate(&prog, &NoiseModel::new(), Some(0)).unwrap();
+        assert!(rho.get(0, 0).approx_eq(Complex::real(1.0), 1e-12));
+        assert!((rho.purity() - 1.0).abs() < 1e-12);
+    }
+}
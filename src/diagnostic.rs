@@ -0,0 +1,140 @@
+//! Diagnostics pointing into the source code, rendered like compiler messages:
+//!
+//! ```text
+//! test.qasm:3:1: warning[unused-register]: Quantum register 'r' is never used.
+//!     qreg r[2];
+//!     ^^^^^^^^^^
+//! ```
+
+use std::fmt;
+
+use errors::{ParseError, TokenizeError};
+
+/// A range of the source code: byte offset and length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub pos: usize,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message about the source code, optionally with the id of the check which produced it and
+/// the span it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, code: None, message, span }
+    }
+
+    pub fn warning(message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, code: None, message, span }
+    }
+
+    /// Renders the diagnostic with the line of `code` it points to. Only the first line of a
+    /// span spreading over several lines is underlined.
+    pub fn render(&self, code: &str, filename: &str) -> String {
+        let span = match self.span {
+            Some(span) if span.pos <= code.len() => span,
+            _ => return format!("{}: {}\n", filename, self),
+        };
+        let (line, col) = line_col(code, span.pos);
+        let start = code[..span.pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let end = code[span.pos..].find('\n').map(|i| span.pos + i).unwrap_or_else(|| code.len());
+        let text = code[start..end].trim_end_matches('\r');
+        // Keep tabs so that the carets line up with the source line.
+        let pad: String = code[start..span.pos].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let underlined = code[span.pos..(span.pos + span.len).min(end).max(span.pos)].chars().count();
+        format!("{}:{}:{}: {}\n    {}\n    {}{}\n", filename, line, col, self, text, pad, "^".repeat(underlined.max(1)))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{}[{}]: {}", self.severity, code, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Diagnostic {
+        Diagnostic::error(e.to_string(), Some(Span { pos: e.pos, len: e.len }))
+    }
+}
+
+impl From<&TokenizeError> for Diagnostic {
+    fn from(e: &TokenizeError) -> Diagnostic {
+        Diagnostic::error(e.to_string(), Some(Span { pos: e.pos, len: e.len }))
+    }
+}
+
+/// The 1-based line and column of a byte offset. Columns count characters.
+pub fn line_col(code: &str, pos: usize) -> (usize, usize) {
+    let before = &code[..pos.min(code.len())];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    (line, before[start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use parser::{parse_str, parse_str_with_spans};
+
+    #[test]
+    fn render_diagnostic() {
+        let code = "OPENQASM 2.0;\n\tqreg r[2];\n";
+        let mut d = Diagnostic::warning("Quantum register 'r' is never used.".to_owned(), Some(Span { pos: 15, len: 10 }));
+        d.code = Some("unused-register");
+        assert_eq!(line_col(code, 15), (2, 2));
+        assert_eq!(d.render(code, "test.qasm"),
+                   "test.qasm:2:2: warning[unused-register]: Quantum register 'r' is never used.\n\
+                    \x20   \tqreg r[2];\n\
+                    \x20   \t^^^^^^^^^^\n");
+        d.span = None;
+        assert_eq!(d.render(code, "test.qasm"),
+                   "test.qasm: warning[unused-register]: Quantum register 'r' is never used.\n");
+    }
+
+    #[test]
+    fn parse_error_diagnostic() {
+        let code = "qreg q[2];\nh q[0]\nx q;";
+        let e = parse_str(code, Path::new("test.qasm")).unwrap_err();
+        let rendered = Diagnostic::from(&e).render(code, "test.qasm");
+        assert!(rendered.starts_with("test.qasm:3:1: error: "), "{}", rendered);
+        assert!(rendered.ends_with("    x q;\n    ^\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn statement_spans() {
+        let code = "OPENQASM 2.0;\n// c\nqreg q[1];\ngate g a {\n  U(0, 0, 0) a;\n}\nmeasure q[0]\n  -> c[0];";
+        let (program, spans) = parse_str_with_spans(code, Path::new("test.qasm")).unwrap();
+        assert_eq!(program.len(), spans.len());
+        let texts: Vec<&str> = spans.iter().map(|s| &code[s.pos..s.pos + s.len]).collect();
+        assert_eq!(texts, ["OPENQASM 2.0;", "// c", "qreg q[1];", "gate g a {\n  U(0, 0, 0) a;\n}",
+                           "measure q[0]\n  -> c[0];"]);
+    }
+}
@@ -0,0 +1,603 @@
+//! Text diagrams of circuits.
+//!
+//! Qubits and clbits are drawn as horizontal wires, operations as boxes on them, ordered in
+//! columns from left to right:
+//!
+//! ```text
+//!        ┌───┐       ┌───┐
+//! q[0]: ─┤ h ├───■───┤ M ├───────
+//!        └───┘   │   └─╥─┘
+//!              ┌─┴─┐   ║   ┌───┐
+//! q[1]: ───────┤ x ├───╫───┤ M ├─
+//!              └───┘   ║   └─╥─┘
+//!                      ║     ║
+//! c[0]: ═══════════════╩═════╬═══
+//!                            ║
+//!                            ║
+//! c[1]: ═════════════════════╩═══
+//! ```
+//!
+//! Diagrams wider than the configured width are folded into several pages. `layout` places the
+//! operations in columns without drawing them, for other output formats.
+
+use std::ops::Range;
+
+use lower::{self, LowerResult, Registers};
+use parser::{Expr, Program, Statement};
+
+/// What an operation does to its qubits, apart from controls and conditions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// A gate box with the name and the parameters of the gate, over the qubits in argument
+    /// order.
+    Gate(String, Vec<Expr>, Vec<usize>),
+    /// A control dot, the target of `cz`.
+    Dot(usize),
+    Swap(usize, usize),
+    /// A measurement of the qubit into the clbit.
+    Measure(usize, usize),
+    Reset(usize),
+    Barrier(Vec<usize>),
+}
+
+/// An operation of a lowered program, described by what is drawn. Qubits and clbits are
+/// global indices (see `lower::Registers`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub target: Target,
+    pub controls: Vec<usize>,
+    /// The clbits of the register in the condition of `if`, and the value compared with.
+    pub condition: Option<(Range<usize>, u64)>,
+}
+
+impl Operation {
+    /// Qubits of the target.
+    pub fn target_qubits(&self) -> Vec<usize> {
+        match self.target {
+            Target::Gate(_, _, ref qubits) | Target::Barrier(ref qubits) => qubits.clone(),
+            Target::Dot(q) | Target::Measure(q, _) | Target::Reset(q) => vec![q],
+            Target::Swap(a, b) => vec![a, b],
+        }
+    }
+
+    /// The range of wires the operation spans. Wires are numbered with the qubits first and the
+    /// clbits after them.
+    pub fn wires(&self, num_qubits: usize) -> Range<usize> {
+        let mut wires = self.target_qubits();
+        wires.extend(self.controls.iter().cloned());
+        if let Target::Measure(_, c) = self.target {
+            wires.push(num_qubits + c);
+        }
+        if let Some((ref clbits, _)) = self.condition {
+            wires.push(num_qubits + clbits.start);
+            wires.push(num_qubits + clbits.end - 1);
+        }
+        let lo = wires.iter().cloned().min().unwrap_or(0);
+        let hi = wires.iter().cloned().max().unwrap_or(0);
+        lo..hi + 1
+    }
+}
+
+/// Operations placed in columns, so that the operations of a column span disjoint wires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub qubit_labels: Vec<String>,
+    pub clbit_labels: Vec<String>,
+    pub columns: Vec<Vec<Operation>>,
+}
+
+impl Layout {
+    pub fn num_qubits(&self) -> usize {
+        self.qubit_labels.len()
+    }
+
+    pub fn num_wires(&self) -> usize {
+        self.qubit_labels.len() + self.clbit_labels.len()
+    }
+}
+
+/// `name` or `name(params)`.
+fn gate_label(name: &str, params: &[Expr]) -> String {
+    if params.is_empty() {
+        name.to_owned()
+    } else {
+        let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+        format!("{}({})", name, params.join(", "))
+    }
+}
+
+/// The number of controls and the gate they control, for controlled gates of `qelib1.inc`.
+fn controlled(name: &str) -> Option<(usize, &'static str)> {
+    Some(match name {
+        "CX" => (1, "X"),
+        "cx" => (1, "x"),
+        "ccx" => (2, "x"),
+        "cy" => (1, "y"),
+        "cz" => (1, "z"),
+        "ch" => (1, "h"),
+        "crz" => (1, "rz"),
+        "cu1" => (1, "u1"),
+        "cu3" => (1, "u3"),
+        "cswap" => (1, "swap"),
+        _ => return None,
+    })
+}
+
+fn operation(stmt: &Statement, regs: &Registers) -> Option<Operation> {
+    let target = match *stmt {
+        Statement::Apply(ref name, ref params, ref qargs) => {
+            let qubits: Vec<usize> = qargs.iter().map(|q| regs.qubit_index(q)).collect();
+            match controlled(name) {
+                Some((n, base)) if qubits.len() == n + if base == "swap" { 2 } else { 1 } => {
+                    let target = match base {
+                        "z" => Target::Dot(qubits[n]),
+                        "swap" => Target::Swap(qubits[n], qubits[n + 1]),
+                        _ => Target::Gate(base.to_owned(), params.clone(), qubits[n..].to_vec()),
+                    };
+                    return Some(Operation { target, controls: qubits[..n].to_vec(), condition: None });
+                }
+                _ if name == "swap" && qubits.len() == 2 => Target::Swap(qubits[0], qubits[1]),
+                _ => Target::Gate(name.clone(), params.clone(), qubits),
+            }
+        }
+        Statement::Measure(ref q, ref c) => Target::Measure(regs.qubit_index(q), regs.clbit_index(c)),
+        Statement::Reset(ref q) => Target::Reset(regs.qubit_index(q)),
+        Statement::Barrier(ref qargs) => Target::Barrier(qargs.iter().map(|q| regs.qubit_index(q)).collect()),
+        Statement::If(ref c, val, ref body) => {
+            let mut op = operation(&body[0], regs)?;
+            op.condition = Some((regs.clbits(c).ok()?, val as u64));
+            return Some(op);
+        }
+        _ => return None,
+    };
+    Some(Operation { target, controls: vec![], condition: None })
+}
+
+/// Places the operations of a program in columns, each one as far left as possible.
+pub fn layout(program: &Program) -> LowerResult<Layout> {
+    let regs = Registers::from_program(program)?;
+    let nq = regs.num_qubits();
+    let mut next_column = vec![0; nq + regs.num_clbits()];
+    let mut columns: Vec<Vec<Operation>> = vec![];
+    for stmt in &lower::lower(program)? {
+        let op = match operation(stmt, &regs) {
+            Some(op) => op,
+            None => continue,
+        };
+        let wires = op.wires(nq);
+        let col = wires.clone().map(|w| next_column[w]).max().unwrap_or(0);
+        for w in wires {
+            next_column[w] = col + 1;
+        }
+        if col == columns.len() {
+            columns.push(vec![]);
+        }
+        columns[col].push(op);
+    }
+    Ok(Layout {
+        qubit_labels: (0..nq).map(|i| regs.qubit(i).to_string()).collect(),
+        clbit_labels: (0..regs.num_clbits()).map(|i| regs.clbit(i).to_string()).collect(),
+        columns,
+    })
+}
+
+/// Options for drawing circuits as text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawOptions {
+    /// Whether to use box-drawing characters rather than ASCII.
+    pub unicode: bool,
+    /// The largest width of a line; wider diagrams are folded. 0 never folds.
+    pub width: usize,
+}
+
+impl Default for DrawOptions {
+    fn default() -> DrawOptions {
+        DrawOptions { unicode: true, width: 80 }
+    }
+}
+
+/// The characters of a diagram.
+struct Symbols {
+    qwire: char,
+    cwire: char,
+    qvert: char,
+    cvert: char,
+    /// Crossings of a vertical line with a wire: quantum over quantum, quantum over classical,
+    /// classical over quantum and classical over classical.
+    crossings: [char; 4],
+    /// Box corners: top left, top right, bottom left and bottom right.
+    corners: [char; 4],
+    hbar: char,
+    vbar: char,
+    /// Box sides where a wire goes in and out.
+    wire_in: char,
+    wire_out: char,
+    /// Where a vertical line meets the top and the bottom of a box.
+    join_top: char,
+    join_bottom: char,
+    cjoin_bottom: char,
+    control: char,
+    swap: char,
+    barrier: char,
+    measured: char,
+    /// Markers of the bits of a condition which must be 1 and 0.
+    one: char,
+    zero: char,
+    fold_out: char,
+    fold_in: char,
+}
+
+const UNICODE: Symbols = Symbols {
+    qwire: '─',
+    cwire: '═',
+    qvert: '│',
+    cvert: '║',
+    crossings: ['┼', '╪', '╫', '╬'],
+    corners: ['┌', '┐', '└', '┘'],
+    hbar: '─',
+    vbar: '│',
+    wire_in: '┤',
+    wire_out: '├',
+    join_top: '┴',
+    join_bottom: '┬',
+    cjoin_bottom: '╥',
+    control: '■',
+    swap: '╳',
+    barrier: '░',
+    measured: '╩',
+    one: '■',
+    zero: '○',
+    fold_out: '»',
+    fold_in: '«',
+};
+
+const ASCII: Symbols = Symbols {
+    qwire: '-',
+    cwire: '=',
+    qvert: '|',
+    cvert: '|',
+    crossings: ['+', '+', '+', '+'],
+    corners: ['+', '+', '+', '+'],
+    hbar: '-',
+    vbar: '|',
+    wire_in: '|',
+    wire_out: '|',
+    join_top: '+',
+    join_bottom: '+',
+    cjoin_bottom: '+',
+    control: '*',
+    swap: 'x',
+    barrier: '#',
+    measured: 'v',
+    one: '1',
+    zero: '0',
+    fold_out: '>',
+    fold_in: '<',
+};
+
+/// A column of the diagram. Wire `w` takes the text rows `3w` to `3w + 2`, with the wire itself
+/// in the middle one.
+struct Canvas<'a> {
+    sym: &'a Symbols,
+    num_qubits: usize,
+    rows: Vec<Vec<char>>,
+}
+
+impl<'a> Canvas<'a> {
+    fn new(sym: &'a Symbols, num_qubits: usize, num_wires: usize, width: usize) -> Canvas<'a> {
+        let rows = (0..3 * num_wires).map(|r| {
+            let c = if r % 3 != 1 { ' ' } else if r / 3 < num_qubits { sym.qwire } else { sym.cwire };
+            vec![c; width]
+        }).collect();
+        Canvas { sym, num_qubits, rows }
+    }
+
+    fn set(&mut self, row: usize, x: usize, c: char) {
+        self.rows[row][x] = c;
+    }
+
+    /// Draws a vertical line between the middle rows of two wires, not including them.
+    fn vertical(&mut self, from: usize, to: usize, x: usize, classical: bool) {
+        let sym = self.sym;
+        for row in 3 * from + 2..3 * to + 1 {
+            let c = self.rows[row][x];
+            let crossing = match (c == sym.qwire, c == sym.cwire) {
+                _ if row % 3 != 1 => None,
+                (true, _) => Some(if classical { 2 } else { 0 }),
+                (_, true) => Some(if classical { 3 } else { 1 }),
+                _ => None,
+            };
+            self.rows[row][x] = match crossing {
+                Some(i) => sym.crossings[i],
+                None if classical => sym.cvert,
+                None => sym.qvert,
+            };
+        }
+    }
+
+    /// Draws a box from wire `top` to wire `bottom`, with the wires of `qubits` going into it.
+    fn gate_box(&mut self, x: usize, width: usize, top: usize, bottom: usize, qubits: &[usize], label: &str) {
+        let sym = self.sym;
+        let (first, last) = (3 * top, 3 * bottom + 2);
+        for row in first..last + 1 {
+            for i in 0..width {
+                let c = match (row == first, row == last, i == 0, i == width - 1) {
+                    (true, _, true, _) => sym.corners[0],
+                    (true, _, _, true) => sym.corners[1],
+                    (_, true, true, _) => sym.corners[2],
+                    (_, true, _, true) => sym.corners[3],
+                    (true, ..) | (_, true, ..) => sym.hbar,
+                    (_, _, true, _) if row % 3 == 1 && qubits.contains(&(row / 3)) => sym.wire_in,
+                    (_, _, _, true) if row % 3 == 1 && qubits.contains(&(row / 3)) => sym.wire_out,
+                    (_, _, true, _) | (_, _, _, true) => sym.vbar,
+                    _ => ' ',
+                };
+                self.rows[row][x + i] = c;
+            }
+        }
+        if qubits.len() > 1 {
+            // Number the arguments, and put the label in the middle.
+            for (i, &q) in qubits.iter().enumerate() {
+                let digits = i.to_string();
+                for (j, c) in digits.chars().enumerate() {
+                    self.rows[3 * q + 1][x + 1 + j] = c;
+                }
+            }
+            let row = (first + last) / 2;
+            for (j, c) in label.chars().enumerate() {
+                self.rows[row][x + 3 + j] = c;
+            }
+        } else {
+            for (j, c) in label.chars().enumerate() {
+                self.rows[3 * top + 1][x + 2 + j] = c;
+            }
+        }
+    }
+
+    /// Draws an operation, starting at `x`.
+    fn operation(&mut self, op: &Operation, x: usize) {
+        let sym = self.sym;
+        let nq = self.num_qubits;
+        let cx = x + op_width(op) / 2;
+        let targets = op.target_qubits();
+
+        // The quantum part spans from `top` to `bottom`.
+        let mut quantum = targets.clone();
+        quantum.extend(op.controls.iter().cloned());
+        let top = *quantum.iter().min().unwrap();
+        let bottom = *quantum.iter().max().unwrap();
+        let barrier = matches!(op.target, Target::Barrier(_));
+        if !barrier && top != bottom {
+            self.vertical(top, bottom, cx, false);
+        }
+        let classical_end = match (&op.target, &op.condition) {
+            (_, &Some((ref clbits, _))) => Some(nq + clbits.end - 1),
+            (&Target::Measure(_, c), _) => Some(nq + c),
+            _ => None,
+        };
+        if let Some(end) = classical_end {
+            self.vertical(bottom, end, cx, true);
+        }
+
+        match op.target {
+            Target::Gate(ref name, ref params, ref qubits) => {
+                self.boxed(op, x, qubits, &gate_label(name, params), classical_end.is_some());
+            }
+            Target::Measure(q, c) => {
+                self.boxed(op, x, &[q], "M", true);
+                self.set(3 * (nq + c) + 1, cx, sym.measured);
+            }
+            Target::Reset(q) => self.boxed(op, x, &[q], "|0>", classical_end.is_some()),
+            Target::Dot(q) => self.set(3 * q + 1, cx, sym.control),
+            Target::Swap(a, b) => {
+                self.set(3 * a + 1, cx, sym.swap);
+                self.set(3 * b + 1, cx, sym.swap);
+            }
+            Target::Barrier(ref qubits) => {
+                for &q in qubits {
+                    for row in 3 * q..3 * q + 3 {
+                        self.set(row, cx, sym.barrier);
+                    }
+                }
+            }
+        }
+        for &q in &op.controls {
+            self.set(3 * q + 1, cx, sym.control);
+        }
+        if let Some((ref clbits, value)) = op.condition {
+            for (i, c) in clbits.clone().enumerate() {
+                let marker = if value >> i & 1 == 1 { sym.one } else { sym.zero };
+                self.set(3 * (nq + c) + 1, cx, marker);
+            }
+        }
+    }
+
+    /// Draws the box of an operation, joined to the vertical lines around it.
+    fn boxed(&mut self, op: &Operation, x: usize, qubits: &[usize], label: &str, classical_below: bool) {
+        let sym = self.sym;
+        let width = op_width(op);
+        let cx = x + width / 2;
+        let top = *qubits.iter().min().unwrap();
+        let bottom = *qubits.iter().max().unwrap();
+        self.gate_box(x, width, top, bottom, qubits, label);
+        if op.controls.iter().any(|&c| c < top) {
+            self.set(3 * top, cx, sym.join_top);
+        }
+        if classical_below {
+            self.set(3 * bottom + 2, cx, sym.cjoin_bottom);
+        } else if op.controls.iter().any(|&c| c > bottom) {
+            self.set(3 * bottom + 2, cx, sym.join_bottom);
+        }
+    }
+}
+
+/// The width of a drawn operation.
+fn op_width(op: &Operation) -> usize {
+    let label_width = |label: &str| label.chars().count() + 4;
+    match op.target {
+        Target::Gate(ref name, ref params, ref qubits) if qubits.len() > 1 => {
+            label_width(&gate_label(name, params)) + 1 + (qubits.len() - 1).to_string().len()
+        }
+        Target::Gate(ref name, ref params, _) => label_width(&gate_label(name, params)),
+        Target::Measure(..) => label_width("M"),
+        Target::Reset(_) => label_width("|0>"),
+        Target::Dot(_) | Target::Swap(..) | Target::Barrier(_) => 1,
+    }
+}
+
+/// Draws a column of operations.
+fn draw_column(layout: &Layout, ops: &[Operation], sym: &Symbols) -> Vec<Vec<char>> {
+    let width = ops.iter().map(op_width).max().unwrap_or(1);
+    let mut canvas = Canvas::new(sym, layout.num_qubits(), layout.num_wires(), width);
+    for op in ops {
+        canvas.operation(op, (width - op_width(op)) / 2);
+    }
+    canvas.rows
+}
+
+/// Draws a program as text.
+pub fn draw(program: &Program, options: &DrawOptions) -> LowerResult<String> {
+    Ok(draw_layout(&layout(program)?, options))
+}
+
+/// Draws placed operations as text.
+pub fn draw_layout(layout: &Layout, options: &DrawOptions) -> String {
+    let sym = if options.unicode { &UNICODE } else { &ASCII };
+    let nq = layout.num_qubits();
+    let num_rows = 3 * layout.num_wires();
+    let labels: Vec<String> = layout.qubit_labels.iter().chain(layout.clbit_labels.iter())
+        .map(|l| format!("{}: ", l)).collect();
+    let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let wire = |row: usize| if row % 3 != 1 { ' ' } else if row / 3 < nq { sym.qwire } else { sym.cwire };
+
+    let columns: Vec<Vec<Vec<char>>> = layout.columns.iter().map(|ops| draw_column(layout, ops, sym)).collect();
+    // Split the columns into pages which fit into the width. Each page starts with the labels
+    // and a wire segment, continued pages with a fold mark too, and has room for a fold mark.
+    let mut pages: Vec<Vec<&Vec<Vec<char>>>> = vec![vec![]];
+    let mut used = label_width + 1;
+    for col in &columns {
+        let width = col.first().map(|r| r.len()).unwrap_or(0) + 1;
+        let page_full = !pages.last().unwrap().is_empty() && used + width + 1 > options.width;
+        if options.width > 0 && page_full {
+            pages.push(vec![]);
+            used = label_width + 2;
+        }
+        used += width;
+        pages.last_mut().unwrap().push(col);
+    }
+
+    let mut out = vec![];
+    for (p, page) in pages.iter().enumerate() {
+        let mut lines = vec![];
+        for row in 0..num_rows {
+            let mut line: String = if row % 3 == 1 {
+                format!("{:<width$}", labels[row / 3], width = label_width)
+            } else {
+                " ".repeat(label_width)
+            };
+            if p > 0 {
+                line.push(if row % 3 == 1 { sym.fold_in } else { ' ' });
+            }
+            line.push(wire(row));
+            for col in page {
+                line.extend(col[row].iter());
+                line.push(wire(row));
+            }
+            if p + 1 < pages.len() && row % 3 == 1 {
+                line.push(sym.fold_out);
+            }
+            lines.push(line.trim_end().to_owned());
+        }
+        // Leave out the empty rows above the first wire and below the last one.
+        let first = lines.iter().position(|l| !l.is_empty()).unwrap_or(0);
+        let last = lines.iter().rposition(|l| !l.is_empty()).map(|i| i + 1).unwrap_or(0);
+        if p > 0 {
+            out.push(String::new());
+        }
+        out.extend(lines.drain(first..last));
+    }
+    let mut out = out.join("\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    fn draw_str(code: &str, options: &DrawOptions) -> String {
+        let program = parse(code);
+        draw(&program, options).unwrap()
+    }
+
+    #[test]
+    fn draw_bell() {
+        let code = "qreg q[2];\ncreg c[2];\nh q[0];\ncx q[0], q[1];\nmeasure q -> c;";
+        let expected = "       ┌───┐       ┌───┐\n\
+                        q[0]: ─┤ h ├───■───┤ M ├───────\n\
+                        \x20      └───┘   │   └─╥─┘\n\
+                        \x20            ┌─┴─┐   ║   ┌───┐\n\
+                        q[1]: ───────┤ x ├───╫───┤ M ├─\n\
+                        \x20            └───┘   ║   └─╥─┘\n\
+                        \x20                    ║     ║\n\
+                        c[0]: ═══════════════╩═════╬═══\n\
+                        \x20                          ║\n\
+                        \x20                          ║\n\
+                        c[1]: ═════════════════════╩═══\n";
+        assert_eq!(draw_str(code, &DrawOptions::default()), expected);
+    }
+
+    #[test]
+    fn draw_ascii() {
+        let code = "qreg q[3];\ncreg c[2];\nccx q[0], q[2], q[1];\nbarrier q[0], q[1];\n\
+                    if (c == 2) u1(pi/2) q[2];\nswap q[0], q[1];\ncz q[2], q[0];\nreset q[1];\nmeasure q[1] -> c[1];";
+        let expected = "                  #\n\
+                        q[0]: ---*--------#-------x-*---------------\n\
+                        \x20        |        #       | |\n\
+                        \x20      +-+-+      #       | | +-----+ +---+\n\
+                        q[1]: -| x |------#-------x-+-| |0> |-| M |-\n\
+                        \x20      +-+-+      #         | +-----+ +-+-+\n\
+                        \x20        |   +----------+   |           |\n\
+                        q[2]: ---*---| u1(pi/2) |---*-----------+---\n\
+                        \x20            +-----+----+               |\n\
+                        \x20                  |                    |\n\
+                        c[0]: =============0====================+===\n\
+                        \x20                  |                    |\n\
+                        \x20                  |                    |\n\
+                        c[1]: =============1====================v===\n";
+        assert_eq!(draw_str(code, &DrawOptions { unicode: false, width: 0 }), expected);
+    }
+
+    #[test]
+    fn fold_wide_circuits() {
+        let code = "qreg q[1];\nh q[0];\nx q[0];\nh q[0];\nrz(0.25) q[0];\nh q[0];\nx q[0];";
+        let expected = "       ┌───┐ ┌───┐ ┌───┐\n\
+                        q[0]: ─┤ h ├─┤ x ├─┤ h ├─»\n\
+                        \x20      └───┘ └───┘ └───┘\n\
+                        \n\
+                        \x20       ┌──────────┐ ┌───┐\n\
+                        q[0]: «─┤ rz(0.25) ├─┤ h ├─»\n\
+                        \x20       └──────────┘ └───┘\n\
+                        \n\
+                        \x20       ┌───┐\n\
+                        q[0]: «─┤ x ├─\n\
+                        \x20       └───┘\n";
+        assert_eq!(draw_str(code, &DrawOptions { unicode: true, width: 30 }), expected);
+        assert_eq!(draw_str(code, &DrawOptions { unicode: true, width: 0 }).lines().count(), 3);
+    }
+
+    #[test]
+    fn layout_columns() {
+        let program = parse("qreg q[3];\ngate g a, b { CX a, b; }\nh q[0];\nh q[2];\ng q[2], q[0];\nx q[1];");
+        let layout = layout(&program).unwrap();
+        assert_eq!(layout.qubit_labels, ["q[0]", "q[1]", "q[2]"]);
+        let targets: Vec<Vec<Target>> = layout.columns.iter()
+            .map(|col| col.iter().map(|op| op.target.clone()).collect()).collect();
+        let gate = |name: &str, qubits: Vec<usize>| Target::Gate(name.to_owned(), vec![], qubits);
+        assert_eq!(targets, vec![vec![gate("h", vec![0]), gate("h", vec![2])], vec![gate("g", vec![2, 0])],
+                                 vec![gate("x", vec![1])]]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Checks whether two circuits implement the same unitary, e.g. before and after optimization.
+//!
+//! Circuits on few qubits are compared exactly, column by column of their unitaries. Larger
+//! circuits are run on random input states, which finds any difference with probability one
+//! (a random state is almost never an eigenvector of `A^-1 B`), but only up to the tolerance.
+//!
+//! Qubits are matched by their global index (see `lower::Registers`), so the register names and
+//! sizes may differ as long as the total number of qubits is the same.
+
+use std::f64::consts::PI;
+
+use complex::{Complex, ONE, ZERO};
+use errors::CircuitError;
+use parser::Program;
+use rng::Rng;
+use simulator::{Executor, SimulationResult, StateVector, MAX_QUBITS};
+
+/// Options of `equivalent_with`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EquivalenceOptions {
+    /// Circuits on at most this many qubits are compared by their unitaries.
+    pub max_exact_qubits: usize,
+    /// The number of random input states for larger circuits.
+    pub num_states: usize,
+    /// The largest difference of an output amplitude.
+    pub tolerance: f64,
+    /// Whether circuits which differ by a global phase are equivalent.
+    pub ignore_global_phase: bool,
+    pub seed: u64,
+}
+
+impl Default for EquivalenceOptions {
+    fn default() -> EquivalenceOptions {
+        EquivalenceOptions { max_exact_qubits: 8, num_states: 3, tolerance: 1e-8, ignore_global_phase: true, seed: 0 }
+    }
+}
+
+/// How the circuits were compared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Unitary,
+    /// Simulation of this many random input states.
+    RandomStates(usize),
+}
+
+/// The input state on which the circuits differ.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// The computational basis state with this index, i.e. a column of the unitaries.
+    Basis(usize),
+    /// The random state with this index in the sequence of tried states, and its amplitudes.
+    Random(usize, Vec<Complex>),
+}
+
+/// The first output amplitude in which the circuits differ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub input: Input,
+    /// The index of the amplitude in the output states.
+    pub index: usize,
+    /// The amplitude of the first circuit, times the global phase if it is ignored.
+    pub first: Complex,
+    pub second: Complex,
+}
+
+/// The result of `equivalent`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equivalence {
+    pub method: Method,
+    pub num_qubits: usize,
+    /// `None` if the circuits are equivalent.
+    pub difference: Option<Difference>,
+}
+
+impl Equivalence {
+    pub fn is_equivalent(&self) -> bool {
+        self.difference.is_none()
+    }
+}
+
+/// A state with normally distributed amplitudes, i.e. uniformly distributed on the sphere.
+fn random_state(num_qubits: usize, rng: &mut Rng) -> Vec<Complex> {
+    let mut amps: Vec<Complex> = (0..1 << num_qubits).map(|_| {
+        // Box-Muller; `1 - x` is in (0, 1], so the logarithm is finite.
+        let r = (-2.0 * (1.0 - rng.next_f64()).ln()).sqrt();
+        Complex::expi(2.0 * PI * rng.next_f64()).scale(r)
+    }).collect();
+    let norm = amps.iter().map(|a| a.norm_sqr()).sum::<f64>().sqrt();
+    for a in &mut amps {
+        *a = a.scale(1.0 / norm);
+    }
+    amps
+}
+
+/// Compares the outputs of the circuits on states one at a time. The global phase is taken from
+/// the largest amplitude of the first output.
+struct Comparison<'a> {
+    first: &'a Executor,
+    second: &'a Executor,
+    options: &'a EquivalenceOptions,
+    phase: Option<Complex>,
+}
+
+impl<'a> Comparison<'a> {
+    fn run(&self, executor: &Executor, input: &[Complex]) -> SimulationResult<StateVector> {
+        let mut state = StateVector::from_amplitudes(input.to_vec());
+        // There are no measurements, so the generator is never used.
+        executor.run(&mut state, &mut Rng::from_seed(0))?;
+        Ok(state)
+    }
+
+    /// The index and amplitudes of the first difference of the outputs on `input`.
+    fn compare(&mut self, input: &[Complex]) -> SimulationResult<Option<(usize, Complex, Complex)>> {
+        let (a, b) = (self.run(self.first, input)?, self.run(self.second, input)?);
+        let (a, b) = (a.amplitudes(), b.amplitudes());
+        let phase = match self.phase {
+            Some(phase) => phase,
+            None if self.options.ignore_global_phase => {
+                let (idx, _) = a.iter().enumerate()
+                    .fold((0, 0.0), |(bi, bv), (i, x)| if x.abs() > bv { (i, x.abs()) } else { (bi, bv) });
+                *self.phase.get_or_insert(Complex::expi(b[idx].arg() - a[idx].arg()))
+            }
+            None => *self.phase.get_or_insert(ONE),
+        };
+        Ok(a.iter().zip(b).enumerate()
+            .map(|(i, (&x, &y))| (i, x * phase, y))
+            .find(|&(_, x, y)| !x.approx_eq(y, self.options.tolerance)))
+    }
+}
+
+/// Whether two measurement-free circuits implement the same unitary, with default options.
+pub fn equivalent(a: &Program, b: &Program) -> SimulationResult<Equivalence> {
+    equivalent_with(a, b, &EquivalenceOptions::default())
+}
+
+/// Whether two measurement-free circuits implement the same unitary. Circuits on at most
+/// `options.max_exact_qubits` qubits are compared on all basis states, larger ones on
+/// `options.num_states` random states. All parameters must be bound.
+pub fn equivalent_with(a: &Program, b: &Program, options: &EquivalenceOptions) -> SimulationResult<Equivalence> {
+    let (first, second) = (Executor::new(a)?, Executor::new(b)?);
+    for executor in &[&first, &second] {
+        if let Some(op) = executor.non_unitary_operation() {
+            return Err(CircuitError::new(format!("Cannot compare circuits with '{}'.", op)));
+        }
+    }
+    let n = first.registers().num_qubits();
+    if second.registers().num_qubits() != n {
+        return Err(CircuitError::new(format!("The circuits act on {} and {} qubits.", n,
+                                             second.registers().num_qubits())));
+    }
+    if n > MAX_QUBITS {
+        return Err(CircuitError::new(format!("{} qubits are too many to simulate.", n)));
+    }
+    let mut comparison = Comparison { first: &first, second: &second, options, phase: None };
+    if n <= options.max_exact_qubits {
+        for col in 0..1 << n {
+            let mut input = vec![ZERO; 1 << n];
+            input[col] = ONE;
+            if let Some((index, first, second)) = comparison.compare(&input)? {
+                let difference = Difference { input: Input::Basis(col), index, first, second };
+                return Ok(Equivalence { method: Method::Unitary, num_qubits: n, difference: Some(difference) });
+            }
+        }
+        return Ok(Equivalence { method: Method::Unitary, num_qubits: n, difference: None });
+    }
+    let mut rng = Rng::from_seed(options.seed);
+    let method = Method::RandomStates(options.num_states);
+    for k in 0..options.num_states {
+        let input = random_state(n, &mut rng);
+        if let Some((index, first, second)) = comparison.compare(&input)? {
+            let difference = Difference { input: Input::Random(k, input), index, first, second };
+            return Ok(Equivalence { method, num_qubits: n, difference: Some(difference) });
+        }
+    }
+    Ok(Equivalence { method, num_qubits: n, difference: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use optimize;
+    use test_util::parse;
+
+    #[test]
+    fn equivalent_circuits() {
+        let a = parse("include \"qelib1.inc\";\nqreg q[2];\ncx q[0], q[1];\ncx q[1], q[0];\ncx q[0], q[1];");
+        let b = parse("include \"qelib1.inc\";\nqreg r[1];\nqreg s[1];\nswap r[0], s[0];");
+        let result = equivalent(&a, &b).unwrap();
+        assert!(result.is_equivalent());
+        assert_eq!(result.method, Method::Unitary);
+        // rz and u1 differ by a global phase only.
+        let a = parse("include \"qelib1.inc\";\nqreg q[1];\nrx(0.3) q[0];");
+        let b = parse("include \"qelib1.inc\";\nqreg q[1];\nh q[0];\nrz(0.3) q[0];\nh q[0];");
+        assert!(equivalent(&a, &b).unwrap().is_equivalent());
+        let exact = EquivalenceOptions { ignore_global_phase: false, ..EquivalenceOptions::default() };
+        assert!(!equivalent_with(&a, &b, &exact).unwrap().is_equivalent());
+    }
+
+    #[test]
+    fn first_difference() {
+        let a = parse("include \"qelib1.inc\";\nqreg q[2];\ncx q[0], q[1];");
+        let b = parse("include \"qelib1.inc\";\nqreg q[2];\ncx q[1], q[0];");
+        let difference = equivalent(&a, &b).unwrap().difference.unwrap();
+        // Column 0 is the same; on |01> the first circuit gives |11> and the second |01>.
+        assert_eq!(difference.input, Input::Basis(1));
+        assert_eq!(difference.index, 1);
+        assert!(difference.first.approx_eq(ZERO, 1e-12) && difference.second.approx_eq(ONE, 1e-12));
+    }
+
+    #[test]
+    fn random_states() {
+        let circuit = |t: &str| {
+            let body: String = (0..9).map(|i| format!("cx q[{}], q[{}];\nry(0.{}) q[{}];\n", i, i + 1, i + 1, i)).collect();
+            parse(&format!("include \"qelib1.inc\";\nqreg q[10];\nh q;\n{}rz(0.25) q[3];\n{} q[3];\nccx q[0], q[5], q[9];",
+                           body, t))
+        };
+        let a = circuit("t");
+        let (optimized, _) = optimize::optimize(&a).unwrap();
+        let result = equivalent(&a, &optimized).unwrap();
+        assert_eq!(result.method, Method::RandomStates(3));
+        assert!(result.is_equivalent());
+        let b = circuit("tdg");
+        let result = equivalent(&a, &b).unwrap();
+        match result.difference.unwrap().input {
+            Input::Random(0, ref amps) => assert_eq!(amps.len(), 1 << 10),
+            ref input => panic!("{:?}", input),
+        }
+        let small = parse("qreg q[3];");
+        assert!(equivalent(&a, &small).is_err());
+    }
+}
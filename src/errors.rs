@@ -32,3 +32,65 @@ impl error::Error for TokenizeError {
         &self.errstr
     }
 }
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub filename: Rc<Path>,
+    pub pos: usize,
+    pub len: usize,
+    errstr: String,
+}
+
+impl ParseError {
+    pub fn new(filename: Rc<Path>, pos: usize, len: usize, errstr: String) -> ParseError {
+        ParseError {
+            filename,
+            pos,
+            len,
+            errstr,
+        }
+    }
+}
+
+impl From<TokenizeError> for ParseError {
+    fn from(e: TokenizeError) -> ParseError {
+        ParseError::new(e.filename, e.pos, e.len, e.errstr)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.errstr)
+    }
+}
+
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        &self.errstr
+    }
+}
+
+/// An error about the structure of a circuit, e.g. an undeclared register or an index out of
+/// range. It has no position because it is found after parsing.
+#[derive(Debug)]
+pub struct CircuitError {
+    errstr: String,
+}
+
+impl CircuitError {
+    pub fn new(errstr: String) -> CircuitError {
+        CircuitError { errstr }
+    }
+}
+
+impl fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.errstr)
+    }
+}
+
+impl error::Error for CircuitError {
+    fn description(&self) -> &str {
+        &self.errstr
+    }
+}
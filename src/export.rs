@@ -0,0 +1,404 @@
+//! Circuit figures: standalone SVG and LaTeX using the `quantikz` package.
+//!
+//! Both use the columns of `draw::layout`. Gate names are capitalized and parameters which are
+//! rational multiples of pi are written as such, e.g. `RZ(3π/4)`.
+
+use draw::{self, Layout, Operation, Target};
+use lower::LowerResult;
+use parser::{Expr, Program};
+
+/// Formats a parameter, with `pi` as the symbol of pi. Values close to a multiple of pi with a
+/// small denominator are written as one, other values with at most four decimals.
+fn param(expr: &Expr, pi: &str) -> String {
+    let angle = match expr.snapped_angle() {
+        Some(angle) => angle,
+        None => return expr.to_string(),
+    };
+    if !angle.is_exact() {
+        let s = format!("{:.4}", angle.value());
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+        return if s == "-0" { "0".to_owned() } else { s.to_owned() };
+    }
+    let (num, den) = angle.pi_fraction();
+    let sign = if num < 0 { "-" } else { "" };
+    let numer = match num.abs() {
+        0 => return "0".to_owned(),
+        1 => pi.to_owned(),
+        n => format!("{}{}", n, pi),
+    };
+    if den == 1 {
+        format!("{}{}", sign, numer)
+    } else {
+        format!("{}{}/{}", sign, numer, den)
+    }
+}
+
+/// Whether the target is the `x` of a `cx` or `ccx`, drawn as a circled plus.
+fn is_cnot_target(op: &Operation) -> bool {
+    match op.target {
+        Target::Gate(ref name, ref params, ref qubits) => {
+            !op.controls.is_empty() && params.is_empty() && qubits.len() == 1 && name.eq_ignore_ascii_case("x")
+        }
+        _ => false,
+    }
+}
+
+// Geometry of SVG figures, in pixels.
+const ROW: f64 = 40.0;
+const MARGIN: f64 = 20.0;
+const CHAR_WIDTH: f64 = 8.0;
+const BOX_HEIGHT: f64 = 30.0;
+const COLUMN_GAP: f64 = 16.0;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn svg_label(name: &str, params: &[Expr]) -> String {
+    let name = name.to_uppercase();
+    if params.is_empty() {
+        name
+    } else {
+        let params: Vec<String> = params.iter().map(|p| param(p, "π")).collect();
+        format!("{}({})", name, params.join(", "))
+    }
+}
+
+fn svg_width(op: &Operation) -> f64 {
+    let text = |s: &str| s.chars().count() as f64 * CHAR_WIDTH + 16.0;
+    match op.target {
+        _ if is_cnot_target(op) => 24.0,
+        Target::Gate(ref name, ref params, ref qubits) => {
+            let width = text(&svg_label(name, params)).max(BOX_HEIGHT);
+            if qubits.len() > 1 { width + 12.0 } else { width }
+        }
+        Target::Measure(..) => BOX_HEIGHT,
+        Target::Reset(_) => text("|0⟩"),
+        Target::Dot(_) | Target::Swap(..) => 16.0,
+        Target::Barrier(_) => 12.0,
+    }
+}
+
+struct Svg {
+    out: String,
+    num_qubits: usize,
+}
+
+impl Svg {
+    fn y(&self, wire: usize) -> f64 {
+        MARGIN + ROW / 2.0 + wire as f64 * ROW
+    }
+
+    fn line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64) {
+        self.out.push_str(&format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\"/>\n", x1, y1, x2, y2));
+    }
+
+    /// Two parallel lines, for classical wires.
+    fn double_line(&mut self, x1: f64, y1: f64, x2: f64, y2: f64) {
+        let (dx, dy) = if y1 == y2 { (0.0, 1.5) } else { (1.5, 0.0) };
+        self.line(x1 - dx, y1 - dy, x2 - dx, y2 - dy);
+        self.line(x1 + dx, y1 + dy, x2 + dx, y2 + dy);
+    }
+
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"white\" stroke=\"black\"/>\n",
+                                   x, y, width, height));
+    }
+
+    fn circle(&mut self, x: f64, y: f64, r: f64, filled: bool) {
+        let fill = if filled { "black" } else { "white" };
+        self.out.push_str(&format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"black\"/>\n", x, y, r, fill));
+    }
+
+    fn text(&mut self, x: f64, y: f64, text: &str, anchor: &str, size: u32) {
+        self.out.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"{}\" dominant-baseline=\"central\" \
+                                    font-size=\"{}\">{}</text>\n", x, y, anchor, size, escape(text)));
+    }
+
+    /// A box from wire `top` to wire `bottom`, centered at `cx`.
+    fn gate_box(&mut self, cx: f64, width: f64, top: usize, bottom: usize, label: &str) {
+        let (y1, y2) = (self.y(top) - BOX_HEIGHT / 2.0, self.y(bottom) + BOX_HEIGHT / 2.0);
+        self.rect(cx - width / 2.0, y1, width, y2 - y1);
+        self.text(cx, (y1 + y2) / 2.0, label, "middle", 14);
+    }
+
+    fn operation(&mut self, op: &Operation, cx: f64) {
+        let nq = self.num_qubits;
+        let width = svg_width(op);
+        let targets = op.target_qubits();
+        let mut quantum = targets.clone();
+        quantum.extend(op.controls.iter().cloned());
+        let top = *quantum.iter().min().unwrap();
+        let bottom = *quantum.iter().max().unwrap();
+
+        // Lines first, so that boxes are drawn over them.
+        if !matches!(op.target, Target::Barrier(_)) && top != bottom {
+            let (y1, y2) = (self.y(top), self.y(bottom));
+            self.line(cx, y1, cx, y2);
+        }
+        let classical_end = match (&op.target, &op.condition) {
+            (_, &Some((ref clbits, _))) => Some(nq + clbits.end - 1),
+            (&Target::Measure(_, c), _) => Some(nq + c),
+            _ => None,
+        };
+        if let Some(end) = classical_end {
+            let (y1, y2) = (self.y(bottom), self.y(end));
+            self.double_line(cx, y1, cx, y2);
+        }
+
+        match op.target {
+            _ if is_cnot_target(op) => {
+                let y = self.y(targets[0]);
+                self.circle(cx, y, 10.0, false);
+                self.line(cx - 10.0, y, cx + 10.0, y);
+                self.line(cx, y - 10.0, cx, y + 10.0);
+            }
+            Target::Gate(ref name, ref params, ref qubits) => {
+                let (lo, hi) = (*qubits.iter().min().unwrap(), *qubits.iter().max().unwrap());
+                self.gate_box(cx, width, lo, hi, &svg_label(name, params));
+                if qubits.len() > 1 {
+                    for (i, &q) in qubits.iter().enumerate() {
+                        let y = self.y(q);
+                        self.text(cx - width / 2.0 + 4.0, y, &i.to_string(), "start", 10);
+                    }
+                }
+            }
+            Target::Measure(q, c) => {
+                let (y, yc) = (self.y(q), self.y(nq + c));
+                self.gate_box(cx, width, q, q, "");
+                // The meter: an arc and a needle.
+                self.out.push_str(&format!("<path d=\"M {} {} A 10 10 0 0 1 {} {}\" fill=\"none\" stroke=\"black\"/>\n",
+                                           cx - 10.0, y + 6.0, cx + 10.0, y + 6.0));
+                self.line(cx, y + 6.0, cx + 7.0, y - 8.0);
+                // An arrow into the clbit.
+                self.out.push_str(&format!("<polygon points=\"{},{} {},{} {},{}\" fill=\"black\"/>\n",
+                                           cx - 5.0, yc - 8.0, cx + 5.0, yc - 8.0, cx, yc));
+            }
+            Target::Reset(q) => self.gate_box(cx, width, q, q, "|0⟩"),
+            Target::Dot(q) => {
+                let y = self.y(q);
+                self.circle(cx, y, 4.0, true);
+            }
+            Target::Swap(a, b) => {
+                for &q in &[a, b] {
+                    let y = self.y(q);
+                    self.line(cx - 6.0, y - 6.0, cx + 6.0, y + 6.0);
+                    self.line(cx - 6.0, y + 6.0, cx + 6.0, y - 6.0);
+                }
+            }
+            Target::Barrier(ref qubits) => {
+                for &q in qubits {
+                    let y = self.y(q);
+                    self.out.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"lightgray\" \
+                                                fill-opacity=\"0.5\"/>\n", cx - 6.0, y - ROW / 2.0, 12.0, ROW));
+                    self.out.push_str(&format!("<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke=\"black\" \
+                                                stroke-dasharray=\"4,3\"/>\n", cx, y - ROW / 2.0, y + ROW / 2.0));
+                }
+            }
+        }
+        for &c in &op.controls {
+            let y = self.y(c);
+            self.circle(cx, y, 4.0, true);
+        }
+        if let Some((ref clbits, value)) = op.condition {
+            for (i, c) in clbits.clone().enumerate() {
+                let y = self.y(nq + c);
+                self.circle(cx, y, 4.0, value >> i & 1 == 1);
+            }
+        }
+    }
+}
+
+fn layout_svg(layout: &Layout) -> String {
+    let labels: Vec<&String> = layout.qubit_labels.iter().chain(layout.clbit_labels.iter()).collect();
+    let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0) as f64 * CHAR_WIDTH + 10.0;
+    let widths: Vec<f64> = layout.columns.iter()
+        .map(|col| col.iter().map(svg_width).fold(0.0, f64::max)).collect();
+    let wires_start = MARGIN + label_width;
+    let wires_end = wires_start + widths.iter().map(|w| w + COLUMN_GAP).sum::<f64>() + COLUMN_GAP;
+    let width = wires_end + MARGIN;
+    let height = 2.0 * MARGIN + layout.num_wires() as f64 * ROW;
+
+    let mut svg = Svg { out: String::new(), num_qubits: layout.num_qubits() };
+    svg.out.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+                               viewBox=\"0 0 {0} {1}\" font-family=\"serif\">\n", width, height));
+    svg.out.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n");
+    for (w, label) in labels.iter().enumerate() {
+        let y = svg.y(w);
+        svg.text(MARGIN + label_width - 10.0, y, label, "end", 14);
+        if w < layout.num_qubits() {
+            svg.line(wires_start, y, wires_end, y);
+        } else {
+            svg.double_line(wires_start, y, wires_end, y);
+        }
+    }
+    let mut x = wires_start + COLUMN_GAP;
+    for (col, width) in layout.columns.iter().zip(widths) {
+        for op in col {
+            svg.operation(op, x + width / 2.0);
+        }
+        x += width + COLUMN_GAP;
+    }
+    svg.out.push_str("</svg>\n");
+    svg.out
+}
+
+/// Draws a program as a standalone SVG image.
+pub fn to_svg(program: &Program) -> LowerResult<String> {
+    Ok(layout_svg(&draw::layout(program)?))
+}
+
+/// Escapes the characters of identifiers and numbers which are special in TeX, i.e. `_`.
+fn tex_escape(s: &str) -> String {
+    s.replace('_', "\\_")
+}
+
+fn tex_label(name: &str, params: &[Expr]) -> String {
+    let name = tex_escape(&name.to_uppercase());
+    let name = if name.len() == 1 { name } else { format!("\\mathrm{{{}}}", name) };
+    if params.is_empty() {
+        name
+    } else {
+        let params: Vec<String> = params.iter().map(|p| tex_escape(&param(p, "\\pi"))).collect();
+        format!("{}({})", name, params.join(", "))
+    }
+}
+
+/// `q[0]` as `q_{0}`.
+fn tex_wire_label(label: &str) -> String {
+    match label.find('[') {
+        Some(i) => format!("{}_{{{}}}", tex_escape(&label[..i]), label[i + 1..].trim_end_matches(']')),
+        None => tex_escape(label),
+    }
+}
+
+/// Writes the cells of an operation into the grid of a column.
+fn tex_operation(op: &Operation, cells: &mut [String], num_qubits: usize) {
+    let nq = num_qubits;
+    let targets = op.target_qubits();
+    let target_top = *targets.iter().min().unwrap();
+    let mut quantum = targets.clone();
+    quantum.extend(op.controls.iter().cloned());
+    let bottom = *quantum.iter().max().unwrap();
+
+    match op.target {
+        _ if is_cnot_target(op) => cells[targets[0]] = "\\targ{}".to_owned(),
+        Target::Gate(ref name, ref params, ref qubits) => {
+            let hi = *qubits.iter().max().unwrap();
+            cells[target_top] = if qubits.len() > 1 {
+                format!("\\gate[wires={}]{{{}}}", hi - target_top + 1, tex_label(name, params))
+            } else {
+                format!("\\gate{{{}}}", tex_label(name, params))
+            };
+        }
+        Target::Measure(q, c) => cells[q] = format!("\\meter{{}} \\vcw{{{}}}", nq + c - q),
+        Target::Reset(q) => cells[q] = "\\gate{\\ket{0}}".to_owned(),
+        Target::Dot(q) => cells[q] = "\\control{}".to_owned(),
+        Target::Swap(a, b) => {
+            cells[a] = format!("\\swap{{{}}}", b as isize - a as isize);
+            cells[b] = "\\targX{}".to_owned();
+        }
+        Target::Barrier(_) => cells[target_top] = "\\qw \\slice{}".to_owned(),
+    }
+    for &c in &op.controls {
+        let target = if c < target_top { target_top } else { *targets.iter().max().unwrap() };
+        cells[c] = format!("\\ctrl{{{}}}", target as isize - c as isize);
+    }
+    if let Some((ref clbits, value)) = op.condition {
+        cells[bottom].push_str(&format!(" \\vcw{{{}}}", nq + clbits.end - 1 - bottom));
+        for (i, c) in clbits.clone().enumerate() {
+            let marker = if value >> i & 1 == 1 { "\\control{}" } else { "\\ocontrol{}" };
+            cells[nq + c] = format!("{} \\cw", marker);
+        }
+    }
+}
+
+/// Writes a program as a `quantikz` environment.
+pub fn to_quantikz(program: &Program) -> LowerResult<String> {
+    let layout = draw::layout(program)?;
+    let nq = layout.num_qubits();
+    let empty = |w: usize| if w < nq { "\\qw" } else { "\\cw" }.to_owned();
+    let mut rows: Vec<Vec<String>> = layout.qubit_labels.iter().chain(layout.clbit_labels.iter())
+        .map(|l| vec![format!("\\lstick{{${}$}}", tex_wire_label(l))]).collect();
+    for col in &layout.columns {
+        let mut cells: Vec<String> = (0..layout.num_wires()).map(empty).collect();
+        for op in col {
+            tex_operation(op, &mut cells, nq);
+        }
+        for (row, cell) in rows.iter_mut().zip(cells) {
+            row.push(cell);
+        }
+    }
+    let mut out = "\\begin{quantikz}\n".to_owned();
+    let last = rows.len().saturating_sub(1);
+    for (w, mut row) in rows.into_iter().enumerate() {
+        row.push(empty(w));
+        out.push_str(&row.join(" & "));
+        out.push_str(if w < last { " \\\\\n" } else { "\n" });
+    }
+    out.push_str("\\end{quantikz}\n");
+    Ok(out)
+}
+
+/// Writes a program as a standalone LaTeX document with a `quantikz` figure.
+pub fn to_latex(program: &Program) -> LowerResult<String> {
+    Ok(format!("\\documentclass[border=2pt]{{standalone}}\n\\usepackage{{tikz}}\n\\usetikzlibrary{{quantikz}}\n\
+                \\begin{{document}}\n{}\\end{{document}}\n", to_quantikz(program)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{expr, parse};
+
+    #[test]
+    fn pi_multiples() {
+        let p = |s: &str| param(&expr(s), "π");
+        assert_eq!(p("pi/2"), "π/2");
+        assert_eq!(p("-3*pi/4"), "-3π/4");
+        assert_eq!(p("2*pi"), "2π");
+        assert_eq!(p("0.7853981633974483"), "π/4");
+        assert_eq!(p("0.1"), "0.1");
+        assert_eq!(p("pi/4 + 0.1"), "0.8854");
+        assert_eq!(p("0"), "0");
+        assert_eq!(p("theta"), "theta");
+    }
+
+    #[test]
+    fn quantikz_source() {
+        let program = parse("include \"qelib1.inc\";\nqreg q[3];\ncreg c[1];\ngate g a, b { cx a, b; }\n\
+                             h q[0];\ncx q[0], q[2];\nrz(pi/4) q[1];\ng q[1], q[2];\nmeasure q[0] -> c[0];\n\
+                             if (c == 1) x q[1];");
+        let expected = "\\begin{quantikz}\n\
+                        \\lstick{$q_{0}$} & \\gate{H} & \\ctrl{2} & \\qw & \\qw & \\meter{} \\vcw{3} & \\qw & \\qw \\\\\n\
+                        \\lstick{$q_{1}$} & \\qw & \\qw & \\gate{\\mathrm{RZ}(\\pi/4)} & \\gate[wires=2]{G} & \\qw & \
+                        \\gate{X} \\vcw{2} & \\qw \\\\\n\
+                        \\lstick{$q_{2}$} & \\qw & \\targ{} & \\qw & \\qw & \\qw & \\qw & \\qw \\\\\n\
+                        \\lstick{$c_{0}$} & \\cw & \\cw & \\cw & \\cw & \\cw & \\control{} \\cw & \\cw\n\
+                        \\end{quantikz}\n";
+        assert_eq!(to_quantikz(&program).unwrap(), expected);
+        let doc = to_latex(&program).unwrap();
+        assert!(doc.starts_with("\\documentclass[border=2pt]{standalone}\n"));
+        assert!(doc.contains(expected));
+        let program = parse("qreg q_a[1];\ngate my_gate(phase_1) a { U(0, 0, phase_1) a; }\nmy_gate(the_angle) q_a[0];");
+        assert!(to_quantikz(&program).unwrap().contains("\\lstick{$q\\_a_{0}$} & \\gate{\\mathrm{MY\\_GATE}(the\\_angle)}"));
+    }
+
+    #[test]
+    fn svg_image() {
+        let program = parse("qreg q[2];\ncreg c[2];\nu1(pi/2) q[0];\nCX q[0], q[1];\nbarrier q;\n\
+                             measure q[1] -> c[1];\nif (c == 2) reset q[0];");
+        let svg = to_svg(&program).unwrap();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        for label in &[">q[0]</text>", ">c[1]</text>", ">U1(π/2)</text>", ">|0⟩</text>"] {
+            assert!(svg.contains(label), "{}", label);
+        }
+        // The CX target is a circled plus, the condition has an open and a filled marker.
+        assert!(svg.contains("r=\"10\" fill=\"white\""));
+        assert!(svg.contains("stroke-dasharray"));
+        assert_eq!(svg.matches("<polygon").count(), 1);
+        assert_eq!(svg.matches("r=\"4\" fill=\"white\"").count(), 1);
+        assert_eq!(svg.matches("r=\"4\" fill=\"black\"").count(), 2);
+    }
+}
@@ -0,0 +1,296 @@
+//! Source formatter working on the token stream, so comments stay where they are and the
+//! formatted code has exactly the tokens of the original.
+//!
+//! Every statement is put on its own line, gate bodies are indented, and spaces are normalized:
+//! one space after `,`, around `->` and `==`, and around binary operators as configured. Comments
+//! after code on the same line stay there; blank lines between statements are kept up to a
+//! maximum.
+
+use std::path::Path;
+use std::rc::Rc;
+
+use errors::TokenizeError;
+use lexer;
+use tokens::{Token, TokenType};
+
+/// Spacing around binary operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperatorSpacing {
+    /// No spaces, e.g. `a+b*c^2`.
+    None,
+    /// Spaces around `+` and `-` only, e.g. `a + b*c^2`. This is the style of `printer`.
+    Additive,
+    /// Spaces around `+`, `-`, `*` and `/`, e.g. `a + b * c^2`.
+    All,
+}
+
+/// Style options of the formatter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    /// Number of spaces to indent gate bodies with.
+    pub indent: usize,
+    pub operator_spacing: OperatorSpacing,
+    /// Whether the `{` of a gate body goes on a line of its own.
+    pub brace_on_new_line: bool,
+    /// The largest number of consecutive blank lines kept.
+    pub max_blank_lines: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            indent: 2,
+            operator_spacing: OperatorSpacing::Additive,
+            brace_on_new_line: false,
+            max_blank_lines: 1,
+        }
+    }
+}
+
+/// The source range of a token, including the `//` of comments and the quotes of strings.
+fn span(tok: &Token) -> (usize, usize) {
+    match tok.token {
+        TokenType::Comment(_) => (tok.pos - 2, tok.pos + tok.len),
+        TokenType::StringLiteral(_) => (tok.pos - 1, tok.pos + tok.len + 1),
+        _ => (tok.pos, tok.pos + tok.len),
+    }
+}
+
+fn is_operator(t: &TokenType) -> bool {
+    matches!(*t, TokenType::Plus | TokenType::Minus | TokenType::Times | TokenType::Devide | TokenType::Power)
+}
+
+struct Formatter<'a> {
+    code: &'a str,
+    options: &'a FormatOptions,
+    lines: Vec<String>,
+    line: String,
+    depth: usize,
+    prev: Option<TokenType>,
+    /// Whether the previous token is a unary minus.
+    prev_unary: bool,
+    /// The end of the previous token in the source.
+    prev_end: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn spaced(&self, op: &TokenType) -> bool {
+        match *op {
+            TokenType::Plus | TokenType::Minus => self.options.operator_spacing != OperatorSpacing::None,
+            TokenType::Times | TokenType::Devide => self.options.operator_spacing == OperatorSpacing::All,
+            _ => false,
+        }
+    }
+
+    /// Whether a `-` after the previous token is a unary minus.
+    fn starts_operand(&self) -> bool {
+        match self.prev {
+            None => true,
+            Some(ref t) => self.prev_unary || is_operator(t) ||
+                matches!(*t, TokenType::LParen | TokenType::Comma),
+        }
+    }
+
+    fn space_before(&self, cur: &TokenType, unary: bool) -> bool {
+        let prev = match self.prev {
+            Some(ref prev) => prev,
+            None => return false,
+        };
+        if self.prev_unary {
+            return false;
+        }
+        if is_operator(prev) {
+            return self.spaced(prev);
+        }
+        if is_operator(cur) && !unary {
+            return self.spaced(cur);
+        }
+        match (prev, cur) {
+            (_, &TokenType::Comma) | (_, &TokenType::Semicolon) | (_, &TokenType::RParen) |
+            (_, &TokenType::LSqBracket) | (_, &TokenType::RSqBracket) |
+            (&TokenType::LParen, _) | (&TokenType::LSqBracket, _) => false,
+            (&TokenType::If, &TokenType::LParen) => true,
+            (_, &TokenType::LParen) => false,
+            _ => true,
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            let indent = " ".repeat(self.depth * self.options.indent);
+            let line = ::std::mem::take(&mut self.line);
+            self.lines.push(indent + &line);
+        }
+    }
+
+    fn push_line(&mut self, text: &str) {
+        self.end_line();
+        self.line = text.to_owned();
+        self.end_line();
+    }
+
+    /// Keeps blank lines of the source before a token which starts a line.
+    fn blank_lines(&mut self, start: usize, cur: &TokenType) {
+        if !self.line.is_empty() || self.lines.is_empty() || *cur == TokenType::RBrace {
+            return;
+        }
+        if self.lines.last().map(|l| l.ends_with('{')).unwrap_or(false) {
+            return;
+        }
+        let newlines = self.code[self.prev_end..start].matches('\n').count();
+        for _ in 0..newlines.saturating_sub(1).min(self.options.max_blank_lines) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn token(&mut self, tok: &Token) {
+        let (start, end) = span(tok);
+        match tok.token {
+            TokenType::Comment(ref text) => {
+                let text = String::from_utf8_lossy(text);
+                let comment = format!("//{}", text.trim_end());
+                let same_line = self.prev.is_some() && !self.code[self.prev_end..start].contains('\n');
+                if same_line && !self.line.is_empty() {
+                    self.line.push(' ');
+                    self.line.push_str(&comment);
+                    self.end_line();
+                } else if same_line && !self.lines.is_empty() {
+                    self.lines.last_mut().unwrap().push_str(&format!(" {}", comment));
+                } else {
+                    self.blank_lines(start, &tok.token);
+                    self.push_line(&comment);
+                }
+                // A comment ends the line, so the next token never joins it.
+                self.prev_end = end;
+                return;
+            }
+            TokenType::LBrace => {
+                if self.options.brace_on_new_line {
+                    self.push_line("{");
+                } else {
+                    self.line.push_str(" {");
+                    self.end_line();
+                }
+                self.depth += 1;
+            }
+            TokenType::RBrace => {
+                self.end_line();
+                self.depth = self.depth.saturating_sub(1);
+                self.push_line("}");
+            }
+            _ => {
+                self.blank_lines(start, &tok.token);
+                let unary = tok.token == TokenType::Minus && self.starts_operand();
+                if !self.line.is_empty() && self.space_before(&tok.token, unary) {
+                    self.line.push(' ');
+                }
+                match tok.token {
+                    TokenType::StringLiteral(ref s) => self.line.push_str(&format!("\"{}\"", s)),
+                    _ => self.line.push_str(&self.code[start..end]),
+                }
+                if tok.token == TokenType::Semicolon {
+                    self.end_line();
+                }
+                self.prev_unary = unary;
+            }
+        }
+        self.prev = Some(tok.token.clone());
+        self.prev_end = end;
+    }
+}
+
+/// Formats OpenQASM source code.
+pub fn format_str(code: &str, filename: &Path, options: &FormatOptions) -> Result<String, TokenizeError> {
+    let mut f = Formatter {
+        code,
+        options,
+        lines: vec![],
+        line: String::new(),
+        depth: 0,
+        prev: None,
+        prev_unary: false,
+        prev_end: 0,
+    };
+    let filename: Rc<Path> = filename.into();
+    for tok in lexer::tokenize(code.into(), filename) {
+        f.token(&tok?);
+    }
+    f.end_line();
+    let mut out = f.lines.join("\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Whether the code is already formatted.
+pub fn is_formatted(code: &str, filename: &Path, options: &FormatOptions) -> Result<bool, TokenizeError> {
+    Ok(format_str(code, filename, options)? == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(code: &str, options: &FormatOptions) -> String {
+        format_str(code, Path::new("test.qasm"), options).unwrap()
+    }
+
+    const MESSY: &str = "OPENQASM   2.0 ;include\"qelib1.inc\";\n\
+                         // registers\n\
+                         qreg q [ 2 ] ;creg c[2];  // two bits\n\
+                         \n\n\n\
+                         gate g ( theta,phi ) a,b{U( -theta+phi *2 , -(-phi)^2,sin( pi/2 ) )a;\n\
+                         // entangle\n\
+                         CX a,b;}\n\
+                         g(1.0,0.25)q[0],q[1];measure q->c;if(c==1)reset q[0];";
+
+    #[test]
+    fn format_default_style() {
+        let expected = "OPENQASM 2.0;\n\
+                        include \"qelib1.inc\";\n\
+                        // registers\n\
+                        qreg q[2];\n\
+                        creg c[2]; // two bits\n\
+                        \n\
+                        gate g(theta, phi) a, b {\n\
+                        \x20 U(-theta + phi*2, -(-phi)^2, sin(pi/2)) a;\n\
+                        \x20 // entangle\n\
+                        \x20 CX a, b;\n\
+                        }\n\
+                        g(1.0, 0.25) q[0], q[1];\n\
+                        measure q -> c;\n\
+                        if (c == 1) reset q[0];\n";
+        let options = FormatOptions::default();
+        assert_eq!(format(MESSY, &options), expected);
+        assert!(is_formatted(expected, Path::new("test.qasm"), &options).unwrap());
+        assert!(!is_formatted(MESSY, Path::new("test.qasm"), &options).unwrap());
+    }
+
+    #[test]
+    fn format_custom_style() {
+        let options = FormatOptions {
+            indent: 4,
+            operator_spacing: OperatorSpacing::All,
+            brace_on_new_line: true,
+            max_blank_lines: 0,
+        };
+        let formatted = format(MESSY, &options);
+        assert!(formatted.contains("creg c[2]; // two bits\ngate g(theta, phi) a, b\n{\n    U(-theta + phi * 2, "));
+        assert_eq!(format(&formatted, &options), formatted);
+        let options = FormatOptions { operator_spacing: OperatorSpacing::None, ..FormatOptions::default() };
+        assert!(format(MESSY, &options).contains("U(-theta+phi*2, -(-phi)^2, sin(pi/2)) a;"));
+    }
+
+    #[test]
+    fn keeps_tokens() {
+        let options = FormatOptions::default();
+        let formatted = format(MESSY, &options);
+        let types = |code: &str| -> Vec<TokenType> {
+            lexer::tokenize(code.into(), Path::new("test.qasm").into()).map(|t| t.unwrap().token).collect()
+        };
+        assert_eq!(types(&formatted), types(MESSY));
+        assert_eq!(format(&formatted, &options), formatted);
+        assert!(format_str("qreg q[1]; $", Path::new("test.qasm"), &options).is_err());
+    }
+}
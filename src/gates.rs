@@ -0,0 +1,154 @@
+//! Matrices of the builtin gates and of the gates in `qelib1.inc`.
+//!
+//! The matrices follow the definitions in `qelib1.inc` exactly, including global phase, so a
+//! simulator may use them instead of inlining the definitions. For example `rz(phi)` is defined
+//! as `u1(phi)`, i.e. `diag(1, e^(i phi))`.
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use complex::{Complex, I, ONE, ZERO};
+
+pub type Matrix2 = [[Complex; 2]; 2];
+
+/// The action of a gate, acting on the last one or two of its arguments and controlled by the
+/// other arguments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GateMatrix {
+    /// A single-qubit unitary on the last argument.
+    Unitary { controls: usize, matrix: Matrix2 },
+    /// A swap of the last two arguments.
+    Swap { controls: usize },
+}
+
+impl GateMatrix {
+    pub fn num_qubits(&self) -> usize {
+        match *self {
+            GateMatrix::Unitary { controls, .. } => controls + 1,
+            GateMatrix::Swap { controls } => controls + 2,
+        }
+    }
+}
+
+pub const IDENTITY: Matrix2 = [[ONE, ZERO], [ZERO, ONE]];
+
+fn c(re: f64, im: f64) -> Complex {
+    Complex::new(re, im)
+}
+
+/// The matrix of `U(theta, phi, lambda)`.
+pub fn u_matrix(theta: f64, phi: f64, lambda: f64) -> Matrix2 {
+    let (cos, sin) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+    [[Complex::real(cos), -Complex::expi(lambda).scale(sin)],
+     [Complex::expi(phi).scale(sin), Complex::expi(phi + lambda).scale(cos)]]
+}
+
+pub fn phase_matrix(lambda: f64) -> Matrix2 {
+    [[ONE, ZERO], [ZERO, Complex::expi(lambda)]]
+}
+
+pub fn matmul(a: &Matrix2, b: &Matrix2) -> Matrix2 {
+    let mut m = [[ZERO; 2]; 2];
+    for i in 0..2 {
+        for j in 0..2 {
+            m[i][j] = a[i][0] * b[0][j] + a[i][1] * b[1][j];
+        }
+    }
+    m
+}
+
+pub fn dagger(a: &Matrix2) -> Matrix2 {
+    [[a[0][0].conj(), a[1][0].conj()], [a[0][1].conj(), a[1][1].conj()]]
+}
+
+/// Wraps an angle into `(-pi, pi]`.
+pub fn wrap(angle: f64) -> f64 {
+    let a = angle % (2.0 * PI);
+    if a > PI {
+        a - 2.0 * PI
+    } else if a <= -PI {
+        a + 2.0 * PI
+    } else {
+        a
+    }
+}
+
+/// The angles `(theta, phi, lambda)` of a unitary such that it equals `U(theta, phi, lambda)`
+/// up to a global phase (the ZYZ decomposition). `theta` is in `[0, pi]`, the others in
+/// `(-pi, pi]`.
+pub fn u_angles(m: &Matrix2) -> (f64, f64, f64) {
+    const EPS: f64 = 1e-12;
+    let theta = 2.0 * m[1][0].abs().atan2(m[0][0].abs());
+    // The entries are e^(i alpha) times those of `u_matrix`, where alpha is the global phase.
+    let (phi, lambda) = if m[0][0].abs() > EPS {
+        let alpha = m[0][0].arg();
+        let phi = if m[1][0].abs() > EPS { m[1][0].arg() - alpha } else { 0.0 };
+        (phi, m[1][1].arg() - alpha - phi)
+    } else {
+        // theta is pi and only the difference of alpha and phi matters.
+        (0.0, (-m[0][1]).arg() - m[1][0].arg())
+    };
+    (theta, wrap(phi), wrap(lambda))
+}
+
+/// The matrix of a single-qubit gate from `qelib1.inc` or `U`.
+fn single_qubit(name: &str, p: &[f64]) -> Option<Matrix2> {
+    let h = FRAC_1_SQRT_2;
+    Some(match (name, p.len()) {
+        ("U", 3) | ("u3", 3) | ("u", 3) => u_matrix(p[0], p[1], p[2]),
+        ("u2", 2) => u_matrix(PI / 2.0, p[0], p[1]),
+        ("u1", 1) | ("p", 1) | ("rz", 1) => phase_matrix(p[0]),
+        ("id", 0) | ("u0", 1) => IDENTITY,
+        ("x", 0) => [[ZERO, ONE], [ONE, ZERO]],
+        ("y", 0) => [[ZERO, -I], [I, ZERO]],
+        ("z", 0) => [[ONE, ZERO], [ZERO, -ONE]],
+        ("h", 0) => [[c(h, 0.0), c(h, 0.0)], [c(h, 0.0), c(-h, 0.0)]],
+        ("s", 0) => [[ONE, ZERO], [ZERO, I]],
+        ("sdg", 0) => [[ONE, ZERO], [ZERO, -I]],
+        ("t", 0) => phase_matrix(PI / 4.0),
+        ("tdg", 0) => phase_matrix(-PI / 4.0),
+        ("rx", 1) => u_matrix(p[0], -PI / 2.0, PI / 2.0),
+        ("ry", 1) => u_matrix(p[0], 0.0, 0.0),
+        ("sx", 0) => [[c(h, 0.0), c(0.0, -h)], [c(0.0, -h), c(h, 0.0)]],
+        ("sxdg", 0) => [[c(h, 0.0), c(0.0, h)], [c(0.0, h), c(h, 0.0)]],
+        _ => return None,
+    })
+}
+
+/// The target matrix of a controlled gate from `qelib1.inc`.
+fn controlled_target(name: &str, p: &[f64]) -> Option<(usize, Matrix2)> {
+    Some(match (name, p.len()) {
+        ("CX", 0) | ("cx", 0) => (1, single_qubit("x", &[])?),
+        ("cy", 0) => (1, single_qubit("y", &[])?),
+        ("cz", 0) => (1, single_qubit("z", &[])?),
+        ("ch", 0) => (1, single_qubit("h", &[])?),
+        ("crx", 1) => (1, single_qubit("rx", p)?),
+        ("cry", 1) => (1, single_qubit("ry", p)?),
+        ("crz", 1) => (1, [[Complex::expi(-p[0] / 2.0), ZERO], [ZERO, Complex::expi(p[0] / 2.0)]]),
+        ("cu1", 1) | ("cp", 1) => (1, phase_matrix(p[0])),
+        ("cu3", 3) => (1, u_matrix(p[0], p[1], p[2])),
+        ("cu", 4) => {
+            let m = u_matrix(p[0], p[1], p[2]);
+            let g = Complex::expi(p[3]);
+            (1, [[g * m[0][0], g * m[0][1]], [g * m[1][0], g * m[1][1]]])
+        }
+        ("csx", 0) => (1, [[c(0.5, 0.5), c(0.5, -0.5)], [c(0.5, -0.5), c(0.5, 0.5)]]),
+        ("ccx", 0) => (2, single_qubit("x", &[])?),
+        _ => return None,
+    })
+}
+
+/// The action of `U`, `CX` or a gate from `qelib1.inc` with evaluated parameters. Returns `None`
+/// for gates which have no direct matrix here (e.g. `rzz`, `c3x`) and must be inlined.
+pub fn standard_gate(name: &str, params: &[f64]) -> Option<GateMatrix> {
+    if let Some(matrix) = single_qubit(name, params) {
+        return Some(GateMatrix::Unitary { controls: 0, matrix });
+    }
+    if let Some((controls, matrix)) = controlled_target(name, params) {
+        return Some(GateMatrix::Unitary { controls, matrix });
+    }
+    match (name, params.len()) {
+        ("swap", 0) => Some(GateMatrix::Swap { controls: 0 }),
+        ("cswap", 0) => Some(GateMatrix::Swap { controls: 1 }),
+        _ => None,
+    }
+}
@@ -0,0 +1,230 @@
+//! Symbolic differentiation of parameter expressions and parameter-shift gradients.
+//!
+//! The parameter-shift rule gives the exact derivative of an expectation value with respect to
+//! the angle `g` of a gate `exp(-i g/2 P)`, where `P` squares to identity:
+//! `d<O>/dg = (<O>(g + pi/2) - <O>(g - pi/2)) / 2`. When `g` is an expression of a parameter
+//! `theta`, the chain rule multiplies each term by `dg/dtheta`, which is computed symbolically.
+
+use std::collections::HashMap;
+
+use angle::Angle;
+use errors::CircuitError;
+use params;
+use parser::{Expr, Program, Statement, UnaryOp};
+
+fn boxed(e: Expr) -> Box<Expr> {
+    Box::new(e)
+}
+
+impl Expr {
+    /// Whether the expression contains the identifier `var`.
+    pub fn depends_on(&self, var: &str) -> bool {
+        match *self {
+            Expr::Real(_) | Expr::NnInteger(_) | Expr::Pi => false,
+            Expr::Id(ref name) => name == var,
+            Expr::Add(ref lhs, ref rhs) | Expr::Sub(ref lhs, ref rhs) | Expr::Mul(ref lhs, ref rhs) |
+            Expr::Div(ref lhs, ref rhs) | Expr::Pow(ref lhs, ref rhs) => lhs.depends_on(var) || rhs.depends_on(var),
+            Expr::Neg(ref v) | Expr::UnaryOp(_, ref v) => v.depends_on(var),
+        }
+    }
+
+    /// The derivative with respect to the identifier `var`, simplified.
+    pub fn derivative(&self, var: &str) -> Expr {
+        self.derivative_rec(var).simplify()
+    }
+
+    fn derivative_rec(&self, var: &str) -> Expr {
+        if !self.depends_on(var) {
+            return Expr::NnInteger(0);
+        }
+        let d = |e: &Expr| e.derivative_rec(var);
+        match *self {
+            Expr::Id(_) => Expr::NnInteger(1),
+            Expr::Add(ref u, ref v) => Expr::Add(boxed(d(u)), boxed(d(v))),
+            Expr::Sub(ref u, ref v) => Expr::Sub(boxed(d(u)), boxed(d(v))),
+            Expr::Mul(ref u, ref v) => Expr::Add(boxed(Expr::Mul(boxed(d(u)), v.clone())),
+                                                 boxed(Expr::Mul(u.clone(), boxed(d(v))))),
+            Expr::Div(ref u, ref v) => {
+                let numer = Expr::Sub(boxed(Expr::Mul(boxed(d(u)), v.clone())),
+                                      boxed(Expr::Mul(u.clone(), boxed(d(v)))));
+                Expr::Div(boxed(numer), boxed(Expr::Pow(v.clone(), boxed(Expr::NnInteger(2)))))
+            }
+            Expr::Neg(ref u) => Expr::Neg(boxed(d(u))),
+            Expr::Pow(ref u, ref v) if !v.depends_on(var) => {
+                // v * u^(v-1) * u'
+                let exp = Expr::Sub(v.clone(), boxed(Expr::NnInteger(1)));
+                Expr::Mul(boxed(Expr::Mul(v.clone(), boxed(Expr::Pow(u.clone(), boxed(exp))))), boxed(d(u)))
+            }
+            Expr::Pow(ref u, ref v) => {
+                // u^v * (v' * ln(u) + v * u' / u)
+                let ln_u = Expr::UnaryOp(UnaryOp::Ln, u.clone());
+                let inner = Expr::Add(boxed(Expr::Mul(boxed(d(v)), boxed(ln_u))),
+                                      boxed(Expr::Div(boxed(Expr::Mul(v.clone(), boxed(d(u)))), u.clone())));
+                Expr::Mul(boxed(self.clone()), boxed(inner))
+            }
+            Expr::UnaryOp(op, ref u) => {
+                let outer = match op {
+                    UnaryOp::Sin => Expr::UnaryOp(UnaryOp::Cos, u.clone()),
+                    UnaryOp::Cos => Expr::Neg(boxed(Expr::UnaryOp(UnaryOp::Sin, u.clone()))),
+                    UnaryOp::Tan => Expr::Div(boxed(Expr::NnInteger(1)),
+                                              boxed(Expr::Pow(boxed(Expr::UnaryOp(UnaryOp::Cos, u.clone())),
+                                                              boxed(Expr::NnInteger(2))))),
+                    UnaryOp::Exp => self.clone(),
+                    UnaryOp::Ln => Expr::Div(boxed(Expr::NnInteger(1)), u.clone()),
+                    UnaryOp::Sqrt => Expr::Div(boxed(Expr::NnInteger(1)),
+                                               boxed(Expr::Mul(boxed(Expr::NnInteger(2)), boxed(self.clone())))),
+                };
+                Expr::Mul(boxed(outer), boxed(d(u)))
+            }
+            Expr::Real(_) | Expr::NnInteger(_) | Expr::Pi => unreachable!(),
+        }
+    }
+}
+
+/// Gates all of whose parameters enter as `exp(-i g/2 P)` with `P^2 = I`, up to global phase.
+const SHIFTABLE_GATES: &[&str] = &["U", "u3", "u2", "u1", "u", "p", "rx", "ry", "rz", "rxx", "rzz"];
+
+/// One circuit of a parameter-shift rule. The derivative is the sum of `coefficient` times the
+/// expectation value measured on `program`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShiftTerm {
+    pub coefficient: f64,
+    pub program: Program,
+}
+
+/// The circuits needed to estimate the partial derivative with respect to `param`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterShift {
+    pub param: String,
+    pub terms: Vec<ShiftTerm>,
+}
+
+/// Position of a gate parameter: statement index, index in the body of `if`, parameter index.
+type Location = (usize, Option<usize>, usize);
+
+fn apply_at(program: &mut Program, loc: Location) -> &mut Statement {
+    let stmt = &mut program[loc.0];
+    match (stmt, loc.1) {
+        (&mut Statement::If(_, _, ref mut body), Some(j)) => &mut body[j],
+        (stmt, _) => stmt,
+    }
+}
+
+fn collect_locations(program: &Program) -> Vec<(Location, &str, &Expr)> {
+    let mut locs = vec![];
+    for (i, stmt) in program.iter().enumerate() {
+        match *stmt {
+            Statement::Apply(ref name, ref params, _) => {
+                for (k, p) in params.iter().enumerate() {
+                    locs.push(((i, None, k), name.as_str(), p));
+                }
+            }
+            Statement::If(_, _, ref body) => {
+                for (j, stmt) in body.iter().enumerate() {
+                    if let Statement::Apply(ref name, ref params, _) = *stmt {
+                        for (k, p) in params.iter().enumerate() {
+                            locs.push(((i, Some(j), k), name.as_str(), p));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    locs
+}
+
+/// Generates the shifted circuits for the partial derivative with respect to `param` at the
+/// point `values`. The circuits have all parameters in `values` substituted.
+///
+/// Gates in `SHIFTABLE_GATES` are supported; other gates using `param`, including user-defined
+/// gates, are rejected and should be expanded first.
+pub fn parameter_shift(program: &Program, values: &HashMap<String, f64>, param: &str)
+                       -> Result<ParameterShift, CircuitError> {
+    if !values.contains_key(param) {
+        return Err(CircuitError::new(format!("No value is given for parameter '{}'.", param)));
+    }
+    let bound = params::bind(program, values)?;
+    let mut terms = vec![];
+    for (loc, name, expr) in collect_locations(program) {
+        if !expr.depends_on(param) {
+            continue;
+        }
+        if !SHIFTABLE_GATES.contains(&name) {
+            return Err(CircuitError::new(format!("The parameter-shift rule does not apply to '{}'.", name)));
+        }
+        let slope = match expr.derivative(param).eval_with(values) {
+            Some(v) => v,
+            None => return Err(CircuitError::new(format!("Parameter values are missing in '{}'.", name))),
+        };
+        if slope == 0.0 {
+            continue;
+        }
+        for &(sign, shift) in &[(1.0, 1), (-1.0, -1)] {
+            let mut shifted = bound.clone();
+            if let Statement::Apply(_, ref mut params, _) = *apply_at(&mut shifted, loc) {
+                let by = Angle::new(shift, 2).to_expr();
+                params[loc.2] = Expr::Add(boxed(params[loc.2].clone()), boxed(by)).simplify();
+            }
+            terms.push(ShiftTerm { coefficient: sign * slope / 2.0, program: shifted });
+        }
+    }
+    Ok(ParameterShift { param: param.to_owned(), terms })
+}
+
+/// Generates the parameter-shift circuits for every parameter in `values`, sorted by name.
+pub fn gradient_circuits(program: &Program, values: &HashMap<String, f64>)
+                         -> Result<Vec<ParameterShift>, CircuitError> {
+    let mut names: Vec<&String> = values.keys().collect();
+    names.sort();
+    names.into_iter().map(|name| parameter_shift(program, values, name)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{expr, parse};
+
+    #[test]
+    fn symbolic_derivative() {
+        assert_eq!(expr("2*x + pi").derivative("x"), expr("2"));
+        assert_eq!(expr("sin(x)").derivative("x"), expr("cos(x)"));
+        assert_eq!(expr("x^3").derivative("x"), expr("3*x^2"));
+        assert_eq!(expr("y * x").derivative("z"), expr("0"));
+    }
+
+    #[test]
+    fn derivative_matches_finite_difference() {
+        let h = 1e-6;
+        for s in &["sin(x) * cos(2*x)", "tan(x / 2) - exp(-x)", "ln(x + 3) / sqrt(x)", "x ^ x",
+                   "-(x - pi)^2 / (1 + x)", "2 ^ sin(x)"] {
+            let e = expr(s);
+            let at = |v: f64| {
+                let mut values = HashMap::new();
+                values.insert("x".to_owned(), v);
+                values
+            };
+            let numeric = (e.eval_with(&at(0.8 + h)).unwrap() - e.eval_with(&at(0.8 - h)).unwrap()) / (2.0 * h);
+            let symbolic = e.derivative("x").eval_with(&at(0.8)).unwrap();
+            assert!((numeric - symbolic).abs() < 1e-6, "{}: {} != {}", s, numeric, symbolic);
+        }
+    }
+
+    #[test]
+    fn shifted_circuits() {
+        let prog = parse("qreg q[1]; rx(2*theta) q[0]; rz(theta + phi) q[0]; u1(phi) q[0];");
+        let mut values = HashMap::new();
+        values.insert("theta".to_owned(), 0.5);
+        values.insert("phi".to_owned(), 0.25);
+        let grads = gradient_circuits(&prog, &values).unwrap();
+        assert_eq!(grads[0].param, "phi");
+        assert_eq!(grads[1].param, "theta");
+        let coeffs: Vec<f64> = grads[1].terms.iter().map(|t| t.coefficient).collect();
+        assert_eq!(coeffs, vec![1.0, -1.0, 0.5, -0.5]);
+        let expected = parse("qreg q[1]; rx(pi/2 + 1) q[0]; rz(0.75) q[0]; u1(0.25) q[0];");
+        assert_eq!(grads[1].terms[0].program, expected);
+
+        let prog = parse("qreg q[2]; crz(theta) q[0], q[1];");
+        assert!(parameter_shift(&prog, &values, "theta").is_err());
+    }
+}
@@ -0,0 +1,317 @@
+//! Gate declarations and inlining of gate applications.
+//!
+//! `include "qelib1.inc";` is resolved to the standard library shipped with this crate. Other
+//! included files are read from the file system, relative to the directory of the including
+//! file if it is known (see `GateTable::from_source`) and to the working directory otherwise.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use errors::CircuitError;
+use lower;
+use parser::{self, Expr, GateName, Params, Program, QArgs, QReg, Statement};
+
+/// The source of the standard gate library.
+pub const QELIB1: &str = include_str!("qelib1.inc");
+
+pub const QELIB1_FILENAME: &str = "qelib1.inc";
+
+pub type InlineResult<T> = Result<T, CircuitError>;
+
+/// A declared gate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GateDef {
+    /// Parameter names.
+    pub params: Vec<String>,
+    /// Qubit argument names.
+    pub qargs: Vec<String>,
+    /// `None` for opaque gates.
+    pub body: Option<Program>,
+    /// Whether the gate comes from `qelib1.inc`.
+    pub standard: bool,
+}
+
+/// All gates declared in a program and in the files it includes.
+#[derive(Debug, Clone, Default)]
+pub struct GateTable {
+    gates: HashMap<GateName, GateDef>,
+}
+
+fn param_names(params: &Params) -> InlineResult<Vec<String>> {
+    params.iter().map(|p| match *p {
+        Expr::Id(ref name) => Ok(name.clone()),
+        _ => Err(CircuitError::new("Gate parameters must be identifiers.".to_owned())),
+    }).collect()
+}
+
+fn qarg_names(qargs: &QArgs) -> Vec<String> {
+    qargs.iter().map(|q| q.name().to_owned()).collect()
+}
+
+fn check_arity(name: &str, nparams: usize, nqargs: usize, params: &Params, qargs: &QArgs) -> InlineResult<()> {
+    if params.len() != nparams || qargs.len() != nqargs {
+        return Err(CircuitError::new(format!("Gate '{}' takes {} parameters and {} qubits, but {} and {} are given.",
+                                             name, nparams, nqargs, params.len(), qargs.len())));
+    }
+    Ok(())
+}
+
+fn read_include(path: &Path) -> InlineResult<Program> {
+    let mut code = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut code))
+        .map_err(|e| CircuitError::new(format!("Failed to read '{}': {}", path.display(), e)))?;
+    parser::parse_str(&code, path)
+        .map_err(|e| CircuitError::new(format!("{}:{}: {}", path.display(), e.pos, e)))
+}
+
+impl GateTable {
+    pub fn new() -> GateTable {
+        GateTable::default()
+    }
+
+    /// Collects gate declarations of a program, resolving its `include` statements relative to
+    /// the working directory.
+    pub fn from_program(program: &Program) -> InlineResult<GateTable> {
+        GateTable::from_source(program, Path::new(""))
+    }
+
+    /// Collects gate declarations of the program read from the file `source`, resolving its
+    /// `include` statements relative to the directory of that file.
+    pub fn from_source(program: &Program, source: &Path) -> InlineResult<GateTable> {
+        let mut table = GateTable::new();
+        table.add_program(program, source.parent().unwrap_or_else(|| Path::new("")), false)?;
+        Ok(table)
+    }
+
+    fn add_program(&mut self, program: &Program, dir: &Path, standard: bool) -> InlineResult<()> {
+        for stmt in program {
+            match *stmt {
+                Statement::Include(ref path) => {
+                    if path == QELIB1_FILENAME {
+                        if !self.gates.values().any(|g| g.standard) {
+                            let qelib1 = parser::parse_str(QELIB1, Path::new(QELIB1_FILENAME))
+                                .expect("qelib1.inc must be valid.");
+                            self.add_program(&qelib1, Path::new(""), true)?;
+                        }
+                    } else {
+                        let path = dir.join(path);
+                        let included = read_include(&path)?;
+                        self.add_program(&included, path.parent().unwrap_or(dir), standard)?;
+                    }
+                }
+                _ => {
+                    if let Some((name, def)) = GateDef::from_declaration(stmt, standard)? {
+                        self.declare(&name, def)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn declare(&mut self, name: &str, def: GateDef) -> InlineResult<()> {
+        if name == "U" || name == "CX" || self.gates.contains_key(name) {
+            return Err(CircuitError::new(format!("Gate '{}' is already declared.", name)));
+        }
+        self.gates.insert(name.to_owned(), def);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&GateDef> {
+        self.gates.get(name)
+    }
+
+    /// Whether `name` is `U`, `CX` or a gate from `qelib1.inc`.
+    pub fn is_standard(&self, name: &str) -> bool {
+        name == "U" || name == "CX" || self.gates.get(name).map(|g| g.standard).unwrap_or(false)
+    }
+
+    /// Checks the number of parameters and arguments of a gate application.
+    pub fn check_apply(&self, name: &str, params: &Params, qargs: &QArgs) -> InlineResult<()> {
+        let (nparams, nqargs) = match name {
+            "U" => (3, 1),
+            "CX" => (0, 2),
+            _ => match self.gates.get(name) {
+                Some(def) => (def.params.len(), def.qargs.len()),
+                None => return Err(CircuitError::new(format!("Gate '{}' is not declared.", name))),
+            },
+        };
+        check_arity(name, nparams, nqargs, params, qargs)
+    }
+
+    /// Checks every gate application of a program, including those in `if` statements.
+    pub fn check_program(&self, program: &Program) -> InlineResult<()> {
+        for stmt in program {
+            match *stmt {
+                Statement::Apply(ref name, ref params, ref qargs) => self.check_apply(name, params, qargs)?,
+                Statement::If(_, _, ref body) => self.check_program(body)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Expands one gate application into the body of the gate, with parameters and arguments
+    /// substituted. Returns `None` for `U`, `CX` and opaque gates.
+    pub fn expand(&self, name: &str, params: &Params, qargs: &QArgs) -> InlineResult<Option<Program>> {
+        self.check_apply(name, params, qargs)?;
+        match self.gates.get(name) {
+            Some(def) if def.body.is_some() => def.instantiate(name, params, qargs).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl GateDef {
+    /// The name and definition of a `gate` or `opaque` declaration, `None` for other statements.
+    pub fn from_declaration(stmt: &Statement, standard: bool) -> InlineResult<Option<(GateName, GateDef)>> {
+        Ok(match *stmt {
+            Statement::Gate(ref name, ref params, ref qargs, ref body) => Some((name.clone(), GateDef {
+                params: param_names(params)?,
+                qargs: qarg_names(qargs),
+                body: Some(body.clone()),
+                standard,
+            })),
+            Statement::Opaque(ref name, ref params, ref qargs) => Some((name.clone(), GateDef {
+                params: param_names(params)?,
+                qargs: qarg_names(qargs),
+                body: None,
+                standard,
+            })),
+            _ => None,
+        })
+    }
+
+    /// The body of the gate with parameters and arguments substituted; empty for opaque gates.
+    /// Only gate applications and barriers of the body are kept.
+    pub fn instantiate(&self, name: &str, params: &Params, qargs: &QArgs) -> InlineResult<Program> {
+        check_arity(name, self.params.len(), self.qargs.len(), params, qargs)?;
+        let body = match self.body {
+            Some(ref body) => body,
+            None => return Ok(vec![]),
+        };
+        let exprs: HashMap<String, Expr> = self.params.iter().cloned().zip(params.iter().cloned()).collect();
+        let args: HashMap<&str, &QReg> = self.qargs.iter().map(|s| s.as_str()).zip(qargs.iter()).collect();
+        let map_qargs = |qs: &QArgs| -> InlineResult<QArgs> {
+            qs.iter().map(|q| match args.get(q.name()) {
+                Some(&actual) => Ok(actual.clone()),
+                None => Err(CircuitError::new(format!("'{}' is not an argument of gate '{}'.", q.name(), name))),
+            }).collect()
+        };
+        let mut expanded = vec![];
+        for stmt in body {
+            match *stmt {
+                Statement::Apply(ref g, ref ps, ref qs) => {
+                    let ps = ps.iter().map(|p| p.substitute(&exprs).simplify()).collect();
+                    expanded.push(Statement::Apply(g.clone(), ps, map_qargs(qs)?));
+                }
+                Statement::Barrier(ref qs) => expanded.push(Statement::Barrier(map_qargs(qs)?)),
+                _ => {}
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+fn inline_statement<F: Fn(&str) -> bool>(stmt: &Statement, table: &GateTable, keep: &F,
+                                         out: &mut Program) -> InlineResult<()> {
+    match *stmt {
+        Statement::Apply(ref name, ref params, ref qargs) if !keep(name) => {
+            match table.expand(name, params, qargs)? {
+                Some(body) => {
+                    for stmt in &body {
+                        inline_statement(stmt, table, keep, out)?;
+                    }
+                }
+                None => out.push(stmt.clone()),
+            }
+        }
+        Statement::Apply(ref name, ref params, ref qargs) => {
+            table.check_apply(name, params, qargs)?;
+            out.push(stmt.clone());
+        }
+        Statement::If(ref c, val, ref body) => {
+            let mut inlined = vec![];
+            for stmt in body {
+                inline_statement(stmt, table, keep, &mut inlined)?;
+            }
+            out.extend(inlined.into_iter().map(|s| Statement::If(c.clone(), val, vec![s])));
+        }
+        _ => out.push(stmt.clone()),
+    }
+    Ok(())
+}
+
+/// Lowers a program and inlines every gate application whose name does not satisfy `keep`,
+/// down to `U`, `CX` and opaque gates. Declarations of inlined gates are removed; `include`
+/// statements are kept.
+pub fn inline_with<F: Fn(&str) -> bool>(program: &Program, keep: F) -> InlineResult<Program> {
+    inline_table(program, &GateTable::from_program(program)?, keep)
+}
+
+fn inline_table<F: Fn(&str) -> bool>(program: &Program, table: &GateTable, keep: F) -> InlineResult<Program> {
+    let lowered = lower::lower(program)?;
+    let mut out = vec![];
+    for stmt in &lowered {
+        match *stmt {
+            Statement::Gate(ref name, ..) if !keep(name) => {}
+            _ => inline_statement(stmt, table, &keep, &mut out)?,
+        }
+    }
+    Ok(out)
+}
+
+/// Lowers a program and inlines all gates down to `U`, `CX` and opaque gates.
+pub fn inline(program: &Program) -> InlineResult<Program> {
+    inline_with(program, |_| false)
+}
+
+/// Like `inline`, for the program read from the file `source`: other files are included relative
+/// to its directory.
+pub fn inline_source(program: &Program, source: &Path) -> InlineResult<Program> {
+    inline_table(program, &GateTable::from_source(program, source)?, |_| false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+    use test_util::parse;
+
+    #[test]
+    fn inline_gates() {
+        let prog = parse("include \"qelib1.inc\"; qreg q[2]; creg c[1];\n\
+                          gate g(a) x, y { rz(a/2) y; cx x, y; }\n\
+                          g(pi) q[1], q[0]; if (c == 0) h q[0];");
+        assert_eq!(inline(&prog).unwrap(),
+                   parse("include \"qelib1.inc\"; qreg q[2]; creg c[1];\n\
+                          U(0, 0, pi/2) q[0]; CX q[1], q[0]; if (c == 0) U(pi/2, 0, pi) q[0];"));
+        assert_eq!(inline_with(&prog, |name| name == "cx" || name == "h").unwrap(),
+                   parse("include \"qelib1.inc\"; qreg q[2]; creg c[1];\n\
+                          U(0, 0, pi/2) q[0]; cx q[1], q[0]; if (c == 0) h q[0];"));
+    }
+
+    #[test]
+    fn inline_errors() {
+        assert!(inline(&parse("qreg q[1]; h q[0];")).is_err());
+        assert!(inline(&parse("include \"qelib1.inc\"; qreg q[1]; rz q[0];")).is_err());
+        assert!(inline(&parse("include \"qelib1.inc\"; gate h a { U(0,0,0) a; }")).is_err());
+        let opaque = parse("opaque magic(a) x; qreg q[1]; magic(1) q[0];");
+        assert_eq!(inline(&opaque).unwrap(), opaque);
+    }
+
+    #[test]
+    fn include_relative_to_source() {
+        let dir = env::temp_dir().join(format!("openqasm-include-{}", process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lib/gates.inc"), "include \"more.inc\";\ngate g a { f a; }").unwrap();
+        fs::write(dir.join("lib/more.inc"), "gate f a { U(0, 0, pi) a; }").unwrap();
+        let prog = parse("include \"lib/gates.inc\";\nqreg q[1];\ng q[0];");
+        let inlined = inline_source(&prog, &dir.join("main.qasm"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(inlined.unwrap(), parse("include \"lib/gates.inc\";\nqreg q[1];\nU(0, 0, pi) q[0];"));
+        assert!(inline(&prog).is_err());
+    }
+}
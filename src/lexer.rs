@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::error::Error;
 use std::fs::File;
 use std::io::{self, Read};
 use std::path::Path;
@@ -13,14 +12,13 @@ pub type TokenResult = Result<Token, TokenizeError>;
 
 pub struct RecursiveTokenizer {
     codes: HashMap<Rc<Path>, Rc<str>>,
-    stack: Vec<Box<Iterator<Item=TokenResult>>>
+    stack: Vec<Box<dyn Iterator<Item=TokenResult>>>
 }
 
 impl RecursiveTokenizer {
     pub fn new(filename: &Path) -> RecursiveTokenizer {
         let mut rtok = RecursiveTokenizer::get_empty();
-        if let Err(_) = rtok.load(filename.to_path_buf()) {
-        }
+        let _ = rtok.load(filename);
         rtok
     }
 
@@ -110,8 +108,9 @@ impl TokenIterator {
         }
         let mut state = LexerState::Whitespace;
         let mut cur = self.pos;
-        while cur < code.len() {
-            let ch = code[cur];
+        // A virtual newline at the end of the code flushes the last token.
+        while cur <= code.len() {
+            let ch = if cur < code.len() { code[cur] } else { b'\n' };
             // Debug
             // println!("pos: {}, cur: {}, state: {:?}, ch: {}",
             //          self.pos,
@@ -159,7 +158,7 @@ impl TokenIterator {
                         }
                     } else if code[self.pos] == b'-' {
                         assert_eq!(self.pos + 1, cur);
-                        if code[cur] == b'>' {
+                        if ch == b'>' {
                             let tok = Token {
                                 token: TokenType::Arrow,
                                 filename: self.filename.clone(),
@@ -180,7 +179,7 @@ impl TokenIterator {
                         }
                     } else if code[self.pos] == b'/' {
                         assert_eq!(self.pos + 1, cur);
-                        if code[cur] == b'/' {
+                        if ch == b'/' {
                             state = LexerState::Comment;
                             self.pos += 2;
                             cur = self.pos;
@@ -298,7 +297,7 @@ impl TokenIterator {
                                 return Some(Err(TokenizeError::new(self.filename.clone(),
                                                                    self.pos,
                                                                    1,
-                                                                   e.description().to_owned())));
+                                                                   e.to_string())));
                             }
                         }
                     }
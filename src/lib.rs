@@ -1,8 +1,40 @@
 mod keyword;
 mod tokens;
+pub mod angle;
+pub mod clifford_t;
+pub mod complex;
+pub mod dag;
+pub mod draw;
+pub mod density;
+pub mod diagnostic;
+pub mod equivalence;
 pub mod errors;
+pub mod export;
+pub mod formatter;
+pub mod gates;
+pub mod gradient;
+pub mod inline;
 pub mod lexer;
+pub mod lint;
+pub mod lower;
+pub mod noise;
+pub mod observable;
+pub mod optimize;
+pub mod params;
 pub mod parser;
+pub mod printer;
+pub mod rng;
+pub mod route;
+pub mod shots;
+pub mod simplify;
+pub mod simulator;
+pub mod stabilizer;
+pub mod stats;
+pub mod translate;
+pub mod unitary;
+
+#[cfg(test)]
+pub(crate) mod test_util;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,415 @@
+//! Checks for code which is valid but most likely not what was meant, e.g. registers which are
+//! never used or gates applied to qubits after they are measured.
+//!
+//! Every lint has an id and a default level, which `LintConfig` can override. Diagnostics point
+//! at the top-level statement they are about if the spans of the statements are given, see
+//! `parser::parse_str_with_spans`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use diagnostic::{Diagnostic, Severity, Span};
+use errors::CircuitError;
+use inline::{QELIB1, QELIB1_FILENAME};
+use lower::{lower_statement, LowerResult, Registers};
+use parser::{self, Program, QReg, Statement};
+
+/// What to do when a lint finds something.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Report nothing.
+    Allow,
+    /// Report a warning.
+    Warn,
+    /// Report an error.
+    Deny,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Level::Allow => write!(f, "allow"),
+            Level::Warn => write!(f, "warn"),
+            Level::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lint {
+    pub id: &'static str,
+    pub default_level: Level,
+    pub description: &'static str,
+}
+
+/// All lints, in the order they are documented.
+pub const LINTS: &[Lint] = &[
+    Lint {
+        id: "unused-register",
+        default_level: Level::Warn,
+        description: "A quantum or classical register is declared but never used.",
+    },
+    Lint {
+        id: "unused-gate",
+        default_level: Level::Warn,
+        description: "A gate is declared but neither applied nor used by another gate.",
+    },
+    Lint {
+        id: "op-after-measure",
+        default_level: Level::Warn,
+        description: "A gate is applied to a qubit after it is measured, without a reset in between.",
+    },
+    Lint {
+        id: "overwritten-measurement",
+        default_level: Level::Warn,
+        description: "A clbit is measured into twice and the first result is never used by an 'if'.",
+    },
+    Lint {
+        id: "useless-barrier",
+        default_level: Level::Warn,
+        description: "A barrier has no operation on its qubits before it or none after it.",
+    },
+    Lint {
+        id: "redundant-reset",
+        default_level: Level::Warn,
+        description: "A qubit is reset before any operation, when it is in |0> already.",
+    },
+    Lint {
+        id: "shadowed-gate",
+        default_level: Level::Warn,
+        description: "A register, gate or gate argument has the name of a gate of qelib1.inc.",
+    },
+];
+
+pub fn find_lint(id: &str) -> Option<&'static Lint> {
+    LINTS.iter().find(|l| l.id == id)
+}
+
+/// Levels of the lints, overriding their default levels.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl LintConfig {
+    pub fn new() -> LintConfig {
+        LintConfig::default()
+    }
+
+    /// Sets the level of a lint. Fails if there is no lint with the id.
+    pub fn set(&mut self, id: &str, level: Level) -> LowerResult<()> {
+        match find_lint(id) {
+            Some(lint) => {
+                self.levels.insert(lint.id, level);
+                Ok(())
+            }
+            None => Err(CircuitError::new(format!("Unknown lint '{}'.", id))),
+        }
+    }
+
+    pub fn allow(&mut self, id: &str) -> LowerResult<()> {
+        self.set(id, Level::Allow)
+    }
+
+    pub fn deny(&mut self, id: &str) -> LowerResult<()> {
+        self.set(id, Level::Deny)
+    }
+
+    /// Sets the level of all lints.
+    pub fn set_all(&mut self, level: Level) {
+        for lint in LINTS {
+            self.levels.insert(lint.id, level);
+        }
+    }
+
+    pub fn level(&self, id: &str) -> Level {
+        match self.levels.get(id) {
+            Some(&level) => level,
+            None => find_lint(id).map(|l| l.default_level).unwrap_or(Level::Allow),
+        }
+    }
+}
+
+/// Names of `U`, `CX` and the gates of `qelib1.inc`.
+fn qelib1_gates() -> HashSet<String> {
+    let qelib1 = parser::parse_str(QELIB1, Path::new(QELIB1_FILENAME)).expect("qelib1.inc must be valid.");
+    let mut names: HashSet<String> = qelib1.into_iter().filter_map(|stmt| match stmt {
+        Statement::Gate(name, ..) | Statement::Opaque(name, ..) => Some(name),
+        _ => None,
+    }).collect();
+    names.insert("U".to_owned());
+    names.insert("CX".to_owned());
+    names
+}
+
+/// The qubits a lowered operation acts on. Barriers act on none.
+fn op_qubits(op: &Statement) -> Vec<&QReg> {
+    match *op {
+        Statement::Apply(_, _, ref qargs) => qargs.iter().collect(),
+        Statement::Measure(ref q, _) | Statement::Reset(ref q) => vec![q],
+        Statement::If(_, _, ref body) => body.iter().flat_map(op_qubits).collect(),
+        _ => vec![],
+    }
+}
+
+/// Collects the registers and gates a lowered operation uses.
+fn collect_uses<'a>(op: &'a Statement, regs: &mut HashSet<&'a str>, gates: &mut HashSet<&'a str>) {
+    match *op {
+        Statement::Apply(ref name, _, ref qargs) => {
+            gates.insert(name);
+            regs.extend(qargs.iter().map(|q| q.name()));
+        }
+        Statement::Measure(ref q, ref c) => {
+            regs.insert(q.name());
+            regs.insert(c.name());
+        }
+        Statement::Reset(ref q) => {
+            regs.insert(q.name());
+        }
+        Statement::Barrier(ref qargs) => regs.extend(qargs.iter().map(|q| q.name())),
+        Statement::If(ref c, _, ref body) => {
+            regs.insert(c.name());
+            for op in body {
+                collect_uses(op, regs, gates);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn bit_list<T: fmt::Display>(bits: &[T]) -> String {
+    bits.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    spans: Option<&'a [Span]>,
+    /// Diagnostics with the index of the statement they are about.
+    diagnostics: Vec<(usize, Diagnostic)>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, id: &'static str, stmt: usize, message: String) {
+        let severity = match self.config.level(id) {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+        let span = self.spans.and_then(|spans| spans.get(stmt).cloned());
+        self.diagnostics.push((stmt, Diagnostic { severity, code: Some(id), message, span }));
+    }
+
+    /// Lints about declarations: unused registers and gates, and names of standard gates.
+    fn declarations(&mut self, program: &Program, lowered: &[Program]) {
+        let mut used_regs = HashSet::new();
+        let mut applied = HashSet::new();
+        for op in lowered.iter().flatten() {
+            collect_uses(op, &mut used_regs, &mut applied);
+        }
+        for stmt in program {
+            if let Statement::Gate(ref gate, _, _, ref body) = *stmt {
+                for op in body {
+                    if let Statement::Apply(ref name, ..) = *op {
+                        if name != gate {
+                            applied.insert(name);
+                        }
+                    }
+                }
+            }
+        }
+
+        let standard = qelib1_gates();
+        for (i, stmt) in program.iter().enumerate() {
+            match *stmt {
+                Statement::Qreg(ref name, _) | Statement::Creg(ref name, _) => {
+                    let kind = if let Statement::Qreg(..) = *stmt { "Quantum" } else { "Classical" };
+                    if !used_regs.contains(name.as_str()) {
+                        self.report("unused-register", i, format!("{} register '{}' is never used.", kind, name));
+                    }
+                    if standard.contains(name) {
+                        self.report("shadowed-gate", i, format!("Register '{}' has the name of a standard gate.", name));
+                    }
+                }
+                Statement::Gate(ref name, ref params, ref qargs, _) | Statement::Opaque(ref name, ref params, ref qargs) => {
+                    if !applied.contains(name.as_str()) {
+                        self.report("unused-gate", i, format!("Gate '{}' is declared but never applied.", name));
+                    }
+                    if standard.contains(name) {
+                        self.report("shadowed-gate", i, format!("Gate '{}' has the name of a standard gate.", name));
+                    }
+                    let params = params.iter().filter_map(|p| match *p {
+                        parser::Expr::Id(ref name) => Some(name.as_str()),
+                        _ => None,
+                    });
+                    for arg in params.chain(qargs.iter().map(|q| q.name())) {
+                        if standard.contains(arg) {
+                            self.report("shadowed-gate", i,
+                                        format!("Argument '{}' of gate '{}' has the name of a standard gate.", arg, name));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Lints about the order of operations on the bits.
+    fn operations(&mut self, lowered: &[Program], regs: &Registers) {
+        // The first and the last statement operating on each qubit, not counting barriers.
+        let mut first = vec![None; regs.num_qubits()];
+        let mut last = vec![None; regs.num_qubits()];
+        for (i, ops) in lowered.iter().enumerate() {
+            for q in ops.iter().flat_map(op_qubits) {
+                let q = regs.qubit_index(q);
+                first[q] = first[q].or(Some(i));
+                last[q] = Some(i);
+            }
+        }
+
+        let mut touched = vec![false; regs.num_qubits()];
+        let mut measured = vec![false; regs.num_qubits()];
+        // Clbits holding a measurement result which is not used yet.
+        let mut pending = vec![false; regs.num_clbits()];
+        for (i, ops) in lowered.iter().enumerate() {
+            let mut after_measure = vec![];
+            let mut overwritten = vec![];
+            let mut fresh = vec![];
+            for op in ops {
+                match *op {
+                    Statement::Apply(ref name, _, ref qargs) => {
+                        for q in qargs {
+                            if measured[regs.qubit_index(q)] {
+                                after_measure.push((name, q));
+                            }
+                        }
+                    }
+                    Statement::Measure(ref q, ref c) => {
+                        let c_idx = regs.clbit_index(c);
+                        if pending[c_idx] {
+                            overwritten.push(c);
+                        }
+                        pending[c_idx] = true;
+                        measured[regs.qubit_index(q)] = true;
+                    }
+                    Statement::Reset(ref q) => {
+                        let q = regs.qubit_index(q);
+                        if !touched[q] {
+                            fresh.push(regs.qubit(q));
+                        }
+                        measured[q] = false;
+                    }
+                    Statement::If(ref c, ..) => {
+                        for c in regs.clbits(c).expect("The program is lowered.") {
+                            pending[c] = false;
+                        }
+                        // A conditional operation after a measurement is feed-forward, e.g. an
+                        // active reset, so the qubit counts as handled.
+                        for q in op_qubits(op) {
+                            measured[regs.qubit_index(q)] = false;
+                        }
+                    }
+                    Statement::Barrier(ref qargs) => {
+                        let qubits: Vec<usize> = qargs.iter().map(|q| regs.qubit_index(q)).collect();
+                        if qubits.iter().all(|&q| first[q].map(|f| f > i).unwrap_or(true)) {
+                            self.report("useless-barrier", i,
+                                        "Barrier has no effect: no operation on its qubits comes before it.".to_owned());
+                        } else if qubits.iter().all(|&q| last[q].map(|l| l < i).unwrap_or(true)) {
+                            self.report("useless-barrier", i,
+                                        "Barrier has no effect: no operation on its qubits comes after it.".to_owned());
+                        }
+                    }
+                    _ => {}
+                }
+                for q in op_qubits(op) {
+                    touched[regs.qubit_index(q)] = true;
+                }
+            }
+            if let Some(&(name, _)) = after_measure.first() {
+                let qubits: Vec<&QReg> = after_measure.iter().map(|&(_, q)| q).collect();
+                self.report("op-after-measure", i,
+                            format!("Gate '{}' is applied to {} after a measurement.", name, bit_list(&qubits)));
+            }
+            if !overwritten.is_empty() {
+                self.report("overwritten-measurement", i,
+                            format!("Measuring into {} overwrites a result which is never used.", bit_list(&overwritten)));
+            }
+            if !fresh.is_empty() {
+                self.report("redundant-reset", i,
+                            format!("Resetting {} has no effect, the qubits are not used before.", bit_list(&fresh)));
+            }
+        }
+    }
+}
+
+/// Lints a program. `spans` are the spans of the top-level statements, used to point the
+/// diagnostics at the source. Fails if the program cannot be lowered.
+pub fn lint(program: &Program, spans: Option<&[Span]>, config: &LintConfig) -> LowerResult<Vec<Diagnostic>> {
+    let regs = Registers::from_program(program)?;
+    let mut lowered = Vec::with_capacity(program.len());
+    for stmt in program {
+        let mut ops = vec![];
+        lower_statement(stmt, &regs, &mut ops)?;
+        lowered.push(ops);
+    }
+    let mut linter = Linter { config, spans, diagnostics: vec![] };
+    linter.declarations(program, &lowered);
+    linter.operations(&lowered, &regs);
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|&(i, _)| i);
+    Ok(diagnostics.into_iter().map(|(_, d)| d).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse_str_with_spans;
+
+    fn lint_str(code: &str, config: &LintConfig) -> Vec<(&'static str, String)> {
+        let (program, spans) = parse_str_with_spans(code, Path::new("test.qasm")).unwrap();
+        lint(&program, Some(&spans), config).unwrap().into_iter()
+            .map(|d| (d.code.unwrap(), code[d.span.unwrap().pos..][..d.span.unwrap().len].to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn clean_program() {
+        let code = "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\ncreg c[2];\n\
+                    gate bell a, b { h a; cx a, b; }\nbell q[0], q[1];\nbarrier q;\nmeasure q -> c;\n\
+                    if (c == 1) x q[0];\nreset q[0];\nh q[0];\nmeasure q[0] -> c[0];";
+        assert_eq!(lint_str(code, &LintConfig::new()), vec![]);
+    }
+
+    #[test]
+    fn all_lints() {
+        let code = "qreg q[2];\nqreg unused[1];\ncreg c[1];\ncreg x[1];\n\
+                    gate g a { U(0, 0, 0) a; }\ngate cz a, b { CX a, b; }\n\
+                    barrier q;\nreset q[0];\nU(0, 0, 0) q[0];\nmeasure q[0] -> c[0];\n\
+                    U(1, 0, 0) q;\nmeasure q[1] -> c[0];\nmeasure q[1] -> x[0];\nbarrier q[1];";
+        let found = lint_str(code, &LintConfig::new());
+        let expected = [("unused-register", "qreg unused[1];"), ("shadowed-gate", "creg x[1];"),
+                        ("unused-gate", "gate g a { U(0, 0, 0) a; }"), ("unused-gate", "gate cz a, b { CX a, b; }"),
+                        ("shadowed-gate", "gate cz a, b { CX a, b; }"), ("useless-barrier", "barrier q;"),
+                        ("redundant-reset", "reset q[0];"), ("op-after-measure", "U(1, 0, 0) q;"),
+                        ("overwritten-measurement", "measure q[1] -> c[0];"), ("useless-barrier", "barrier q[1];")];
+        let expected: Vec<(&str, String)> = expected.iter().map(|&(id, s)| (id, s.to_owned())).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn configure_levels() {
+        let code = "qreg q[1];\nqreg r[1];\nreset q[0];";
+        let (program, _) = parse_str_with_spans(code, Path::new("test.qasm")).unwrap();
+        let mut config = LintConfig::new();
+        config.allow("redundant-reset").unwrap();
+        config.deny("unused-register").unwrap();
+        assert!(config.set("no-such-lint", Level::Warn).is_err());
+        let diagnostics = lint(&program, None, &config).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].to_string(), "error[unused-register]: Quantum register 'r' is never used.");
+        config.set_all(Level::Allow);
+        assert!(lint(&program, None, &config).unwrap().is_empty());
+        assert_eq!(config.level("redundant-reset"), Level::Allow);
+    }
+}
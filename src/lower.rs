@@ -0,0 +1,274 @@
+//! Lowering of a parsed program into a flat, bit-level circuit.
+//!
+//! A lowered program has the same statements as the original one, but every register argument
+//! of a quantum operation is expanded into single bits. `h q;` on `qreg q[2];` becomes
+//! `h q[0]; h q[1];`. The condition of `if` keeps referring to a whole classical register.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use errors::CircuitError;
+use parser::{CReg, NnInteger, Program, QArgs, QReg, RegName, Statement};
+
+pub type LowerResult<T> = Result<T, CircuitError>;
+
+/// Declared quantum and classical registers, and the global index of each bit.
+///
+/// Bits are numbered in declaration order: the bits of the first declared register come first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Registers {
+    qregs: Vec<(RegName, NnInteger)>,
+    cregs: Vec<(RegName, NnInteger)>,
+    qoffsets: HashMap<RegName, usize>,
+    coffsets: HashMap<RegName, usize>,
+    num_qubits: usize,
+    num_clbits: usize,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers::default()
+    }
+
+    /// Collects the register declarations of a program.
+    pub fn from_program(program: &Program) -> LowerResult<Registers> {
+        let mut regs = Registers::new();
+        for stmt in program {
+            match *stmt {
+                Statement::Qreg(ref name, size) => regs.add_qreg(name, size)?,
+                Statement::Creg(ref name, size) => regs.add_creg(name, size)?,
+                _ => {}
+            }
+        }
+        Ok(regs)
+    }
+
+    fn check_unique(&self, name: &str) -> LowerResult<()> {
+        if self.qoffsets.contains_key(name) || self.coffsets.contains_key(name) {
+            return Err(CircuitError::new(format!("Register '{}' is already declared.", name)));
+        }
+        Ok(())
+    }
+
+    pub fn add_qreg(&mut self, name: &str, size: NnInteger) -> LowerResult<()> {
+        self.check_unique(name)?;
+        self.qoffsets.insert(name.to_owned(), self.num_qubits);
+        self.qregs.push((name.to_owned(), size));
+        self.num_qubits += size as usize;
+        Ok(())
+    }
+
+    pub fn add_creg(&mut self, name: &str, size: NnInteger) -> LowerResult<()> {
+        self.check_unique(name)?;
+        self.coffsets.insert(name.to_owned(), self.num_clbits);
+        self.cregs.push((name.to_owned(), size));
+        self.num_clbits += size as usize;
+        Ok(())
+    }
+
+    pub fn qregs(&self) -> &[(RegName, NnInteger)] {
+        &self.qregs
+    }
+
+    pub fn cregs(&self) -> &[(RegName, NnInteger)] {
+        &self.cregs
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn num_clbits(&self) -> usize {
+        self.num_clbits
+    }
+
+    /// The global indices of the qubits of a quantum argument.
+    pub fn qubits(&self, qreg: &QReg) -> LowerResult<Range<usize>> {
+        let name = qreg.name();
+        let offset = match self.qoffsets.get(name) {
+            Some(&offset) => offset,
+            None => return Err(CircuitError::new(format!("Quantum register '{}' is not declared.", name))),
+        };
+        let size = self.qregs.iter().find(|r| r.0 == name).unwrap().1;
+        match *qreg {
+            QReg::Reg(_) => Ok(offset..offset + size as usize),
+            QReg::Bit(_, idx) if idx < size => Ok(offset + idx as usize..offset + idx as usize + 1),
+            QReg::Bit(_, idx) => Err(CircuitError::new(format!("Index {} is out of range of '{}[{}]'.",
+                                                                  idx, name, size))),
+        }
+    }
+
+    /// The global indices of the bits of a classical argument.
+    pub fn clbits(&self, creg: &CReg) -> LowerResult<Range<usize>> {
+        let name = creg.name();
+        let offset = match self.coffsets.get(name) {
+            Some(&offset) => offset,
+            None => return Err(CircuitError::new(format!("Classical register '{}' is not declared.", name))),
+        };
+        let size = self.cregs.iter().find(|r| r.0 == name).unwrap().1;
+        match *creg {
+            CReg::Reg(_) => Ok(offset..offset + size as usize),
+            CReg::Bit(_, idx) if idx < size => Ok(offset + idx as usize..offset + idx as usize + 1),
+            CReg::Bit(_, idx) => Err(CircuitError::new(format!("Index {} is out of range of '{}[{}]'.",
+                                                                  idx, name, size))),
+        }
+    }
+
+    /// The qubit argument naming the qubit with the global index `idx`.
+    pub fn qubit(&self, idx: usize) -> QReg {
+        let (name, offset) = find_bit(&self.qregs, idx);
+        QReg::Bit(name.clone(), offset)
+    }
+
+    /// The classical argument naming the bit with the global index `idx`.
+    pub fn clbit(&self, idx: usize) -> CReg {
+        let (name, offset) = find_bit(&self.cregs, idx);
+        CReg::Bit(name.clone(), offset)
+    }
+
+    /// The global index of a single-qubit argument. Panics unless it is a declared `QReg::Bit`.
+    pub fn qubit_index(&self, qreg: &QReg) -> usize {
+        match *qreg {
+            QReg::Bit(..) => self.qubits(qreg).expect("Undeclared qubit.").start,
+            QReg::Reg(_) => panic!("Expected a single qubit."),
+        }
+    }
+
+    /// The global index of a single-bit argument. Panics unless it is a declared `CReg::Bit`.
+    pub fn clbit_index(&self, creg: &CReg) -> usize {
+        match *creg {
+            CReg::Bit(..) => self.clbits(creg).expect("Undeclared bit.").start,
+            CReg::Reg(_) => panic!("Expected a single bit."),
+        }
+    }
+}
+
+fn find_bit(regs: &[(RegName, NnInteger)], mut idx: usize) -> (&RegName, NnInteger) {
+    for &(ref name, size) in regs {
+        if idx < size as usize {
+            return (name, idx as NnInteger);
+        }
+        idx -= size as usize;
+    }
+    panic!("Bit index out of range.")
+}
+
+/// Lowers a program. Gate declarations are kept, but register arguments in their bodies are not
+/// touched.
+pub fn lower(program: &Program) -> LowerResult<Program> {
+    let regs = Registers::from_program(program)?;
+    let mut lowered = Vec::with_capacity(program.len());
+    for stmt in program {
+        lower_statement(stmt, &regs, &mut lowered)?;
+    }
+    Ok(lowered)
+}
+
+fn broadcast(qargs: &QArgs, regs: &Registers) -> LowerResult<Vec<QArgs>> {
+    let ranges = qargs.iter().map(|q| regs.qubits(q)).collect::<LowerResult<Vec<_>>>()?;
+    let mut size = 1;
+    for (q, r) in qargs.iter().zip(ranges.iter()) {
+        if let QReg::Reg(_) = *q {
+            if size != 1 && r.len() != size {
+                return Err(CircuitError::new("Registers of different sizes are used in one operation."
+                                                 .to_owned()));
+            }
+            size = r.len();
+        }
+    }
+    Ok((0..size).map(|i| {
+        qargs.iter().zip(ranges.iter()).map(|(q, r)| {
+            match *q {
+                QReg::Reg(_) => regs.qubit(r.start + i),
+                QReg::Bit(..) => q.clone(),
+            }
+        }).collect()
+    }).collect())
+}
+
+/// Lowers a single statement of a program with the registers `regs`, appending the result to
+/// `lowered`.
+pub fn lower_statement(stmt: &Statement, regs: &Registers, lowered: &mut Program) -> LowerResult<()> {
+    match *stmt {
+        Statement::Apply(ref name, ref params, ref qargs) => {
+            for qargs in broadcast(qargs, regs)? {
+                for (i, q) in qargs.iter().enumerate() {
+                    if qargs[..i].contains(q) {
+                        return Err(CircuitError::new(format!("Qubit arguments of '{}' are not distinct.", name)));
+                    }
+                }
+                lowered.push(Statement::Apply(name.clone(), params.clone(), qargs));
+            }
+        }
+        Statement::Barrier(ref qargs) => {
+            let mut bits = vec![];
+            for q in qargs {
+                for i in regs.qubits(q)? {
+                    let q = regs.qubit(i);
+                    if !bits.contains(&q) {
+                        bits.push(q);
+                    }
+                }
+            }
+            lowered.push(Statement::Barrier(bits));
+        }
+        Statement::Reset(ref q) => {
+            for i in regs.qubits(q)? {
+                lowered.push(Statement::Reset(regs.qubit(i)));
+            }
+        }
+        Statement::Measure(ref q, ref c) => {
+            let qs = regs.qubits(q)?;
+            let cs = regs.clbits(c)?;
+            if qs.len() != cs.len() {
+                return Err(CircuitError::new("Sizes of the measured registers do not match.".to_owned()));
+            }
+            for (qi, ci) in qs.zip(cs) {
+                lowered.push(Statement::Measure(regs.qubit(qi), regs.clbit(ci)));
+            }
+        }
+        Statement::If(ref c, val, ref body) => {
+            if let CReg::Bit(..) = *c {
+                return Err(CircuitError::new("The condition of 'if' must be a whole register.".to_owned()));
+            }
+            regs.clbits(c)?;
+            // Each lowered operation is conditioned on its own, so that `if` stays a single-operation
+            // statement.
+            for stmt in body {
+                let mut ops = vec![];
+                lower_statement(stmt, regs, &mut ops)?;
+                for op in ops {
+                    lowered.push(Statement::If(c.clone(), val, vec![op]));
+                }
+            }
+        }
+        _ => lowered.push(stmt.clone()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    #[test]
+    fn lower_broadcast() {
+        let prog = parse("qreg q[2]; qreg r[2]; creg c[2];\n\
+                          cx q, r; h q[1]; measure r -> c; if (c == 1) x q;");
+        let expected = parse("qreg q[2]; qreg r[2]; creg c[2];\n\
+                              cx q[0], r[0]; cx q[1], r[1]; h q[1];\n\
+                              measure r[0] -> c[0]; measure r[1] -> c[1];\n\
+                              if (c == 1) x q[0]; if (c == 1) x q[1];");
+        assert_eq!(lower(&prog).unwrap(), expected);
+    }
+
+    #[test]
+    fn lower_errors() {
+        for code in &["qreg q[2]; h q[2];", "qreg q[2]; qreg r[3]; cx q, r;", "qreg q[1]; h p;",
+                      "qreg q[2]; cx q[0], q[0];"] {
+            let prog = parse(code);
+            assert!(lower(&prog).is_err(), "{}", code);
+        }
+    }
+}
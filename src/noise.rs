@@ -0,0 +1,190 @@
+//! Noise models for the density-matrix simulator.
+//!
+//! Gate errors are single-qubit channels attached to a gate name, either for all qubits or for
+//! one qubit. After a gate is applied, the channels for its name are applied to each qubit it
+//! acts on. An error for a specific qubit replaces the errors for all qubits of the same gate.
+//! Gate names are matched as they are applied in the circuit, before inlining, so an error on
+//! `cx` does not apply to the `cx` gates in the definition of `ccx`.
+//!
+//! Readout errors flip measured bits without affecting the state.
+
+use std::collections::HashMap;
+
+use complex::{Complex, I, ONE, ZERO};
+use errors::CircuitError;
+use gates::Matrix2;
+
+/// A single-qubit noise channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    /// Replaces the state by the maximally mixed state with probability `p`.
+    Depolarizing(f64),
+    /// Decays `|1>` to `|0>` with probability `gamma`.
+    AmplitudeDamping(f64),
+    /// Loses the coherence between `|0>` and `|1>` with probability `lambda`.
+    PhaseDamping(f64),
+}
+
+fn check_probability(p: f64) -> Result<f64, CircuitError> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(p)
+    } else {
+        Err(CircuitError::new(format!("{} is not a probability.", p)))
+    }
+}
+
+impl Channel {
+    fn probability(&self) -> f64 {
+        match *self {
+            Channel::Depolarizing(p) | Channel::AmplitudeDamping(p) | Channel::PhaseDamping(p) => p,
+        }
+    }
+
+    /// The Kraus operators of the channel.
+    pub fn kraus(&self) -> Vec<Matrix2> {
+        let r = Complex::real;
+        match *self {
+            Channel::Depolarizing(p) => {
+                let (a, b) = (r((1.0 - 0.75 * p).sqrt()), r((p / 4.0).sqrt()));
+                vec![[[a, ZERO], [ZERO, a]],
+                     [[ZERO, b], [b, ZERO]],
+                     [[ZERO, -(b * I)], [b * I, ZERO]],
+                     [[b, ZERO], [ZERO, -b]]]
+            }
+            Channel::AmplitudeDamping(gamma) => {
+                vec![[[ONE, ZERO], [ZERO, r((1.0 - gamma).sqrt())]],
+                     [[ZERO, r(gamma.sqrt())], [ZERO, ZERO]]]
+            }
+            Channel::PhaseDamping(lambda) => {
+                vec![[[ONE, ZERO], [ZERO, r((1.0 - lambda).sqrt())]],
+                     [[ZERO, ZERO], [ZERO, r(lambda.sqrt())]]]
+            }
+        }
+    }
+}
+
+/// Probabilities of reading a wrong bit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadoutError {
+    /// Probability of reading 1 when the qubit is measured as 0.
+    pub p01: f64,
+    /// Probability of reading 0 when the qubit is measured as 1.
+    pub p10: f64,
+}
+
+impl ReadoutError {
+    pub fn new(p01: f64, p10: f64) -> Result<ReadoutError, CircuitError> {
+        Ok(ReadoutError { p01: check_probability(p01)?, p10: check_probability(p10)? })
+    }
+
+    /// The same error probability for both outcomes.
+    pub fn symmetric(p: f64) -> Result<ReadoutError, CircuitError> {
+        ReadoutError::new(p, p)
+    }
+
+    /// The probability of flipping a measured `outcome`.
+    pub fn flip_probability(&self, outcome: bool) -> f64 {
+        if outcome { self.p10 } else { self.p01 }
+    }
+}
+
+/// Gate and readout errors.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NoiseModel {
+    gate_errors: HashMap<String, Vec<Channel>>,
+    qubit_gate_errors: HashMap<(String, usize), Vec<Channel>>,
+    readout_error: Option<ReadoutError>,
+    qubit_readout_errors: HashMap<usize, ReadoutError>,
+}
+
+impl NoiseModel {
+    /// A model without errors.
+    pub fn new() -> NoiseModel {
+        NoiseModel::default()
+    }
+
+    /// Adds an error after every application of `gate` on every qubit.
+    pub fn add_gate_error(&mut self, gate: &str, channel: Channel) -> Result<(), CircuitError> {
+        check_probability(channel.probability())?;
+        self.gate_errors.entry(gate.to_owned()).or_default().push(channel);
+        Ok(())
+    }
+
+    /// Adds an error after every application of `gate` on `qubit`, a global qubit index.
+    pub fn add_qubit_gate_error(&mut self, gate: &str, qubit: usize, channel: Channel) -> Result<(), CircuitError> {
+        check_probability(channel.probability())?;
+        self.qubit_gate_errors.entry((gate.to_owned(), qubit)).or_default().push(channel);
+        Ok(())
+    }
+
+    /// Sets the readout error of all qubits.
+    pub fn set_readout_error(&mut self, error: ReadoutError) {
+        self.readout_error = Some(error);
+    }
+
+    /// Sets the readout error of one qubit, replacing the error for all qubits.
+    pub fn set_qubit_readout_error(&mut self, qubit: usize, error: ReadoutError) {
+        self.qubit_readout_errors.insert(qubit, error);
+    }
+
+    /// The channels applied to `qubit` after `gate`.
+    pub fn gate_errors(&self, gate: &str, qubit: usize) -> &[Channel] {
+        self.qubit_gate_errors.get(&(gate.to_owned(), qubit))
+            .or_else(|| self.gate_errors.get(gate))
+            .map(|c| c.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn readout_error(&self, qubit: usize) -> Option<ReadoutError> {
+        self.qubit_readout_errors.get(&qubit).cloned().or(self.readout_error)
+    }
+
+    /// Whether the model has no errors.
+    pub fn is_ideal(&self) -> bool {
+        self.gate_errors.is_empty() && self.qubit_gate_errors.is_empty() &&
+            self.readout_error.is_none() && self.qubit_readout_errors.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gates::{dagger, matmul, IDENTITY};
+
+    #[test]
+    fn channels_are_trace_preserving() {
+        for channel in &[Channel::Depolarizing(0.3), Channel::AmplitudeDamping(0.2), Channel::PhaseDamping(0.7)] {
+            let mut sum = [[ZERO; 2]; 2];
+            for k in channel.kraus() {
+                let m = matmul(&dagger(&k), &k);
+                for i in 0..2 {
+                    for j in 0..2 {
+                        sum[i][j] += m[i][j];
+                    }
+                }
+            }
+            for i in 0..2 {
+                for j in 0..2 {
+                    assert!(sum[i][j].approx_eq(IDENTITY[i][j], 1e-12), "{:?}", channel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn error_lookup() {
+        let mut model = NoiseModel::new();
+        assert!(model.is_ideal());
+        model.add_gate_error("cx", Channel::Depolarizing(0.01)).unwrap();
+        model.add_qubit_gate_error("cx", 1, Channel::AmplitudeDamping(0.1)).unwrap();
+        model.set_readout_error(ReadoutError::symmetric(0.02).unwrap());
+        model.set_qubit_readout_error(3, ReadoutError::new(0.0, 0.1).unwrap());
+        assert_eq!(model.gate_errors("cx", 0), &[Channel::Depolarizing(0.01)]);
+        assert_eq!(model.gate_errors("cx", 1), &[Channel::AmplitudeDamping(0.1)]);
+        assert!(model.gate_errors("h", 0).is_empty());
+        assert_eq!(model.readout_error(0).unwrap().flip_probability(true), 0.02);
+        assert_eq!(model.readout_error(3).unwrap().flip_probability(true), 0.1);
+        assert!(model.add_gate_error("h", Channel::PhaseDamping(1.5)).is_err());
+        assert!(ReadoutError::new(-0.1, 0.0).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Pauli observables and their expectation values.
+//!
+//! An observable is a weighted sum of Pauli strings such as `0.5*ZZI + 0.3*XIX`. Pauli strings
+//! are written in the same order as the bitstrings of `shots::sample`: the rightmost character
+//! acts on qubit 0.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+use complex::{Complex, I, ONE, ZERO};
+use errors::CircuitError;
+use parser::{CReg, Expr, Program, Statement};
+use rng::Rng;
+use shots;
+use simulator::{Executor, SimulationResult, StateVector, MAX_QUBITS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pauli {
+    I,
+    X,
+    Y,
+    Z,
+}
+
+/// A Pauli string with a coefficient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauliTerm {
+    pub coefficient: f64,
+    /// The Pauli acting on qubit `k` is `paulis[k]`.
+    pub paulis: Vec<Pauli>,
+}
+
+impl PauliTerm {
+    /// Bits of the qubits with an `X` or `Y`, and of the qubits with a `Y` or `Z`.
+    fn masks(&self) -> (usize, usize) {
+        self.paulis.iter().enumerate().fold((0, 0), |(x, z), (k, p)| match *p {
+            Pauli::I => (x, z),
+            Pauli::X => (x | 1 << k, z),
+            Pauli::Y => (x | 1 << k, z | 1 << k),
+            Pauli::Z => (x, z | 1 << k),
+        })
+    }
+}
+
+/// A weighted sum of Pauli strings on the same number of qubits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observable {
+    terms: Vec<PauliTerm>,
+}
+
+impl Observable {
+    /// Returns an error if the terms do not act on the same number of qubits.
+    pub fn new(terms: Vec<PauliTerm>) -> Result<Observable, CircuitError> {
+        if terms.is_empty() {
+            return Err(CircuitError::new("An observable needs at least one term.".to_owned()));
+        }
+        if terms.iter().any(|t| t.paulis.len() != terms[0].paulis.len()) {
+            return Err(CircuitError::new("All Pauli strings must have the same length.".to_owned()));
+        }
+        Ok(Observable { terms })
+    }
+
+    pub fn terms(&self) -> &[PauliTerm] {
+        &self.terms
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.terms[0].paulis.len()
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Result<Option<f64>, CircuitError> {
+    let mut number = String::new();
+    while let Some(&c) = chars.peek() {
+        let exponent_sign = (c == '+' || c == '-') && number.ends_with(['e', 'E']);
+        if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+            number.push(c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    if number.is_empty() {
+        return Ok(None);
+    }
+    number.parse().map(Some).map_err(|_| CircuitError::new(format!("Invalid coefficient '{}'.", number)))
+}
+
+fn parse_paulis(chars: &mut Peekable<Chars>) -> Result<Vec<Pauli>, CircuitError> {
+    let mut paulis = vec![];
+    while let Some(&c) = chars.peek() {
+        paulis.push(match c {
+            'I' => Pauli::I,
+            'X' => Pauli::X,
+            'Y' => Pauli::Y,
+            'Z' => Pauli::Z,
+            _ => break,
+        });
+        chars.next();
+    }
+    if paulis.is_empty() {
+        return Err(CircuitError::new("Expected a Pauli string.".to_owned()));
+    }
+    // The rightmost character acts on qubit 0.
+    paulis.reverse();
+    Ok(paulis)
+}
+
+impl FromStr for Observable {
+    type Err = CircuitError;
+
+    /// Parses a sum such as `0.5*ZZI - 0.3 * XIX + YYZ`.
+    fn from_str(s: &str) -> Result<Observable, CircuitError> {
+        let mut chars = s.chars().peekable();
+        let mut terms = vec![];
+        loop {
+            skip_whitespace(&mut chars);
+            // The sign is optional for the first term only.
+            let sign = match chars.peek() {
+                Some(&'+') => 1.0,
+                Some(&'-') => -1.0,
+                None if !terms.is_empty() => break,
+                Some(&c) if !terms.is_empty() => {
+                    return Err(CircuitError::new(format!("Expected '+' or '-' but found '{}'.", c)));
+                }
+                _ => 0.0,
+            };
+            if sign != 0.0 {
+                chars.next();
+                skip_whitespace(&mut chars);
+            }
+            let coefficient = parse_number(&mut chars)?;
+            skip_whitespace(&mut chars);
+            if coefficient.is_some() && chars.peek() == Some(&'*') {
+                chars.next();
+                skip_whitespace(&mut chars);
+            }
+            let paulis = parse_paulis(&mut chars)?;
+            let sign = if sign == 0.0 { 1.0 } else { sign };
+            terms.push(PauliTerm { coefficient: sign * coefficient.unwrap_or(1.0), paulis });
+        }
+        Observable::new(terms)
+    }
+}
+
+impl fmt::Display for Observable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            let sign = if term.coefficient < 0.0 { "-" } else { "+" };
+            match i {
+                0 if sign == "-" => write!(f, "-")?,
+                0 => {}
+                _ => write!(f, " {} ", sign)?,
+            }
+            write!(f, "{}*", term.coefficient.abs())?;
+            for p in term.paulis.iter().rev() {
+                write!(f, "{:?}", p)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `<psi|P|psi>` for a single Pauli string, ignoring its coefficient.
+fn pauli_expectation(amps: &[Complex], term: &PauliTerm) -> f64 {
+    let (xmask, zmask) = term.masks();
+    let ys = term.paulis.iter().filter(|&&p| p == Pauli::Y).count();
+    // Y = i X Z, so P |i> = i^ys (-1)^(popcount(i & zmask)) |i ^ xmask>.
+    let phase = [ONE, I, -ONE, -I][ys % 4];
+    let mut sum = ZERO;
+    for (i, &a) in amps.iter().enumerate() {
+        let v = amps[i ^ xmask].conj() * a;
+        sum += if (i & zmask).count_ones() % 2 == 1 { -v } else { v };
+    }
+    (sum * phase).re
+}
+
+/// The expectation value of an observable in a state.
+pub fn state_expectation(state: &StateVector, observable: &Observable) -> SimulationResult<f64> {
+    if state.num_qubits() != observable.num_qubits() {
+        return Err(CircuitError::new(format!("The observable acts on {} qubits but the state has {}.",
+                                             observable.num_qubits(), state.num_qubits())));
+    }
+    Ok(observable.terms.iter().map(|t| t.coefficient * pauli_expectation(state.amplitudes(), t)).sum())
+}
+
+fn unitary_executor(program: &Program, observable: &Observable) -> SimulationResult<Executor> {
+    let executor = Executor::new(program)?;
+    if let Some(op) = executor.non_unitary_operation() {
+        return Err(CircuitError::new(format!("Expectation values need a circuit without '{}'.", op)));
+    }
+    let n = executor.registers().num_qubits();
+    if n > MAX_QUBITS {
+        return Err(CircuitError::new(format!("{} qubits are too many to simulate.", n)));
+    }
+    if n != observable.num_qubits() {
+        return Err(CircuitError::new(format!("The observable acts on {} qubits but the circuit has {}.",
+                                             observable.num_qubits(), n)));
+    }
+    Ok(executor)
+}
+
+/// The exact expectation value of an observable in the final state of a program without
+/// `measure`, `reset` and `if`.
+pub fn expectation(program: &Program, observable: &Observable) -> SimulationResult<f64> {
+    let executor = unitary_executor(program, observable)?;
+    let mut state = StateVector::new(executor.registers().num_qubits());
+    // There are no measurements, so the generator is never used.
+    executor.run(&mut state, &mut Rng::from_seed(0))?;
+    state_expectation(&state, observable)
+}
+
+/// The program with rotations into the eigenbasis of a Pauli string and measurements of the
+/// qubits it acts on into a new register `creg`.
+fn measurement_program(program: &Program, executor: &Executor, term: &PauliTerm, creg: &str) -> Program {
+    let regs = executor.registers();
+    let mut measured = program.clone();
+    let qubits: Vec<usize> = (0..term.paulis.len()).filter(|&k| term.paulis[k] != Pauli::I).collect();
+    measured.push(Statement::Creg(creg.to_owned(), qubits.len() as u32));
+    let half_pi = Expr::Div(Box::new(Expr::Pi), Box::new(Expr::NnInteger(2)));
+    for (bit, &k) in qubits.iter().enumerate() {
+        let q = regs.qubit(k);
+        // H = U(pi/2, 0, pi) and H Sdg = U(pi/2, 0, pi/2) map the eigenbases of X and Y to Z.
+        let lambda = match term.paulis[k] {
+            Pauli::X => Some(Expr::Pi),
+            Pauli::Y => Some(half_pi.clone()),
+            _ => None,
+        };
+        if let Some(lambda) = lambda {
+            let params = vec![half_pi.clone(), Expr::NnInteger(0), lambda];
+            measured.push(Statement::Apply("U".to_owned(), params, vec![q.clone()]));
+        }
+        measured.push(Statement::Measure(q, CReg::Bit(creg.to_owned(), bit as u32)));
+    }
+    measured
+}
+
+/// Estimates the expectation value of an observable in the final state of a program without
+/// `measure`, `reset` and `if` from `shots` measurements of each Pauli string.
+pub fn estimate(program: &Program, observable: &Observable, shots: usize, seed: Option<u64>)
+                -> SimulationResult<f64> {
+    if shots == 0 {
+        return Err(CircuitError::new("An estimate needs at least one shot.".to_owned()));
+    }
+    let executor = unitary_executor(program, observable)?;
+    let taken: Vec<&str> = executor.registers().cregs().iter().map(|(name, _)| name.as_str()).collect();
+    let creg = (0..).map(|i| format!("pauli{}", i)).find(|name| !taken.contains(&name.as_str())).unwrap();
+    let mut rng = seed.map(Rng::from_seed).unwrap_or_else(Rng::from_time);
+    let mut total = 0.0;
+    for term in &observable.terms {
+        if term.paulis.iter().all(|&p| p == Pauli::I) {
+            total += term.coefficient;
+            continue;
+        }
+        let measured = measurement_program(program, &executor, term, &creg);
+        let counts = shots::sample(&measured, shots, Some(rng.next_u64()))?;
+        // The new register is declared last, so it is leftmost in the bitstrings.
+        let sum: i64 = counts.iter().map(|(bits, &n)| {
+            let ones = bits.split(' ').next().unwrap_or("").matches('1').count();
+            if ones % 2 == 0 { n as i64 } else { -(n as i64) }
+        }).sum();
+        total += term.coefficient * sum as f64 / shots as f64;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    #[test]
+    fn parse_observables() {
+        let obs: Observable = "0.5*ZZI + 0.3 * XIX - YYZ".parse().unwrap();
+        assert_eq!(obs.num_qubits(), 3);
+        assert_eq!(obs.terms()[0], PauliTerm { coefficient: 0.5, paulis: vec![Pauli::I, Pauli::Z, Pauli::Z] });
+        assert_eq!(obs.terms()[2].coefficient, -1.0);
+        assert_eq!(obs.to_string(), "0.5*ZZI + 0.3*XIX - 1*YYZ");
+        assert_eq!("-2e-1 XY".parse::<Observable>().unwrap().terms()[0].coefficient, -0.2);
+        assert!("0.5*ZZ + XXX".parse::<Observable>().is_err());
+        assert!("0.5*ZQ".parse::<Observable>().is_err());
+        assert!("".parse::<Observable>().is_err());
+        assert!("ZZ 0.5".parse::<Observable>().is_err());
+    }
+
+    #[test]
+    fn exact_expectation_values() {
+        let bell = parse("include \"qelib1.inc\"; qreg q[2]; h q[0]; cx q[0], q[1];");
+        let obs: Observable = "ZZ + XX - YY + 0.5*IZ + 2*II".parse().unwrap();
+        assert!((expectation(&bell, &obs).unwrap() - 5.0).abs() < 1e-12);
+
+        let ry = parse("include \"qelib1.inc\"; qreg q[2]; ry(0.3) q[0]; rx(0.7) q[1];");
+        let obs: Observable = "ZX + YI".parse().unwrap();
+        let expected = 0.7f64.cos() * 0.3f64.sin() - 0.7f64.sin();
+        assert!((expectation(&ry, &obs).unwrap() - expected).abs() < 1e-12);
+
+        assert!(expectation(&ry, &"Z".parse().unwrap()).is_err());
+        let measured = parse("qreg q[1]; creg c[1]; measure q[0] -> c[0];");
+        assert!(expectation(&measured, &"Z".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn estimated_expectation_values() {
+        let prog = parse("include \"qelib1.inc\"; qreg q[2]; creg pauli0[1]; ry(0.3) q[0]; rx(0.7) q[1];\n\
+                          cx q[0], q[1];");
+        let obs: Observable = "0.5*ZX + YI - 0.25*ZZ + 0.1*II".parse().unwrap();
+        let exact = expectation(&prog, &obs).unwrap();
+        let estimate = estimate(&prog, &obs, 20000, Some(4)).unwrap();
+        assert!((exact - estimate).abs() < 0.03, "{} {}", exact, estimate);
+        assert_eq!(estimate, super::estimate(&prog, &obs, 20000, Some(4)).unwrap());
+        assert!(super::estimate(&prog, &obs, 0, Some(4)).is_err());
+    }
+}
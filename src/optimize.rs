@@ -0,0 +1,457 @@
+//! Optimisation passes over the circuit DAG.
+//!
+//! The passes only rewrite `U`, `CX` and gates from `qelib1.inc`, since the meaning of other
+//! gates is up to their declarations. Rewrites keep the unitary of the circuit up to a global
+//! phase.
+
+use angle::{self, Angle};
+use dag::{DagCircuit, NodeId, Wire};
+use gates::{self, GateMatrix, Matrix2};
+use inline::GateTable;
+use lower::LowerResult;
+use parser::{Expr, Program, QReg, Statement};
+
+/// Gates which are their own inverse.
+fn is_self_inverse(name: &str) -> bool {
+    matches!(name, "CX" | "cx" | "x" | "y" | "z" | "h" | "cy" | "cz" | "ch" | "swap" | "ccx" | "cswap" |
+                   "c3x" | "c4x")
+}
+
+/// Whether `a` followed by `b` is the identity.
+fn are_inverses(a: &str, b: &str) -> bool {
+    matches!((a, b), ("s", "sdg") | ("sdg", "s") | ("t", "tdg") | ("tdg", "t") | ("sx", "sxdg") | ("sxdg", "sx"))
+}
+
+/// Gates with a single angle parameter where two rotations add their angles. The second value
+/// is how many turns of `2*pi` the identity takes, up to a global phase.
+fn rotation(name: &str) -> Option<i64> {
+    match name {
+        "rx" | "ry" | "rz" | "u1" | "p" | "cu1" | "cp" | "rxx" | "rzz" => Some(1),
+        "crx" | "cry" | "crz" => Some(2),
+        _ => None,
+    }
+}
+
+/// Gates whose qubits can be swapped.
+fn is_symmetric(name: &str) -> bool {
+    matches!(name, "cz" | "swap" | "cu1" | "cp" | "rxx" | "rzz")
+}
+
+fn same_qubits(name: &str, a: &[QReg], b: &[QReg]) -> bool {
+    a == b || (is_symmetric(name) && a.len() == 2 && b.len() == 2 && a[0] == b[1] && a[1] == b[0])
+}
+
+/// Whether a standard gate application is the identity up to a global phase.
+fn is_identity(name: &str, params: &[Expr]) -> bool {
+    if let Some(turns) = rotation(name) {
+        return params[0].snapped_angle().map(|a| a.div(&Angle::from_f64(turns as f64)).is_zero()).unwrap_or(false);
+    }
+    match name {
+        "U" | "u3" | "u" => angle::u_phase(params).map(|a| a.is_zero()).unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// The gate application of a node, if it is one of a standard gate.
+fn standard_apply<'a>(dag: &'a DagCircuit, gates: &GateTable, id: NodeId) -> Option<(&'a str, &'a [Expr], &'a [QReg])> {
+    match *dag.node(id).statement() {
+        Statement::Apply(ref name, ref params, ref qargs) if gates.is_standard(name) => Some((name, params, qargs)),
+        _ => None,
+    }
+}
+
+/// The node directly after `id` on all of its wires, if there is one acting on the same wires.
+fn next_on_same_wires(dag: &DagCircuit, id: NodeId) -> Option<NodeId> {
+    let wires = dag.node(id).wires();
+    let next = dag.successor(id, wires[0])?;
+    if dag.node(next).wires().len() == wires.len() && wires.iter().all(|&w| dag.successor(id, w) == Some(next)) {
+        Some(next)
+    } else {
+        None
+    }
+}
+
+/// A basis in which a gate is diagonal on one of its qubits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Basis {
+    X,
+    Y,
+    Z,
+}
+
+/// The basis in which a standard gate is diagonal on its `k`-th qubit, i.e. the gate is a sum
+/// of projections onto the basis states of that qubit, each times an operation on the other
+/// qubits. Two gates commute if they are diagonal in the same basis on every qubit they share.
+fn basis(name: &str, k: usize) -> Option<Basis> {
+    Some(match (name, k) {
+        ("z", 0) | ("s", 0) | ("sdg", 0) | ("t", 0) | ("tdg", 0) | ("rz", 0) | ("u1", 0) | ("p", 0) => Basis::Z,
+        ("cz", _) | ("crz", _) | ("cu1", _) | ("cp", _) | ("rzz", _) => Basis::Z,
+        ("x", 0) | ("rx", 0) | ("sx", 0) | ("sxdg", 0) | ("rxx", _) => Basis::X,
+        ("y", 0) | ("ry", 0) => Basis::Y,
+        ("CX", 1) | ("cx", 1) | ("crx", 1) | ("csx", 1) | ("ccx", 2) | ("c3x", 3) | ("c3sqrtx", 3) | ("c4x", 4) => Basis::X,
+        ("cy", 1) | ("cry", 1) => Basis::Y,
+        // The controls of controlled gates.
+        ("CX", 0) | ("cx", 0) | ("cy", 0) | ("ch", 0) | ("crx", 0) | ("cry", 0) | ("cu3", 0) | ("cu", 0) |
+        ("csx", 0) | ("cswap", 0) => Basis::Z,
+        ("ccx", 0..=1) | ("c3x", 0..=2) | ("c3sqrtx", 0..=2) | ("c4x", 0..=3) => Basis::Z,
+        _ => return None,
+    })
+}
+
+/// Whether the operations of two nodes commute, judged by `basis`.
+fn commutes(dag: &DagCircuit, gates: &GateTable, a: NodeId, b: NodeId) -> bool {
+    let ((a_name, _, a_qargs), (b_name, _, b_qargs)) = match (standard_apply(dag, gates, a), standard_apply(dag, gates, b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+    if matches!(a_name, "id" | "u0") || matches!(b_name, "id" | "u0") {
+        return true;
+    }
+    a_qargs.iter().enumerate().all(|(i, q)| match b_qargs.iter().position(|r| r == q) {
+        Some(j) => basis(a_name, i).is_some() && basis(a_name, i) == basis(b_name, j),
+        None => true,
+    })
+}
+
+/// Whether `id` commutes with all nodes between it and `later` on `wire`.
+fn commutes_until(dag: &DagCircuit, gates: &GateTable, id: NodeId, later: NodeId, wire: Wire) -> bool {
+    let mut cur = dag.successor(id, wire);
+    while let Some(k) = cur {
+        if k == later {
+            return true;
+        }
+        if !commutes(dag, gates, id, k) {
+            return false;
+        }
+        cur = dag.successor(k, wire);
+    }
+    false
+}
+
+/// How two gates combine into at most one.
+enum Combined {
+    Cancel,
+    Merge(Statement),
+}
+
+/// Whether the nodes `a` and, after it, `b` cancel or merge.
+fn combine(dag: &DagCircuit, gates: &GateTable, a: NodeId, b: NodeId) -> Option<Combined> {
+    let (a, a_params, a_qargs) = standard_apply(dag, gates, a)?;
+    let (b, b_params, b_qargs) = standard_apply(dag, gates, b)?;
+    if (a == b && is_self_inverse(a) && same_qubits(a, a_qargs, b_qargs)) || (are_inverses(a, b) && a_qargs == b_qargs) {
+        Some(Combined::Cancel)
+    } else if a == b && rotation(a).is_some() && same_qubits(a, a_qargs, b_qargs) {
+        let sum = Expr::Add(Box::new(a_params[0].clone()), Box::new(b_params[0].clone())).simplify();
+        Some(Combined::Merge(Statement::Apply(a.to_owned(), vec![sum], a_qargs.to_vec())))
+    } else {
+        None
+    }
+}
+
+/// A later node which `id` cancels or merges with after moving `id` past the nodes in between,
+/// all of which it commutes with.
+fn commuting_partner(dag: &DagCircuit, gates: &GateTable, id: NodeId) -> Option<NodeId> {
+    let wires = dag.node(id).wires();
+    let mut cur = dag.successor(id, wires[0]);
+    while let Some(k) = cur {
+        if combine(dag, gates, id, k).is_some() && wires.iter().all(|&w| commutes_until(dag, gates, id, k, w)) {
+            return Some(k);
+        }
+        if !commutes(dag, gates, id, k) {
+            return None;
+        }
+        cur = dag.successor(k, wires[0]);
+    }
+    None
+}
+
+/// Rewrites the node `id` with its partner, if there is one. The result takes the place of the
+/// partner. Returns the number of removed gates.
+fn rewrite_pair(dag: &mut DagCircuit, gates: &GateTable, id: NodeId, commutation: bool) -> LowerResult<usize> {
+    let partner = if commutation { commuting_partner(dag, gates, id) } else { next_on_same_wires(dag, id) };
+    let (partner, combined) = match partner.and_then(|p| combine(dag, gates, id, p).map(|c| (p, c))) {
+        Some(pair) => pair,
+        None => return Ok(0),
+    };
+    dag.remove_node(id);
+    match combined {
+        Combined::Cancel => {
+            dag.remove_node(partner);
+            Ok(2)
+        }
+        Combined::Merge(stmt) => {
+            dag.substitute_node(partner, vec![stmt])?;
+            Ok(1)
+        }
+    }
+}
+
+/// Removes identity rotations and rewrites pairs with `rewrite_pair` until nothing changes.
+fn rewrite_pairs(dag: &mut DagCircuit, gates: &GateTable, commutation: bool) -> LowerResult<usize> {
+    let mut removed = 0;
+    loop {
+        let before = removed;
+        for id in dag.topological() {
+            if !dag.contains(id) {
+                continue;
+            }
+            let identity = match standard_apply(dag, gates, id) {
+                Some((name, params, _)) => is_identity(name, params),
+                None => continue,
+            };
+            if identity {
+                dag.remove_node(id);
+                removed += 1;
+            } else {
+                removed += rewrite_pair(dag, gates, id, commutation)?;
+            }
+        }
+        if removed == before {
+            return Ok(removed);
+        }
+    }
+}
+
+/// Cancels adjacent gates which are inverses of each other (`h q; h q;`, `s q; sdg q;`), merges
+/// adjacent rotations about the same axis by adding their angles (`rz(a) q; rz(b) q;` becomes
+/// `rz(a + b) q;`) and removes rotations by zero. Gates are adjacent when no other operation on
+/// their wires is between them; barriers and conditional operations are never rewritten.
+/// Returns the number of removed gates.
+pub fn peephole(dag: &mut DagCircuit, gates: &GateTable) -> LowerResult<usize> {
+    rewrite_pairs(dag, gates, false)
+}
+
+/// Like `peephole`, but gates also cancel or merge when the gates between them commute with
+/// the first one, e.g. in `cx a, b; rz(t) a; x b; cx a, b;`. Gates commute when they are
+/// diagonal in the same basis on each qubit they share: `rz` and the control of `cx` in the Z
+/// basis, `x` and the target of `cx` in the X basis.
+pub fn cancel_commuting(dag: &mut DagCircuit, gates: &GateTable) -> LowerResult<usize> {
+    rewrite_pairs(dag, gates, true)
+}
+
+/// The matrix of a node applying a standard single-qubit gate with constant parameters.
+fn single_qubit_matrix(dag: &DagCircuit, gates: &GateTable, id: NodeId) -> Option<Matrix2> {
+    let (name, params, qargs) = standard_apply(dag, gates, id)?;
+    if qargs.len() != 1 || !params.iter().all(Expr::is_constant) {
+        return None;
+    }
+    let values: Vec<f64> = params.iter().map(Expr::eval).collect();
+    match gates::standard_gate(name, &values)? {
+        GateMatrix::Unitary { controls: 0, matrix } => Some(matrix),
+        _ => None,
+    }
+}
+
+/// An angle as an expression, written as a multiple of `pi` if it is within `tolerance` of one.
+fn angle_expr(angle: f64, tolerance: f64) -> (Angle, Expr) {
+    let angle = Angle::from_f64(gates::wrap(angle)).snap(tolerance);
+    (angle, angle.to_expr())
+}
+
+/// Writes a single-qubit unitary as one gate: `u1` if it is diagonal, `u2` if it needs a
+/// rotation by `pi/2` and `u3` otherwise, or `U` for all if `qelib1.inc` is not included.
+/// Returns `None` for the identity.
+fn synthesize(matrix: &Matrix2, qelib1: bool, tolerance: f64) -> Option<(&'static str, Vec<Expr>)> {
+    let (theta, phi, lambda) = gates::u_angles(matrix);
+    let (theta, theta_expr) = angle_expr(theta, tolerance);
+    let (_, phi_expr) = angle_expr(phi, tolerance);
+    let (_, lambda_expr) = angle_expr(lambda, tolerance);
+    if theta.is_zero() {
+        let (sum, sum_expr) = angle_expr(phi + lambda, tolerance);
+        return match (sum.is_zero(), qelib1) {
+            (true, _) => None,
+            (false, true) => Some(("u1", vec![sum_expr])),
+            (false, false) => Some(("U", vec![Expr::NnInteger(0), Expr::NnInteger(0), sum_expr])),
+        };
+    }
+    if !qelib1 {
+        Some(("U", vec![theta_expr, phi_expr, lambda_expr]))
+    } else if (theta - Angle::new(1, 2)).is_zero() {
+        Some(("u2", vec![phi_expr, lambda_expr]))
+    } else {
+        Some(("u3", vec![theta_expr, phi_expr, lambda_expr]))
+    }
+}
+
+/// Replaces a run of single-qubit gates on one qubit by at most one gate. A single gate is only
+/// replaced if it is the identity. Returns the number of removed gates.
+fn replace_run(dag: &mut DagCircuit, run: &[(NodeId, Matrix2)], qelib1: bool, tolerance: f64) -> LowerResult<usize> {
+    if run.is_empty() {
+        return Ok(0);
+    }
+    let product = run.iter().skip(1).fold(run[0].1, |acc, &(_, m)| gates::matmul(&m, &acc));
+    let gate = synthesize(&product, qelib1, tolerance);
+    if run.len() == 1 && gate.is_some() {
+        return Ok(0);
+    }
+    let qarg = match *dag.node(run[0].0).statement() {
+        Statement::Apply(_, _, ref qargs) => qargs[0].clone(),
+        _ => unreachable!(),
+    };
+    let replacement: Program = gate.into_iter()
+        .map(|(name, params)| Statement::Apply(name.to_owned(), params, vec![qarg.clone()]))
+        .collect();
+    let added = replacement.len();
+    dag.substitute_node(run[0].0, replacement)?;
+    for &(id, _) in &run[1..] {
+        dag.remove_node(id);
+    }
+    Ok(run.len() - added)
+}
+
+/// Multiplies each run of consecutive single-qubit gates with constant parameters into one
+/// unitary and writes it as a single gate (see `synthesize`), or removes it if it is the
+/// identity up to a global phase. Angles within `tolerance` of a multiple of `pi` are written
+/// exactly and decide whether `u1` or `u2` is enough. Returns the number of removed gates.
+pub fn resynthesize(dag: &mut DagCircuit, gates: &GateTable, tolerance: f64) -> LowerResult<usize> {
+    let qelib1 = gates.is_standard("u3");
+    let mut removed = 0;
+    for q in 0..dag.registers().num_qubits() {
+        let wire = Wire::Qubit(q);
+        let mut run = vec![];
+        let mut cur = dag.first(wire);
+        while let Some(id) = cur {
+            if let Some(matrix) = single_qubit_matrix(dag, gates, id) {
+                run.push((id, matrix));
+            } else {
+                removed += replace_run(dag, &run, qelib1, tolerance)?;
+                run.clear();
+            }
+            cur = dag.successor(id, wire);
+        }
+        removed += replace_run(dag, &run, qelib1, tolerance)?;
+    }
+    Ok(removed)
+}
+
+/// Lowers a program and runs the cancellation of commuting gates, the resynthesis of
+/// single-qubit gates and the cancellation again, since removed runs may leave inverse gates
+/// next to each other. Returns
+/// the optimised program and the number of removed gates. Comments in the body of the program
+/// are dropped.
+pub fn optimize(program: &Program) -> LowerResult<(Program, usize)> {
+    let gates = GateTable::from_program(program)?;
+    gates.check_program(program)?;
+    let mut dag = DagCircuit::from_program(program)?;
+    let mut removed = cancel_commuting(&mut dag, &gates)?;
+    removed += resynthesize(&mut dag, &gates, angle::TOLERANCE)?;
+    removed += cancel_commuting(&mut dag, &gates)?;
+    Ok((dag.to_program(), removed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printer::to_qasm;
+    use test_util::parse;
+    use unitary::unitary;
+
+    fn circuit(body: &str) -> Program {
+        parse(&format!("include \"qelib1.inc\";\nqreg q[3];\n{}", body))
+    }
+
+    /// Runs a pass and returns the operations of the result as text, and the number of removed
+    /// gates.
+    fn run<F>(body: &str, pass: F) -> (String, usize)
+        where F: Fn(&mut DagCircuit, &GateTable) -> LowerResult<usize>
+    {
+        let program = circuit(body);
+        let mut dag = DagCircuit::from_program(&program).unwrap();
+        let removed = pass(&mut dag, &GateTable::from_program(&program).unwrap()).unwrap();
+        let text = to_qasm(&dag.to_program());
+        (text.lines().skip(2).collect::<Vec<_>>().join("\n"), removed)
+    }
+
+    fn optimized(body: &str) -> (String, usize) {
+        run(body, peephole)
+    }
+
+    #[test]
+    fn cancel_inverse_pairs() {
+        assert_eq!(optimized("cx q[0], q[1];\ncx q[0], q[1];\nh q[2];\nh q[2];"), (String::new(), 4));
+        // The pairs only become adjacent once the inner ones are gone.
+        assert_eq!(optimized("h q[0];\ns q[0];\nt q[0];\ntdg q[0];\nsdg q[0];\nh q[0];"), (String::new(), 6));
+        assert_eq!(optimized("cz q[0], q[1];\ncz q[1], q[0];\nswap q[1], q[2];\nswap q[2], q[1];"), (String::new(), 4));
+        // Different qubits, a gate in between and a condition block the cancellation.
+        assert_eq!(optimized("cx q[0], q[1];\ncx q[1], q[0];").1, 0);
+        assert_eq!(optimized("x q[0];\ncx q[0], q[1];\nx q[0];").1, 0);
+        assert_eq!(optimized("creg c[1];\nh q[0];\nif (c == 0) h q[0];").1, 0);
+    }
+
+    #[test]
+    fn merge_rotations() {
+        assert_eq!(optimized("rz(pi/4) q[0];\nx q[1];\nrz(pi/4) q[0];\nrz(pi/2) q[0];"),
+                   ("x q[1];\nrz(pi) q[0];".to_owned(), 2));
+        assert_eq!(optimized("u1(0.1) q[0];\nu1(-0.1) q[0];\nrx(2*pi) q[1];\ncrz(2*pi) q[0], q[1];"),
+                   ("crz(2*pi) q[0], q[1];".to_owned(), 3));
+        assert_eq!(optimized("rzz(theta) q[0], q[1];\nrzz(pi) q[1], q[0];"),
+                   ("rzz(theta + pi) q[0], q[1];".to_owned(), 1));
+        assert_eq!(optimized("U(0, pi/2, -pi/2) q[0];\nU(0, pi/2, 0) q[0];\nrx(pi) q[0];\nry(pi) q[0];").1, 1);
+    }
+
+    #[test]
+    fn keep_declared_gates() {
+        let code = "qreg q[1];\ngate h a { U(pi/2, 0, pi) a; }\ngate rz(t) a { U(0, 0, t) a; }\n\
+                    h q[0];\nh q[0];\nrz(0) q[0];";
+        let (_, removed) = optimize(&parse(code)).unwrap();
+        assert_eq!(removed, 0);
+    }
+
+    #[test]
+    fn malformed_applications() {
+        for body in &["rz q[0];", "cx q[0];", "creg c[1];\nif (c == 0) u3(0, 0) q[0];"] {
+            assert!(optimize(&circuit(body)).is_err(), "{}", body);
+        }
+    }
+
+    #[test]
+    fn resynthesize_runs() {
+        let resynthesized = |body: &str| run(body, |dag, gates| resynthesize(dag, gates, angle::TOLERANCE));
+        assert_eq!(resynthesized("h q[0];\nt q[0];"), ("u2(pi/4, pi) q[0];".to_owned(), 1));
+        assert_eq!(resynthesized("s q[1];\nt q[1];\nrz(pi/2) q[1];"), ("u1(-(3*pi/4)) q[1];".to_owned(), 2));
+        assert_eq!(resynthesized("h q[0];\ns q[0];\ns q[0];\nh q[0];\nx q[0];\nh q[1];"), ("h q[1];".to_owned(), 5));
+        // Two-qubit gates and symbolic parameters end the runs.
+        assert_eq!(resynthesized("x q[0];\ncx q[0], q[1];\nh q[0];\nrz(a) q[0];\nrz(b) q[0];").1, 0);
+        // Near-identities are removed only with a large enough tolerance.
+        let program = circuit("rz(0.0000001) q[0];");
+        let gates = GateTable::from_program(&program).unwrap();
+        let mut dag = DagCircuit::from_program(&program).unwrap();
+        assert_eq!(resynthesize(&mut dag, &gates, angle::TOLERANCE).unwrap(), 0);
+        assert_eq!(resynthesize(&mut dag, &gates, 1e-6).unwrap(), 1);
+    }
+
+    #[test]
+    fn resynthesis_keeps_unitary() {
+        let body = "h q[0];\nrx(0.3) q[0];\nt q[1];\nry(1.2) q[1];\nsx q[1];\ncx q[0], q[1];\nu3(0.1, 0.2, 0.3) q[1];\n\
+                    y q[1];\nsdg q[2];\nu2(1, 2) q[2];\nz q[2];\nccx q[0], q[1], q[2];\nx q[2];\nx q[2];\nrz(pi) q[0];\n\
+                    ry(pi) q[0];\nswap q[1], q[2];\nU(pi, 0.5, 0.5) q[1];";
+        let program = circuit(body);
+        let (optimized, removed) = optimize(&program).unwrap();
+        assert_eq!(removed, 9);
+        assert!(unitary(&program).unwrap().equiv_up_to_global_phase(&unitary(&optimized).unwrap(), 1e-9));
+        // Without qelib1.inc, everything is written with U.
+        let program = parse("qreg q[1];\nU(0.1, 0, 0) q[0];\nU(0, 0, 0.2) q[0];\nU(0, 0.5, 0.5) q[0];\nU(0, 0, 1) q[0];");
+        let (optimized, _) = optimize(&program).unwrap();
+        assert_eq!(optimized.len(), 2);
+        assert!(unitary(&program).unwrap().equiv_up_to_global_phase(&unitary(&optimized).unwrap(), 1e-9));
+    }
+
+    #[test]
+    fn cancel_across_commuting_gates() {
+        let cancelled = |body: &str| run(body, cancel_commuting);
+        assert_eq!(cancelled("cx q[0], q[1];\nrz(0.3) q[0];\nx q[1];\ncx q[0], q[1];"),
+                   ("rz(0.3) q[0];\nx q[1];".to_owned(), 2));
+        assert_eq!(cancelled("cx q[0], q[1];\ncx q[2], q[1];\ncx q[0], q[1];"), ("cx q[2], q[1];".to_owned(), 2));
+        assert_eq!(cancelled("t q[0];\ncz q[0], q[1];\nu1(a) q[0];\ntdg q[0];"), ("cz q[0], q[1];\nu1(a) q[0];".to_owned(), 2));
+        assert_eq!(cancelled("rz(a) q[0];\ncx q[0], q[1];\nrz(b) q[0];"), ("cx q[0], q[1];\nrz(a + b) q[0];".to_owned(), 1));
+        // h does not commute with cx, nor does measure with anything.
+        assert_eq!(cancelled("cx q[0], q[1];\nh q[0];\ncx q[0], q[1];").1, 0);
+        assert_eq!(cancelled("cx q[0], q[1];\nrx(0.1) q[0];\ncx q[0], q[1];").1, 0);
+        assert_eq!(cancelled("creg c[1];\nz q[0];\nmeasure q[0] -> c[0];\nz q[0];").1, 0);
+        let program = circuit("cx q[0], q[1];\nccx q[0], q[2], q[1];\nsx q[1];\nrzz(0.5) q[0], q[2];\ncx q[0], q[1];\n\
+                             y q[2];\ncy q[0], q[2];\nry(0.2) q[2];\ncy q[0], q[2];");
+        let (optimized, removed) = optimize(&program).unwrap();
+        assert_eq!(removed, 5);
+        assert!(unitary(&program).unwrap().equiv_up_to_global_phase(&unitary(&optimized).unwrap(), 1e-9));
+    }
+}
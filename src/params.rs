@@ -0,0 +1,136 @@
+//! Free parameters of parameterized circuits and late binding of their values.
+//!
+//! A free parameter is an identifier used in a gate application outside of gate declarations,
+//! e.g. `theta0` in `rz(theta0) q[0];`. Identifiers in gate bodies are gate parameters and are
+//! not free.
+
+use std::collections::{BTreeSet, HashMap};
+
+use errors::CircuitError;
+use parser::{Expr, Program, Statement};
+
+fn collect_ids(expr: &Expr, names: &mut BTreeSet<String>) {
+    match *expr {
+        Expr::Real(_) | Expr::NnInteger(_) | Expr::Pi => {}
+        Expr::Id(ref name) => {
+            names.insert(name.clone());
+        }
+        Expr::Add(ref lhs, ref rhs) | Expr::Sub(ref lhs, ref rhs) | Expr::Mul(ref lhs, ref rhs) |
+        Expr::Div(ref lhs, ref rhs) | Expr::Pow(ref lhs, ref rhs) => {
+            collect_ids(lhs, names);
+            collect_ids(rhs, names);
+        }
+        Expr::Neg(ref v) | Expr::UnaryOp(_, ref v) => collect_ids(v, names),
+    }
+}
+
+fn collect_statement(stmt: &Statement, names: &mut BTreeSet<String>) {
+    match *stmt {
+        Statement::Apply(_, ref params, _) => {
+            for p in params {
+                collect_ids(p, names);
+            }
+        }
+        Statement::If(_, _, ref body) => {
+            for stmt in body {
+                collect_statement(stmt, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Names of all free parameters of a program, sorted.
+pub fn free_parameters(program: &Program) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    for stmt in program {
+        collect_statement(stmt, &mut names);
+    }
+    names.into_iter().collect()
+}
+
+impl Expr {
+    /// Replaces identifiers found in `exprs` by the given expressions. Nothing is simplified.
+    pub fn substitute(&self, exprs: &HashMap<String, Expr>) -> Expr {
+        let sub = |e: &Expr| Box::new(e.substitute(exprs));
+        match *self {
+            Expr::Id(ref name) => exprs.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Expr::Add(ref lhs, ref rhs) => Expr::Add(sub(lhs), sub(rhs)),
+            Expr::Sub(ref lhs, ref rhs) => Expr::Sub(sub(lhs), sub(rhs)),
+            Expr::Mul(ref lhs, ref rhs) => Expr::Mul(sub(lhs), sub(rhs)),
+            Expr::Div(ref lhs, ref rhs) => Expr::Div(sub(lhs), sub(rhs)),
+            Expr::Pow(ref lhs, ref rhs) => Expr::Pow(sub(lhs), sub(rhs)),
+            Expr::Neg(ref v) => Expr::Neg(sub(v)),
+            Expr::UnaryOp(op, ref v) => Expr::UnaryOp(op, sub(v)),
+            Expr::Real(_) | Expr::NnInteger(_) | Expr::Pi => self.clone(),
+        }
+    }
+}
+
+fn bind_statement(stmt: &Statement, values: &HashMap<String, f64>) -> Statement {
+    match *stmt {
+        Statement::Apply(ref name, ref params, ref qargs) => {
+            let params = params.iter().map(|p| {
+                if values.keys().any(|name| p.depends_on(name)) {
+                    p.simplify_with(values)
+                } else {
+                    p.clone()
+                }
+            }).collect();
+            Statement::Apply(name.clone(), params, qargs.clone())
+        }
+        Statement::If(ref c, val, ref body) => {
+            Statement::If(c.clone(), val, body.iter().map(|s| bind_statement(s, values)).collect())
+        }
+        _ => stmt.clone(),
+    }
+}
+
+/// Substitutes values for free parameters. Parameters missing in `values` are left free, so a
+/// subset can be bound. Expressions using a bound parameter are simplified; other expressions
+/// are kept as they are.
+///
+/// Returns an error if `values` has a name which is not a free parameter of the program.
+pub fn bind(program: &Program, values: &HashMap<String, f64>) -> Result<Program, CircuitError> {
+    let free = free_parameters(program);
+    let mut unknown: Vec<&String> = values.keys().filter(|name| !free.contains(name)).collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        let names: Vec<&str> = unknown.iter().map(|s| s.as_str()).collect();
+        return Err(CircuitError::new(format!("Unknown parameter: {}.", names.join(", "))));
+    }
+    Ok(program.iter().map(|s| bind_statement(s, values)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    const ANSATZ: &str = "qreg q[2]; creg c[2];\n\
+                          gate layer(a) x { rz(a) x; }\n\
+                          layer(theta1) q[0]; rz(theta0 + pi) q[1]; if (c == 1) ry(phi / 2) q[0];\n\
+                          cx q[0], q[1];";
+
+    #[test]
+    fn list_free_parameters() {
+        assert_eq!(free_parameters(&parse(ANSATZ)), vec!["phi", "theta0", "theta1"]);
+        assert!(free_parameters(&parse("qreg q[1]; rz(pi) q[0];")).is_empty());
+    }
+
+    #[test]
+    fn bind_parameters() {
+        let mut values = HashMap::new();
+        values.insert("theta0".to_owned(), 0.5);
+        values.insert("phi".to_owned(), 2.0);
+        let bound = bind(&parse(ANSATZ), &values).unwrap();
+        assert_eq!(bound, parse("qreg q[2]; creg c[2];\n\
+                                 gate layer(a) x { rz(a) x; }\n\
+                                 layer(theta1) q[0]; rz(pi + 0.5) q[1]; if (c == 1) ry(1) q[0];\n\
+                                 cx q[0], q[1];"));
+        assert_eq!(free_parameters(&bound), vec!["theta1"]);
+
+        values.insert("a".to_owned(), 1.0);
+        assert!(bind(&parse(ANSATZ), &values).is_err());
+    }
+}
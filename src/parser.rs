@@ -1,5 +1,15 @@
+use std::collections::HashMap;
 use std::f64;
+use std::iter::Peekable;
+use std::path::Path;
+use std::rc::Rc;
 
+use diagnostic::Span;
+use errors::ParseError;
+use lexer::{self, TokenResult};
+use tokens::{Token, TokenType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnaryOp {
     Sin,
     Cos,
@@ -11,6 +21,7 @@ pub enum UnaryOp {
 
 pub type OpenqasmVersion = f64;
 pub type GateName = String;
+pub type RegName = String;
 pub type PathString = String;
 pub type CommentString = Vec<u8>;
 pub type NnInteger = u32;
@@ -20,26 +31,53 @@ pub type QArgs = Vec<QReg>;
 
 pub type Program = Vec<Statement>;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     Openqasm(OpenqasmVersion),
     Include(PathString),
     Comment(CommentString),
+    Qreg(RegName, NnInteger),
+    Creg(RegName, NnInteger),
     If(CReg, NnInteger, Program),
     Measure(QReg, CReg),
     Opaque(GateName, Params, QArgs),
     Gate(GateName, Params, QArgs, Program),
     Reset(QReg),
-    Barrier,
+    Barrier(QArgs),
     Apply(GateName, Params, QArgs),
 }
 
 pub enum Predicate {
 }
 
+/// A quantum argument: a whole register or a single qubit of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum QReg {
+    Reg(RegName),
+    Bit(RegName, NnInteger),
 }
 
+/// A classical argument: a whole register or a single bit of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CReg {
+    Reg(RegName),
+    Bit(RegName, NnInteger),
+}
+
+impl QReg {
+    pub fn name(&self) -> &str {
+        match *self {
+            QReg::Reg(ref name) | QReg::Bit(ref name, _) => name,
+        }
+    }
+}
+
+impl CReg {
+    pub fn name(&self) -> &str {
+        match *self {
+            CReg::Reg(ref name) | CReg::Bit(ref name, _) => name,
+        }
+    }
 }
 
 impl UnaryOp {
@@ -55,6 +93,7 @@ impl UnaryOp {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Real(f64),
     NnInteger(usize),
@@ -85,4 +124,474 @@ impl Expr {
             Expr::UnaryOp(ref op, ref v) => op.apply(v.eval()),
         }
     }
+
+    /// Evaluates the expression, looking up identifiers in `values`. Returns `None` if an
+    /// identifier is not found.
+    pub fn eval_with(&self, values: &HashMap<String, f64>) -> Option<f64> {
+        Some(match *self {
+            Expr::Id(ref name) => *values.get(name)?,
+            Expr::Add(ref lhs, ref rhs) => lhs.eval_with(values)? + rhs.eval_with(values)?,
+            Expr::Sub(ref lhs, ref rhs) => lhs.eval_with(values)? - rhs.eval_with(values)?,
+            Expr::Mul(ref lhs, ref rhs) => lhs.eval_with(values)? * rhs.eval_with(values)?,
+            Expr::Div(ref lhs, ref rhs) => lhs.eval_with(values)? / rhs.eval_with(values)?,
+            Expr::Neg(ref v) => -v.eval_with(values)?,
+            Expr::Pow(ref lhs, ref rhs) => lhs.eval_with(values)?.powf(rhs.eval_with(values)?),
+            Expr::UnaryOp(ref op, ref v) => op.apply(v.eval_with(values)?),
+            _ => self.eval(),
+        })
+    }
+}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// Parses OpenQASM source code. `include` statements are kept as they are.
+pub fn parse_str(code: &str, filename: &Path) -> ParseResult<Program> {
+    parse(lexer::tokenize(code.into(), filename.into()), filename.into())
+}
+
+/// Parses OpenQASM source code like `parse_str`, and returns the source span of every top-level
+/// statement along with the program.
+pub fn parse_str_with_spans(code: &str, filename: &Path) -> ParseResult<(Program, Vec<Span>)> {
+    let mut parser = Parser {
+        tokens: lexer::tokenize(code.into(), filename.into()).peekable(),
+        filename: filename.into(),
+        last_pos: 0,
+        spans: vec![],
+    };
+    let program = parser.program(false)?;
+    Ok((program, parser.spans))
+}
+
+/// Parses a token stream. Comments between statements are kept as `Statement::Comment`,
+/// comments inside a statement are dropped.
+pub fn parse<I: Iterator<Item=TokenResult>>(tokens: I, filename: Rc<Path>) -> ParseResult<Program> {
+    let mut parser = Parser {
+        tokens: tokens.peekable(),
+        filename,
+        last_pos: 0,
+        spans: vec![],
+    };
+    parser.program(false)
+}
+
+struct Parser<I: Iterator<Item=TokenResult>> {
+    tokens: Peekable<I>,
+    filename: Rc<Path>,
+    last_pos: usize,
+    /// Spans of the top-level statements parsed so far.
+    spans: Vec<Span>,
+}
+
+impl<I: Iterator<Item=TokenResult>> Parser<I> {
+    fn error<T>(&self, tok: Option<&Token>, errstr: &str) -> ParseResult<T> {
+        match tok {
+            Some(tok) => Err(ParseError::new(tok.filename.clone(), tok.pos, tok.len, errstr.to_owned())),
+            None => Err(ParseError::new(self.filename.clone(),
+                                        self.last_pos,
+                                        0,
+                                        format!("{} (unexpected end of file)", errstr))),
+        }
+    }
+
+    /// Peeks the next token. If `keep_comment` is false, comments are skipped.
+    fn peek(&mut self, keep_comment: bool) -> ParseResult<Option<&Token>> {
+        loop {
+            let skip = match self.tokens.peek() {
+                None => return Ok(None),
+                Some(&Err(_)) => false,
+                Some(Ok(tok)) => !keep_comment && tok.is_comment(),
+            };
+            if !skip {
+                break;
+            }
+            self.tokens.next();
+        }
+        if let Some(&Err(_)) = self.tokens.peek() {
+            if let Some(Err(e)) = self.tokens.next() {
+                return Err(e.into());
+            }
+        }
+        match self.tokens.peek() {
+            Some(Ok(tok)) => Ok(Some(tok)),
+            _ => Ok(None),
+        }
+    }
+
+    fn next(&mut self) -> ParseResult<Option<Token>> {
+        self.peek(false)?;
+        match self.tokens.next() {
+            Some(Ok(tok)) => {
+                self.last_pos = tok.pos + tok.len;
+                Ok(Some(tok))
+            }
+            Some(Err(e)) => Err(e.into()),
+            None => Ok(None),
+        }
+    }
+
+    fn peek_type(&mut self) -> ParseResult<Option<TokenType>> {
+        Ok(self.peek(false)?.map(|tok| tok.token.clone()))
+    }
+
+    fn expect(&mut self, expected: TokenType, errstr: &str) -> ParseResult<Token> {
+        let tok = self.next()?;
+        match tok {
+            Some(ref tok) if tok.token == expected => {}
+            _ => return self.error(tok.as_ref(), errstr),
+        }
+        Ok(tok.unwrap())
+    }
+
+    fn consume_if(&mut self, expected: TokenType) -> ParseResult<bool> {
+        if self.peek_type()? == Some(expected) {
+            self.next()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn identifier(&mut self) -> ParseResult<String> {
+        let tok = self.next()?;
+        if let Some(Token { token: TokenType::Identifier(ref s), .. }) = tok {
+            return Ok(s.clone());
+        }
+        self.error(tok.as_ref(), "Expected an identifier.")
+    }
+
+    fn uint(&mut self) -> ParseResult<NnInteger> {
+        let tok = self.next()?;
+        if let Some(Token { token: TokenType::UInt(n), .. }) = tok {
+            if n <= NnInteger::MAX as usize {
+                return Ok(n as NnInteger);
+            }
+        }
+        self.error(tok.as_ref(), "Expected a non-negative integer.")
+    }
+
+    fn program(&mut self, in_gate: bool) -> ParseResult<Program> {
+        let mut program = Vec::new();
+        loop {
+            let (is_comment, start) = match self.peek(true)? {
+                None => break,
+                Some(tok) => {
+                    if in_gate && tok.token == TokenType::RBrace {
+                        break;
+                    }
+                    (tok.is_comment(), tok.pos)
+                }
+            };
+            if is_comment {
+                if let Some(Ok(Token { token: TokenType::Comment(s), len, .. })) = self.tokens.next() {
+                    if !in_gate {
+                        // The position of a comment token is after the `//`.
+                        self.spans.push(Span { pos: start - 2, len: len + 2 });
+                    }
+                    program.push(Statement::Comment(s));
+                }
+                continue;
+            }
+            program.push(self.statement(in_gate)?);
+            if !in_gate {
+                self.spans.push(Span { pos: start, len: self.last_pos - start });
+            }
+        }
+        Ok(program)
+    }
+
+    fn statement(&mut self, in_gate: bool) -> ParseResult<Statement> {
+        let tok = self.next()?.unwrap();
+        match tok.token {
+            TokenType::Openqasm if !in_gate => {
+                let ver = self.next()?;
+                let ver = match ver {
+                    Some(Token { token: TokenType::Real(v), .. }) => v,
+                    Some(Token { token: TokenType::UInt(v), .. }) => v as f64,
+                    _ => return self.error(ver.as_ref(), "Expected a version number."),
+                };
+                self.expect(TokenType::Semicolon, "Expected ';'.")?;
+                Ok(Statement::Openqasm(ver))
+            }
+            TokenType::Include if !in_gate => {
+                let path = self.next()?;
+                let path = match path {
+                    Some(Token { token: TokenType::StringLiteral(ref s), .. }) => s.clone(),
+                    _ => return self.error(path.as_ref(), "Expected a file name."),
+                };
+                self.expect(TokenType::Semicolon, "Expected ';'.")?;
+                Ok(Statement::Include(path))
+            }
+            TokenType::Qreg | TokenType::Creg if !in_gate => {
+                let name = self.identifier()?;
+                self.expect(TokenType::LSqBracket, "Expected '['.")?;
+                let size = self.uint()?;
+                self.expect(TokenType::RSqBracket, "Expected ']'.")?;
+                self.expect(TokenType::Semicolon, "Expected ';'.")?;
+                if tok.token == TokenType::Qreg {
+                    Ok(Statement::Qreg(name, size))
+                } else {
+                    Ok(Statement::Creg(name, size))
+                }
+            }
+            TokenType::Gate if !in_gate => {
+                let name = self.identifier()?;
+                let params = self.param_names()?;
+                let qargs = self.qargs(true)?;
+                self.expect(TokenType::LBrace, "Expected '{'.")?;
+                let body = self.program(true)?;
+                self.expect(TokenType::RBrace, "Expected '}'.")?;
+                Ok(Statement::Gate(name, params, qargs, body))
+            }
+            TokenType::Opaque if !in_gate => {
+                let name = self.identifier()?;
+                let params = self.param_names()?;
+                let qargs = self.qargs(true)?;
+                self.expect(TokenType::Semicolon, "Expected ';'.")?;
+                Ok(Statement::Opaque(name, params, qargs))
+            }
+            TokenType::If if !in_gate => {
+                self.expect(TokenType::LParen, "Expected '('.")?;
+                let creg = self.identifier()?;
+                self.expect(TokenType::DoubleEqual, "Expected '=='.")?;
+                let val = self.uint()?;
+                self.expect(TokenType::RParen, "Expected ')'.")?;
+                let next = self.next()?;
+                match next {
+                    Some(tok) => {
+                        let stmt = self.qop(tok, false)?;
+                        Ok(Statement::If(CReg::Reg(creg), val, vec![stmt]))
+                    }
+                    None => self.error(None, "Expected a quantum operation."),
+                }
+            }
+            _ => self.qop(tok, in_gate),
+        }
+    }
+
+    fn qop(&mut self, tok: Token, in_gate: bool) -> ParseResult<Statement> {
+        let stmt = match tok.token {
+            TokenType::Measure if !in_gate => {
+                let q = self.qarg(false)?;
+                self.expect(TokenType::Arrow, "Expected '->'.")?;
+                let c = self.carg()?;
+                Statement::Measure(q, c)
+            }
+            TokenType::Reset if !in_gate => Statement::Reset(self.qarg(false)?),
+            TokenType::Barrier => Statement::Barrier(self.qargs(in_gate)?),
+            TokenType::U | TokenType::CX | TokenType::Identifier(_) => {
+                let name = match tok.token {
+                    TokenType::U => "U".to_owned(),
+                    TokenType::CX => "CX".to_owned(),
+                    TokenType::Identifier(ref s) => s.clone(),
+                    _ => unreachable!(),
+                };
+                let params = if self.consume_if(TokenType::LParen)? {
+                    self.exprs(TokenType::RParen)?
+                } else {
+                    vec![]
+                };
+                let qargs = self.qargs(in_gate)?;
+                Statement::Apply(name, params, qargs)
+            }
+            _ => return self.error(Some(&tok), "Unexpected token."),
+        };
+        self.expect(TokenType::Semicolon, "Expected ';'.")?;
+        Ok(stmt)
+    }
+
+    fn param_names(&mut self) -> ParseResult<Params> {
+        let mut params = vec![];
+        if self.consume_if(TokenType::LParen)? {
+            if self.consume_if(TokenType::RParen)? {
+                return Ok(params);
+            }
+            loop {
+                params.push(Expr::Id(self.identifier()?));
+                if !self.consume_if(TokenType::Comma)? {
+                    break;
+                }
+            }
+            self.expect(TokenType::RParen, "Expected ')'.")?;
+        }
+        Ok(params)
+    }
+
+    fn qargs(&mut self, id_only: bool) -> ParseResult<QArgs> {
+        let mut qargs = vec![self.qarg(id_only)?];
+        while self.consume_if(TokenType::Comma)? {
+            qargs.push(self.qarg(id_only)?);
+        }
+        Ok(qargs)
+    }
+
+    fn qarg(&mut self, id_only: bool) -> ParseResult<QReg> {
+        let name = self.identifier()?;
+        if !id_only && self.consume_if(TokenType::LSqBracket)? {
+            let idx = self.uint()?;
+            self.expect(TokenType::RSqBracket, "Expected ']'.")?;
+            Ok(QReg::Bit(name, idx))
+        } else {
+            Ok(QReg::Reg(name))
+        }
+    }
+
+    fn carg(&mut self) -> ParseResult<CReg> {
+        Ok(match self.qarg(false)? {
+            QReg::Reg(name) => CReg::Reg(name),
+            QReg::Bit(name, idx) => CReg::Bit(name, idx),
+        })
+    }
+
+    fn exprs(&mut self, close: TokenType) -> ParseResult<Params> {
+        let mut exprs = vec![];
+        if self.consume_if(close.clone())? {
+            return Ok(exprs);
+        }
+        loop {
+            exprs.push(self.expr()?);
+            if !self.consume_if(TokenType::Comma)? {
+                break;
+            }
+        }
+        self.expect(close, "Expected ')'.")?;
+        Ok(exprs)
+    }
+
+    fn expr(&mut self) -> ParseResult<Expr> {
+        let mut lhs = self.term()?;
+        loop {
+            match self.peek_type()? {
+                Some(TokenType::Plus) => {
+                    self.next()?;
+                    lhs = Expr::Add(Box::new(lhs), Box::new(self.term()?));
+                }
+                Some(TokenType::Minus) => {
+                    self.next()?;
+                    lhs = Expr::Sub(Box::new(lhs), Box::new(self.term()?));
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    fn term(&mut self) -> ParseResult<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            match self.peek_type()? {
+                Some(TokenType::Times) => {
+                    self.next()?;
+                    lhs = Expr::Mul(Box::new(lhs), Box::new(self.unary()?));
+                }
+                Some(TokenType::Devide) => {
+                    self.next()?;
+                    lhs = Expr::Div(Box::new(lhs), Box::new(self.unary()?));
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        if self.consume_if(TokenType::Minus)? {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> ParseResult<Expr> {
+        let base = self.primary()?;
+        if self.consume_if(TokenType::Power)? {
+            Ok(Expr::Pow(Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        let tok = self.next()?;
+        let op = match tok {
+            Some(Token { token: TokenType::Real(v), .. }) => return Ok(Expr::Real(v)),
+            Some(Token { token: TokenType::UInt(v), .. }) => return Ok(Expr::NnInteger(v)),
+            Some(Token { token: TokenType::Pi, .. }) => return Ok(Expr::Pi),
+            Some(Token { token: TokenType::Identifier(ref s), .. }) => return Ok(Expr::Id(s.clone())),
+            Some(Token { token: TokenType::LParen, .. }) => {
+                let e = self.expr()?;
+                self.expect(TokenType::RParen, "Expected ')'.")?;
+                return Ok(e);
+            }
+            Some(Token { token: TokenType::Sin, .. }) => UnaryOp::Sin,
+            Some(Token { token: TokenType::Cos, .. }) => UnaryOp::Cos,
+            Some(Token { token: TokenType::Tan, .. }) => UnaryOp::Tan,
+            Some(Token { token: TokenType::Exp, .. }) => UnaryOp::Exp,
+            Some(Token { token: TokenType::Ln, .. }) => UnaryOp::Ln,
+            Some(Token { token: TokenType::Sqrt, .. }) => UnaryOp::Sqrt,
+            _ => return self.error(tok.as_ref(), "Expected an expression."),
+        };
+        self.expect(TokenType::LParen, "Expected '('.")?;
+        let e = self.expr()?;
+        self.expect(TokenType::RParen, "Expected ')'.")?;
+        Ok(Expr::UnaryOp(op, Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    #[test]
+    fn parse_statements() {
+        let prog = parse("OPENQASM 2.0;\n\
+                          include \"qelib1.inc\";\n\
+                          qreg q[2];\n\
+                          creg c[2];\n\
+                          // bell\n\
+                          gate bell a, b { U(pi/2, 0, pi) a; CX a, b; }\n\
+                          bell q[0], q[1];\n\
+                          barrier q;\n\
+                          measure q -> c;\n\
+                          if (c == 3) reset q[1];");
+        let bit = |i| QReg::Bit("q".to_owned(), i);
+        assert_eq!(prog, vec![
+            Statement::Openqasm(2.0),
+            Statement::Include("qelib1.inc".to_owned()),
+            Statement::Qreg("q".to_owned(), 2),
+            Statement::Creg("c".to_owned(), 2),
+            Statement::Comment(b" bell".to_vec()),
+            Statement::Gate("bell".to_owned(), vec![],
+                            vec![QReg::Reg("a".to_owned()), QReg::Reg("b".to_owned())],
+                            vec![Statement::Apply("U".to_owned(),
+                                                  vec![Expr::Div(Box::new(Expr::Pi), Box::new(Expr::NnInteger(2))),
+                                                       Expr::NnInteger(0),
+                                                       Expr::Pi],
+                                                  vec![QReg::Reg("a".to_owned())]),
+                                 Statement::Apply("CX".to_owned(), vec![],
+                                                  vec![QReg::Reg("a".to_owned()), QReg::Reg("b".to_owned())])]),
+            Statement::Apply("bell".to_owned(), vec![], vec![bit(0), bit(1)]),
+            Statement::Barrier(vec![QReg::Reg("q".to_owned())]),
+            Statement::Measure(QReg::Reg("q".to_owned()), CReg::Reg("c".to_owned())),
+            Statement::If(CReg::Reg("c".to_owned()), 3, vec![Statement::Reset(bit(1))]),
+        ]);
+    }
+
+    #[test]
+    fn parse_expr_precedence() {
+        let prog = parse("u3(-1+2*3^2, -x^2, sin(pi)/2) q;");
+        let params = match prog[0] {
+            Statement::Apply(_, ref params, _) => params.clone(),
+            _ => panic!(),
+        };
+        assert_eq!(params[0].eval(), 17.0);
+        assert_eq!(params[1], Expr::Neg(Box::new(Expr::Pow(Box::new(Expr::Id("x".to_owned())),
+                                                           Box::new(Expr::NnInteger(2))))));
+        assert!(params[2].eval().abs() < 1e-15);
+    }
+
+    #[test]
+    fn parse_error() {
+        let e = parse_str("qreg q[2]\ncreg c[2];", Path::new("test.qasm")).unwrap_err();
+        assert_eq!(e.pos, 10);
+        assert!(parse_str("measure q", Path::new("test.qasm")).is_err());
+    }
 }
@@ -0,0 +1,333 @@
+//! Printing programs as OpenQASM 2.0 source.
+//!
+//! The output can be parsed again and gives the same AST, unless `PrintOptions::snap_angles` is
+//! set. Expressions are printed with the fewest parentheses the precedence of the operators
+//! allows, e.g. `-(a + b)*c^2`.
+
+use std::fmt;
+
+use parser::{CReg, Expr, QReg, Statement};
+
+/// Options for printing programs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintOptions {
+    /// Whether `Statement::Comment`s are printed.
+    pub comments: bool,
+    /// Number of spaces to indent gate bodies with.
+    pub indent: usize,
+    /// Whether reals within `angle::TOLERANCE` of a multiple of pi are printed as one, e.g.
+    /// `pi/4` rather than `0.7853981633974483`. Off by default, since the printed values differ
+    /// slightly.
+    pub snap_angles: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> PrintOptions {
+        PrintOptions { comments: true, indent: 2, snap_angles: false }
+    }
+}
+
+/// Formats a number so that it is lexed as a real again, e.g. `2.0` rather than `2`. Infinities
+/// and NaN have no literal and are written as divisions by zero, e.g. `(1.0/0.0)`.
+fn real(v: f64) -> String {
+    if v.is_nan() {
+        return "(0.0/0.0)".to_owned();
+    } else if v.is_infinite() {
+        return if v > 0.0 { "(1.0/0.0)" } else { "(-1.0/0.0)" }.to_owned();
+    }
+    let s = v.to_string();
+    if s.contains('.') { s } else { s + ".0" }
+}
+
+// Precedence levels, following the grammar of the parser.
+const ADDITIVE: u8 = 1;
+const MULTIPLICATIVE: u8 = 2;
+const UNARY: u8 = 3;
+const POWER: u8 = 4;
+const PRIMARY: u8 = 5;
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match *self {
+            Expr::Add(..) | Expr::Sub(..) => ADDITIVE,
+            Expr::Mul(..) | Expr::Div(..) => MULTIPLICATIVE,
+            Expr::Neg(..) => UNARY,
+            Expr::Real(v) if v.is_sign_negative() && v.is_finite() => UNARY,
+            Expr::Pow(..) => POWER,
+            _ => PRIMARY,
+        }
+    }
+
+    /// Writes the expression, with parentheses if its precedence is lower than `min`.
+    fn fmt_prec(&self, f: &mut fmt::Formatter, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            write!(f, "(")?;
+            self.fmt_prec(f, 0)?;
+            return write!(f, ")");
+        }
+        match *self {
+            Expr::Real(v) => write!(f, "{}", real(v)),
+            Expr::NnInteger(v) => write!(f, "{}", v),
+            Expr::Pi => write!(f, "pi"),
+            Expr::Id(ref name) => write!(f, "{}", name),
+            Expr::Add(ref lhs, ref rhs) => binary(f, lhs, " + ", rhs, ADDITIVE),
+            Expr::Sub(ref lhs, ref rhs) => binary(f, lhs, " - ", rhs, ADDITIVE),
+            Expr::Mul(ref lhs, ref rhs) => binary(f, lhs, "*", rhs, MULTIPLICATIVE),
+            Expr::Div(ref lhs, ref rhs) => binary(f, lhs, "/", rhs, MULTIPLICATIVE),
+            Expr::Neg(ref v) => {
+                write!(f, "-")?;
+                v.fmt_prec(f, UNARY)
+            }
+            Expr::Pow(ref base, ref exp) => {
+                base.fmt_prec(f, PRIMARY)?;
+                write!(f, "^")?;
+                exp.fmt_prec(f, UNARY)
+            }
+            Expr::UnaryOp(op, ref v) => {
+                write!(f, "{}(", format!("{:?}", op).to_lowercase())?;
+                v.fmt_prec(f, 0)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Writes a left-associative binary operation.
+fn binary(f: &mut fmt::Formatter, lhs: &Expr, op: &str, rhs: &Expr, prec: u8) -> fmt::Result {
+    lhs.fmt_prec(f, prec)?;
+    write!(f, "{}", op)?;
+    rhs.fmt_prec(f, prec + 1)
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_prec(f, 0)
+    }
+}
+
+impl fmt::Display for QReg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QReg::Reg(ref name) => write!(f, "{}", name),
+            QReg::Bit(ref name, idx) => write!(f, "{}[{}]", name, idx),
+        }
+    }
+}
+
+impl fmt::Display for CReg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CReg::Reg(ref name) => write!(f, "{}", name),
+            CReg::Bit(ref name, idx) => write!(f, "{}[{}]", name, idx),
+        }
+    }
+}
+
+/// Replaces the reals in an expression which are close to a multiple of pi by the multiple.
+fn snap_angles(e: &Expr) -> Expr {
+    let snap = |e: &Expr| Box::new(snap_angles(e));
+    match *e {
+        Expr::Real(_) => match e.snapped_angle() {
+            Some(a) if a.is_exact() && a.pi_fraction().0 != 0 => a.to_expr(),
+            _ => e.clone(),
+        },
+        Expr::Add(ref lhs, ref rhs) => Expr::Add(snap(lhs), snap(rhs)),
+        Expr::Sub(ref lhs, ref rhs) => Expr::Sub(snap(lhs), snap(rhs)),
+        Expr::Mul(ref lhs, ref rhs) => Expr::Mul(snap(lhs), snap(rhs)),
+        Expr::Div(ref lhs, ref rhs) => Expr::Div(snap(lhs), snap(rhs)),
+        Expr::Pow(ref lhs, ref rhs) => Expr::Pow(snap(lhs), snap(rhs)),
+        Expr::Neg(ref v) => Expr::Neg(snap(v)),
+        Expr::UnaryOp(op, ref v) => Expr::UnaryOp(op, snap(v)),
+        Expr::NnInteger(_) | Expr::Pi | Expr::Id(_) => e.clone(),
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// `name(params) args`, without parentheses if there are no parameters.
+fn signature<T: fmt::Display>(name: &str, params: &[Expr], args: &[T]) -> String {
+    if params.is_empty() {
+        format!("{} {}", name, join(args))
+    } else {
+        format!("{}({}) {}", name, join(params), join(args))
+    }
+}
+
+fn write_statement(out: &mut String, stmt: &Statement, options: &PrintOptions, depth: usize) {
+    let indent = " ".repeat(depth * options.indent);
+    let line = match *stmt {
+        Statement::Openqasm(ver) => format!("OPENQASM {};", real(ver)),
+        Statement::Include(ref path) => format!("include \"{}\";", path),
+        Statement::Comment(ref text) => {
+            if !options.comments {
+                return;
+            }
+            format!("//{}", String::from_utf8_lossy(text))
+        }
+        Statement::Qreg(ref name, size) => format!("qreg {}[{}];", name, size),
+        Statement::Creg(ref name, size) => format!("creg {}[{}];", name, size),
+        Statement::If(ref c, val, ref body) => {
+            for stmt in body {
+                let mut op = String::new();
+                write_statement(&mut op, stmt, options, 0);
+                out.push_str(&format!("{}if ({} == {}) {}", indent, c, val, op));
+            }
+            return;
+        }
+        Statement::Measure(ref q, ref c) => format!("measure {} -> {};", q, c),
+        Statement::Opaque(ref name, ref params, ref qargs) => format!("opaque {};", signature(name, params, qargs)),
+        Statement::Gate(ref name, ref params, ref qargs, ref body) => {
+            out.push_str(&format!("{}gate {} {{\n", indent, signature(name, params, qargs)));
+            for stmt in body {
+                write_statement(out, stmt, options, depth + 1);
+            }
+            "}".to_owned()
+        }
+        Statement::Reset(ref q) => format!("reset {};", q),
+        Statement::Barrier(ref qargs) => format!("barrier {};", join(qargs)),
+        Statement::Apply(ref name, ref params, ref qargs) if options.snap_angles => {
+            let params: Vec<Expr> = params.iter().map(snap_angles).collect();
+            format!("{};", signature(name, &params, qargs))
+        }
+        Statement::Apply(ref name, ref params, ref qargs) => format!("{};", signature(name, params, qargs)),
+    };
+    out.push_str(&indent);
+    out.push_str(&line);
+    out.push('\n');
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        write_statement(&mut out, self, &PrintOptions::default(), 0);
+        write!(f, "{}", out.trim_end_matches('\n'))
+    }
+}
+
+/// Prints a program, one statement per line.
+pub fn print_program(program: &[Statement], options: &PrintOptions) -> String {
+    let mut out = String::new();
+    for stmt in program {
+        write_statement(&mut out, stmt, options, 0);
+    }
+    out
+}
+
+/// Prints a program with the default options.
+pub fn to_qasm(program: &[Statement]) -> String {
+    print_program(program, &PrintOptions::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::UnaryOp;
+    use rng::Rng;
+    use test_util::{expr, parse};
+
+    #[test]
+    fn print_program_text() {
+        let code = "OPENQASM 2.0;\n\
+                    include \"qelib1.inc\";\n\
+                    // registers\n\
+                    qreg q[2];\n\
+                    creg c[2];\n\
+                    opaque magic(a) x;\n\
+                    gate g(theta, phi) a, b {\n\
+                    \x20 // body\n\
+                    \x20 U(-(theta + phi)*2, theta^-phi^2, sin(pi/2)) a;\n\
+                    \x20 CX a, b;\n\
+                    \x20 barrier a, b;\n\
+                    }\n\
+                    g(1.0, 0.25) q[0], q[1];\n\
+                    measure q -> c;\n\
+                    if (c == 1) reset q[0];\n";
+        let prog = parse(code);
+        assert_eq!(to_qasm(&prog), code);
+        let options = PrintOptions { comments: false, indent: 4, ..PrintOptions::default() };
+        assert_eq!(print_program(&prog[..7], &options), "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\n\
+                                                        creg c[2];\nopaque magic(a) x;\ngate g(theta, phi) a, b {\n\
+                                                        \x20   U(-(theta + phi)*2, theta^-phi^2, sin(pi/2)) a;\n\
+                                                        \x20   CX a, b;\n    barrier a, b;\n}\n");
+        assert_eq!(prog[7].to_string(), "g(1.0, 0.25) q[0], q[1];");
+    }
+
+    #[test]
+    fn snapped_angles() {
+        let prog = parse("qreg q[1];\nU(0.7853981633974483, 0, 0) q[0];\nU(0.5, 0.0, -1.5707963267948966*2) q[0];");
+        let options = PrintOptions { snap_angles: true, ..PrintOptions::default() };
+        assert_eq!(print_program(&prog[1..], &options), "U(pi/4, 0, 0) q[0];\nU(0.5, 0.0, -(pi/2)*2) q[0];\n");
+        assert_eq!(to_qasm(&prog[1..2]), "U(0.7853981633974483, 0, 0) q[0];\n");
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        for &(src, printed) in &[("((a+b))+(c)", "a + b + c"), ("a-(b-c)", "a - (b - c)"), ("a*(b*c)", "a*(b*c)"),
+                                 ("(a/b)/c", "a/b/c"), ("-(a)", "-a"), ("-(-a)", "--a"), ("(-a)^2", "(-a)^2"),
+                                 ("a^(b^c)", "a^b^c"), ("(a^b)^c", "(a^b)^c"), ("a*-b", "a*-b"),
+                                 ("a^(-b)", "a^-b"), ("-(a*b)", "-(a*b)"), ("exp((a))", "exp(a)")] {
+            assert_eq!(expr(src).to_string(), printed);
+        }
+        let neg = Expr::Pow(Box::new(Expr::Real(-0.5)), Box::new(Expr::NnInteger(2)));
+        assert_eq!(neg.to_string(), "(-0.5)^2");
+    }
+
+    fn random_expr(rng: &mut Rng, depth: u32) -> Expr {
+        let boxed = |rng: &mut Rng| Box::new(random_expr(rng, depth - 1));
+        let choice = if depth == 0 { rng.next_u64() % 4 } else { rng.next_u64() % 11 };
+        match choice {
+            0 => Expr::Real((rng.next_u64() % 10000) as f64 / 64.0),
+            1 => Expr::NnInteger((rng.next_u64() % 100) as usize),
+            2 => Expr::Pi,
+            3 => Expr::Id(format!("p{}", rng.next_u64() % 3)),
+            4 => Expr::Add(boxed(rng), boxed(rng)),
+            5 => Expr::Sub(boxed(rng), boxed(rng)),
+            6 => Expr::Mul(boxed(rng), boxed(rng)),
+            7 => Expr::Div(boxed(rng), boxed(rng)),
+            8 => Expr::Neg(boxed(rng)),
+            9 => Expr::Pow(boxed(rng), boxed(rng)),
+            _ => {
+                let ops = [UnaryOp::Sin, UnaryOp::Cos, UnaryOp::Tan, UnaryOp::Exp, UnaryOp::Ln, UnaryOp::Sqrt];
+                Expr::UnaryOp(ops[(rng.next_u64() % 6) as usize], boxed(rng))
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = Rng::from_seed(37);
+        for _ in 0..300 {
+            let params: Vec<Expr> = (0..3).map(|_| random_expr(&mut rng, 4)).collect();
+            let q = QReg::Reg("q".to_owned());
+            let prog = vec![
+                Statement::Qreg("q".to_owned(), 1),
+                Statement::Comment(b" random".to_vec()),
+                Statement::Gate("g".to_owned(), vec![], vec![QReg::Reg("a".to_owned())],
+                                vec![Statement::Apply("U".to_owned(), params.clone(), vec![QReg::Reg("a".to_owned())])]),
+                Statement::Apply("U".to_owned(), params, vec![q]),
+            ];
+            let printed = to_qasm(&prog);
+            assert_eq!(parse(&printed), prog, "{}", printed);
+        }
+        // Reals close to multiples of pi are kept as they are.
+        let prog = parse("qreg q[1];\nU(3.14159265359, 0, 0.785398163) q[0];");
+        assert_eq!(parse(&to_qasm(&prog)), prog);
+        // Non-finite reals have no literal, so only their values survive.
+        let boxed = |v: f64| Box::new(Expr::Real(v));
+        let params = vec![Expr::Real(f64::INFINITY), Expr::Pow(boxed(f64::NEG_INFINITY), boxed(2.0)),
+                          Expr::Neg(boxed(f64::NAN))];
+        let prog = vec![Statement::Apply("U".to_owned(), params, vec![QReg::Reg("q".to_owned())])];
+        let printed = to_qasm(&prog);
+        assert_eq!(printed, "U((1.0/0.0), (-1.0/0.0)^2.0, -(0.0/0.0)) q;\n");
+        match parse(&format!("qreg q[1];\n{}", printed))[1] {
+            Statement::Apply(_, ref params, _) => {
+                assert_eq!(params[0].eval(), f64::INFINITY);
+                assert_eq!(params[1].eval(), f64::INFINITY);
+                assert!(params[2].eval().is_nan());
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,542 @@
+//! Routing of circuits onto hardware where two-qubit gates only act on connected qubits.
+//!
+//! A coupling map lists the pairs of connected physical qubits, in either direction. `route`
+//! places the qubits of a program on physical qubits and, whenever a two-qubit gate acts on
+//! qubits which are not connected, inserts swaps written as three `cx`. The swaps are chosen
+//! with the heuristic of SABRE (Li, Ding and Xie, 2019): among the swaps touching the qubits of
+//! the blocked gates, the one which brings these gates, and with a smaller weight the gates
+//! after them, closest to executable. A decay factor on recently swapped qubits keeps the
+//! search from going back and forth.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use dag::{DagCircuit, NodeId, Wire};
+use errors::CircuitError;
+use inline::GateTable;
+use lower::{LowerResult, Registers};
+use parser::{NnInteger, Program, QReg, Statement};
+
+/// The number of two-qubit gates after the blocked ones the heuristic looks at.
+const EXTENDED_SET_SIZE: usize = 20;
+/// The weight of the gates after the blocked ones in the heuristic.
+const EXTENDED_SET_WEIGHT: f64 = 0.5;
+const DECAY_DELTA: f64 = 0.001;
+/// The number of swaps after which the decay is reset.
+const DECAY_RESET: usize = 5;
+
+/// The connected pairs of physical qubits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CouplingMap {
+    edges: Vec<(usize, usize)>,
+    neighbours: Vec<Vec<usize>>,
+    /// The lengths of the shortest paths, `None` if there is none.
+    distances: Vec<Vec<Option<usize>>>,
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> LowerResult<()> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(CircuitError::new(format!("Expected '{}' but found '{}'.", expected, c))),
+        None => Err(CircuitError::new(format!("Expected '{}' but found the end.", expected))),
+    }
+}
+
+fn parse_index(chars: &mut Peekable<Chars>) -> LowerResult<usize> {
+    skip_whitespace(chars);
+    let mut number = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        number.push(c);
+        chars.next();
+    }
+    number.parse().map_err(|_| CircuitError::new("Expected a qubit index.".to_owned()))
+}
+
+impl CouplingMap {
+    /// A coupling map with the given edges. The physical qubits are numbered from 0 to the
+    /// largest index in `edges`.
+    pub fn new(edges: &[(usize, usize)]) -> LowerResult<CouplingMap> {
+        let num_qubits = edges.iter().map(|&(a, b)| a.max(b) + 1).max().unwrap_or(0);
+        let mut neighbours = vec![vec![]; num_qubits];
+        for &(a, b) in edges {
+            if a == b {
+                return Err(CircuitError::new(format!("Qubit {} cannot be coupled to itself.", a)));
+            }
+            if !neighbours[a].contains(&b) {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+        for n in &mut neighbours {
+            n.sort();
+        }
+        let distances = (0..num_qubits).map(|start| {
+            let mut dist = vec![None; num_qubits];
+            dist[start] = Some(0);
+            let mut queue = VecDeque::new();
+            queue.push_back(start);
+            while let Some(p) = queue.pop_front() {
+                for &n in &neighbours[p] {
+                    if dist[n].is_none() {
+                        dist[n] = dist[p].map(|d| d + 1);
+                        queue.push_back(n);
+                    }
+                }
+            }
+            dist
+        }).collect();
+        Ok(CouplingMap { edges: edges.to_vec(), neighbours, distances })
+    }
+
+    /// Reads a JSON list of edges such as `[[0, 1], [1, 2]]`.
+    pub fn from_json(json: &str) -> LowerResult<CouplingMap> {
+        let mut chars = json.chars().peekable();
+        let mut edges = vec![];
+        expect(&mut chars, '[')?;
+        skip_whitespace(&mut chars);
+        if chars.peek() == Some(&']') {
+            chars.next();
+        } else {
+            loop {
+                expect(&mut chars, '[')?;
+                let a = parse_index(&mut chars)?;
+                expect(&mut chars, ',')?;
+                let b = parse_index(&mut chars)?;
+                expect(&mut chars, ']')?;
+                edges.push((a, b));
+                skip_whitespace(&mut chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => break,
+                    _ => return Err(CircuitError::new("Expected ',' or ']' after an edge.".to_owned())),
+                }
+            }
+        }
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() {
+            return Err(CircuitError::new("Unexpected text after the list of edges.".to_owned()));
+        }
+        CouplingMap::new(&edges)
+    }
+
+    /// Qubits `0` to `n - 1` connected in a line.
+    pub fn line(n: usize) -> CouplingMap {
+        let edges: Vec<(usize, usize)> = (1..n).map(|i| (i - 1, i)).collect();
+        CouplingMap::new(&edges).expect("The edges are valid.")
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    pub fn edges(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+
+    pub fn neighbours(&self, qubit: usize) -> &[usize] {
+        &self.neighbours[qubit]
+    }
+
+    /// The number of edges on a shortest path between two qubits, `None` if they are not
+    /// connected at all.
+    pub fn distance(&self, a: usize, b: usize) -> Option<usize> {
+        self.distances[a][b]
+    }
+}
+
+/// The result of `route`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routed {
+    /// The program on a single register of physical qubits.
+    pub program: Program,
+    /// The physical qubit of each qubit of the input program (by the global index, see
+    /// `lower::Registers`) at the start.
+    pub initial_layout: Vec<usize>,
+    /// The physical qubit of each qubit of the input program at the end. The measurements in
+    /// `program` are already remapped; this is for reading the final state or for measurements
+    /// appended later.
+    pub final_layout: Vec<usize>,
+    pub num_swaps: usize,
+}
+
+/// The qubits of an operation, for operations which need them to be connected.
+fn connected_qubits(dag: &DagCircuit, id: NodeId) -> LowerResult<Option<(usize, usize)>> {
+    let stmt = dag.node(id).statement();
+    let is_gate = match *stmt {
+        Statement::Apply(..) => true,
+        Statement::If(_, _, ref body) => matches!(body[0], Statement::Apply(..)),
+        _ => false,
+    };
+    if !is_gate {
+        return Ok(None);
+    }
+    let qubits: Vec<usize> = dag.node(id).wires().iter().filter_map(|&w| match w {
+        Wire::Qubit(i) => Some(i),
+        Wire::Clbit(_) => None,
+    }).collect();
+    match qubits.len() {
+        1 => Ok(None),
+        2 => {
+            // The wires of a node come in the order of the arguments.
+            Ok(Some((qubits[0], qubits[1])))
+        }
+        n => {
+            let name = match *stmt {
+                Statement::Apply(ref name, ..) => name,
+                Statement::If(_, _, ref body) => match body[0] {
+                    Statement::Apply(ref name, ..) => name,
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            };
+            Err(CircuitError::new(format!("Gate '{}' acts on {} qubits; inline it before routing.", name, n)))
+        }
+    }
+}
+
+/// Replaces the qubit arguments of an operation by physical qubits.
+fn map_qubits(stmt: &Statement, regs: &Registers, layout: &[usize], physical: &str) -> Statement {
+    let map = |q: &QReg| QReg::Bit(physical.to_owned(), layout[regs.qubit_index(q)] as NnInteger);
+    match *stmt {
+        Statement::Apply(ref name, ref params, ref qargs) => {
+            Statement::Apply(name.clone(), params.clone(), qargs.iter().map(map).collect())
+        }
+        Statement::Barrier(ref qargs) => Statement::Barrier(qargs.iter().map(map).collect()),
+        Statement::Reset(ref q) => Statement::Reset(map(q)),
+        Statement::Measure(ref q, ref c) => Statement::Measure(map(q), c.clone()),
+        Statement::If(ref c, value, ref body) => {
+            Statement::If(c.clone(), value, body.iter().map(|s| map_qubits(s, regs, layout, physical)).collect())
+        }
+        ref other => other.clone(),
+    }
+}
+
+/// The state of the routing: where each qubit is and what has been written so far.
+struct Router<'a> {
+    coupling: &'a CouplingMap,
+    /// The physical qubit of each logical qubit.
+    layout: Vec<usize>,
+    /// The logical qubit on each physical qubit.
+    occupant: Vec<Option<usize>>,
+    decay: Vec<f64>,
+    cx: &'static str,
+    physical: String,
+    ops: Program,
+    num_swaps: usize,
+}
+
+impl<'a> Router<'a> {
+    fn distance(&self, gate: (usize, usize)) -> LowerResult<usize> {
+        let (a, b) = (self.layout[gate.0], self.layout[gate.1]);
+        self.coupling.distance(a, b)
+            .ok_or_else(|| CircuitError::new(format!("Physical qubits {} and {} are not connected.", a, b)))
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        let physical = self.physical.clone();
+        let q = |i: usize| QReg::Bit(physical.clone(), i as NnInteger);
+        for &(c, t) in &[(a, b), (b, a), (a, b)] {
+            self.ops.push(Statement::Apply(self.cx.to_owned(), vec![], vec![q(c), q(t)]));
+        }
+        self.occupant.swap(a, b);
+        for &p in &[a, b] {
+            if let Some(l) = self.occupant[p] {
+                self.layout[l] = p;
+            }
+        }
+        self.decay[a] += DECAY_DELTA;
+        self.decay[b] += DECAY_DELTA;
+        self.num_swaps += 1;
+        if self.num_swaps.is_multiple_of(DECAY_RESET) {
+            self.reset_decay();
+        }
+    }
+
+    fn reset_decay(&mut self) {
+        for d in &mut self.decay {
+            *d = 1.0;
+        }
+    }
+
+    /// The mean distance of gates with the layout after swapping `a` and `b`.
+    fn mean_distance(&self, gates: &[(usize, usize)], a: usize, b: usize) -> f64 {
+        if gates.is_empty() {
+            return 0.0;
+        }
+        let moved = |p: usize| if p == a { b } else if p == b { a } else { p };
+        let total: usize = gates.iter().map(|&(x, y)| {
+            self.coupling.distance(moved(self.layout[x]), moved(self.layout[y])).unwrap_or(0)
+        }).sum();
+        total as f64 / gates.len() as f64
+    }
+
+    /// Chooses a swap for the blocked gates `front` by the heuristic of SABRE.
+    fn best_swap(&self, front: &[(usize, usize)], extended: &[(usize, usize)]) -> (usize, usize) {
+        let mut candidates = BTreeSet::new();
+        for &(x, y) in front {
+            for &p in &[self.layout[x], self.layout[y]] {
+                for &n in self.coupling.neighbours(p) {
+                    candidates.insert((p.min(n), p.max(n)));
+                }
+            }
+        }
+        let score = |&(a, b): &(usize, usize)| {
+            let cost = self.mean_distance(front, a, b) + EXTENDED_SET_WEIGHT * self.mean_distance(extended, a, b);
+            self.decay[a].max(self.decay[b]) * cost
+        };
+        let mut best = None;
+        for swap in candidates {
+            let s = score(&swap);
+            if best.map(|(_, b)| s < b).unwrap_or(true) {
+                best = Some((swap, s));
+            }
+        }
+        best.expect("A blocked gate has a neighbour.").0
+    }
+
+    /// Swaps the first qubit of a gate along a shortest path until the gate is executable.
+    fn swap_along_path(&mut self, gate: (usize, usize)) -> LowerResult<()> {
+        while self.distance(gate)? > 1 {
+            let (a, b) = (self.layout[gate.0], self.layout[gate.1]);
+            let d = self.coupling.distance(a, b);
+            let next = *self.coupling.neighbours(a).iter()
+                .find(|&&n| self.coupling.distance(n, b).map(|x| x + 1) == d)
+                .expect("A shortest path continues.");
+            self.swap(a, next);
+        }
+        Ok(())
+    }
+}
+
+/// Two-qubit gates after the front layer, in the order they become executable.
+fn extended_set(dag: &DagCircuit, front: &[NodeId], indegree: &[usize]) -> LowerResult<Vec<(usize, usize)>> {
+    let mut indegree = indegree.to_vec();
+    let mut queue: VecDeque<NodeId> = front.iter().cloned().collect();
+    let mut gates = vec![];
+    while let Some(id) = queue.pop_front() {
+        for s in dag.successors(id) {
+            indegree[s] -= 1;
+            if indegree[s] == 0 {
+                if let Some(gate) = connected_qubits(dag, s)? {
+                    gates.push(gate);
+                    if gates.len() == EXTENDED_SET_SIZE {
+                        return Ok(gates);
+                    }
+                }
+                queue.push_back(s);
+            }
+        }
+    }
+    Ok(gates)
+}
+
+/// A name for the register of physical qubits which no classical register has.
+fn physical_register_name(regs: &Registers) -> String {
+    let mut name = "q".to_owned();
+    while regs.cregs().iter().any(|(c, _)| *c == name) {
+        name.push('_');
+    }
+    name
+}
+
+/// Lowers a program and routes it onto the coupling map. `initial_layout` gives the physical
+/// qubit of each qubit of the program, by the global index; by default qubit `i` starts on
+/// physical qubit `i`. Gates may act on at most two qubits, so other gates have to be inlined
+/// first.
+pub fn route(program: &Program, coupling: &CouplingMap, initial_layout: Option<&[usize]>) -> LowerResult<Routed> {
+    let gates = GateTable::from_program(program)?;
+    gates.check_program(program)?;
+    let dag = DagCircuit::from_program(program)?;
+    let regs = dag.registers();
+    let num_qubits = regs.num_qubits();
+    let num_physical = coupling.num_qubits();
+    if num_qubits > num_physical {
+        return Err(CircuitError::new(format!("The program needs {} qubits, but there are only {}.", num_qubits, num_physical)));
+    }
+    let layout = match initial_layout {
+        Some(layout) => {
+            if layout.len() != num_qubits {
+                return Err(CircuitError::new(format!("The layout has {} qubits, but the program {}.", layout.len(), num_qubits)));
+            }
+            if let Some(&p) = layout.iter().find(|&&p| p >= num_physical) {
+                return Err(CircuitError::new(format!("Physical qubit {} is not in the coupling map.", p)));
+            }
+            if let Some(&p) = layout.iter().enumerate().find(|&(i, p)| layout[..i].contains(p)).map(|(_, p)| p) {
+                return Err(CircuitError::new(format!("Physical qubit {} is used twice in the layout.", p)));
+            }
+            layout.to_vec()
+        }
+        None => (0..num_qubits).collect(),
+    };
+    let mut occupant = vec![None; num_physical];
+    for (l, &p) in layout.iter().enumerate() {
+        occupant[p] = Some(l);
+    }
+    let mut router = Router {
+        coupling,
+        layout: layout.clone(),
+        occupant,
+        decay: vec![1.0; num_physical],
+        cx: if gates.is_standard("cx") { "cx" } else { "CX" },
+        physical: physical_register_name(regs),
+        ops: vec![],
+        num_swaps: 0,
+    };
+
+    let num_ids = dag.node_ids().last().map(|&id| id + 1).unwrap_or(0);
+    let mut indegree = vec![0; num_ids];
+    let mut front = vec![];
+    for id in dag.node_ids() {
+        indegree[id] = dag.predecessors(id).len();
+        if indegree[id] == 0 {
+            front.push(id);
+        }
+    }
+    // Without progress for this many swaps, the first blocked gate is routed directly.
+    let max_swaps_without_progress = 10 * num_physical;
+    let mut swaps_without_progress = 0;
+    while !front.is_empty() {
+        let mut executed = vec![];
+        let mut blocked = vec![];
+        for &id in &front {
+            match connected_qubits(&dag, id)? {
+                Some(gate) if router.distance(gate)? > 1 => blocked.push(gate),
+                _ => executed.push(id),
+            }
+        }
+        if !executed.is_empty() {
+            for &id in &executed {
+                router.ops.push(map_qubits(dag.node(id).statement(), regs, &router.layout, &router.physical));
+                for s in dag.successors(id) {
+                    indegree[s] -= 1;
+                    if indegree[s] == 0 {
+                        front.push(s);
+                    }
+                }
+            }
+            front.retain(|id| !executed.contains(id));
+            front.sort();
+            router.reset_decay();
+            swaps_without_progress = 0;
+            continue;
+        }
+        if swaps_without_progress >= max_swaps_without_progress {
+            router.swap_along_path(blocked[0])?;
+            continue;
+        }
+        let extended = extended_set(&dag, &front, &indegree)?;
+        let (a, b) = router.best_swap(&blocked, &extended);
+        router.swap(a, b);
+        swaps_without_progress += 1;
+    }
+
+    // The header with the registers of the program replaced by the physical one.
+    let mut routed = vec![];
+    let mut declared = false;
+    for stmt in dag.header() {
+        match *stmt {
+            Statement::Qreg(..) if declared => {}
+            Statement::Qreg(..) => {
+                routed.push(Statement::Qreg(router.physical.clone(), num_physical as NnInteger));
+                declared = true;
+            }
+            _ => routed.push(stmt.clone()),
+        }
+    }
+    routed.extend(router.ops);
+    Ok(Routed { program: routed, initial_layout: layout, final_layout: router.layout, num_swaps: router.num_swaps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use complex::Complex;
+    use parser::CReg;
+    use simulator::simulate;
+    use test_util::parse;
+
+    /// Checks that all two-qubit gates act on connected qubits.
+    fn check_connected(routed: &Routed, coupling: &CouplingMap) {
+        for stmt in &routed.program {
+            if let Statement::Apply(_, _, ref qargs) = *stmt {
+                if qargs.len() == 2 {
+                    let index = |q: &QReg| match *q {
+                        QReg::Bit(_, i) => i as usize,
+                        _ => unreachable!(),
+                    };
+                    assert_eq!(coupling.distance(index(&qargs[0]), index(&qargs[1])), Some(1), "{:?}", stmt);
+                }
+            }
+        }
+    }
+
+    /// The final state of the routed program with the physical qubits read in the final layout.
+    fn logical_state(routed: &Routed, num_qubits: usize) -> Vec<Complex> {
+        let state = simulate(&routed.program, Some(0)).unwrap().state;
+        let mut logical = vec![Complex::new(0.0, 0.0); 1 << num_qubits];
+        for (i, amp) in logical.iter_mut().enumerate() {
+            let physical = (0..num_qubits).filter(|&l| i >> l & 1 == 1).map(|l| 1 << routed.final_layout[l]).sum::<usize>();
+            *amp = state.amplitudes()[physical];
+        }
+        logical
+    }
+
+    #[test]
+    fn route_on_line() {
+        let code = "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg a[2];\nqreg b[2];\nh a[0];\nrx(0.3) a[1];\nry(0.7) b[0];\n\
+                    cx a[0], b[1];\ncx b[1], a[1];\ncx a[1], b[0];\nt b[1];\ncx a[0], b[0];\ncx b[1], a[0];";
+        let program = parse(code);
+        let coupling = CouplingMap::line(4);
+        let routed = route(&program, &coupling, None).unwrap();
+        check_connected(&routed, &coupling);
+        assert!(routed.num_swaps > 0);
+        assert_eq!(routed.initial_layout, [0, 1, 2, 3]);
+        let expected = simulate(&program, Some(0)).unwrap().state;
+        for (a, b) in logical_state(&routed, 4).iter().zip(expected.amplitudes()) {
+            assert!(a.approx_eq(*b, 1e-9), "{} != {}", a, b);
+        }
+        assert!(routed.program.contains(&Statement::Qreg("q".to_owned(), 4)));
+    }
+
+    #[test]
+    fn initial_layout_and_errors() {
+        let program = parse("include \"qelib1.inc\";\nqreg q[3];\ncreg c[3];\ncx q[0], q[2];\nmeasure q -> c;");
+        let coupling = CouplingMap::from_json("[[0, 1], [1, 2], [2, 3]]").unwrap();
+        let routed = route(&program, &coupling, Some(&[0, 2, 1])).unwrap();
+        assert_eq!(routed.num_swaps, 0);
+        assert_eq!(routed.final_layout, [0, 2, 1]);
+        assert!(routed.program.contains(&Statement::Measure(QReg::Bit("q".to_owned(), 2), CReg::Bit("c".to_owned(), 1))));
+        assert!(route(&program, &coupling, Some(&[0, 0, 1])).is_err());
+        assert!(route(&program, &coupling, Some(&[0, 1, 4])).is_err());
+        assert!(route(&program, &CouplingMap::line(2), None).is_err());
+        assert!(route(&program, &CouplingMap::new(&[(0, 1), (2, 3)]).unwrap(), None).is_err());
+        let ccx = parse("include \"qelib1.inc\";\nqreg q[3];\nccx q[0], q[1], q[2];");
+        assert!(route(&ccx, &coupling, None).is_err());
+        let malformed = parse("include \"qelib1.inc\";\nqreg q[2];\ncx q[0];");
+        assert!(route(&malformed, &coupling, None).is_err());
+    }
+
+    #[test]
+    fn coupling_map_from_json() {
+        let coupling = CouplingMap::from_json(" [ [0,1], [1 ,2],\n[1, 3] ] ").unwrap();
+        assert_eq!(coupling.num_qubits(), 4);
+        assert_eq!(coupling.neighbours(1), [0, 2, 3]);
+        assert_eq!(coupling.distance(0, 3), Some(2));
+        assert_eq!(CouplingMap::from_json("[]").unwrap().num_qubits(), 0);
+        assert!(CouplingMap::from_json("[[0, 1]").is_err());
+        assert!(CouplingMap::from_json("[[0, 1, 2]]").is_err());
+        assert!(CouplingMap::from_json("[[1, 1]]").is_err());
+        assert!(CouplingMap::from_json("[[0, 1]] x").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Running a circuit many times and counting the measured classical bitstrings.
+//!
+//! Bitstrings use the ordering of Qiskit and of hardware results: within a register the bit with
+//! the highest index is leftmost, and registers are separated by spaces with the last declared
+//! register leftmost. For `creg c[2]; creg d[1];` with `c[0] = 1` and `d[0] = 1` the key is
+//! `"1 01"`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use errors::CircuitError;
+use lower::Registers;
+use parser::{Program, Statement};
+use rng::Rng;
+use simulator::{Executor, QuantumState, SimulationResult, StateVector, MAX_QUBITS};
+
+/// Number of shots for each measured bitstring.
+pub type Counts = BTreeMap<String, usize>;
+
+/// Formats classical bits as a bitstring in Qiskit's ordering.
+pub fn bitstring(regs: &Registers, clbits: &[bool]) -> String {
+    let mut offset = 0;
+    let mut regstrs = vec![];
+    for &(_, size) in regs.cregs() {
+        let bits = &clbits[offset..offset + size as usize];
+        regstrs.push(bits.iter().rev().map(|&b| if b { '1' } else { '0' }).collect::<String>());
+        offset += size as usize;
+    }
+    regstrs.reverse();
+    regstrs.join(" ")
+}
+
+/// Whether no measured qubit is used afterwards and no operation depends on classical bits, so
+/// that all shots can be sampled from a single final state.
+fn measurements_are_terminal(executor: &Executor) -> bool {
+    let regs = executor.registers();
+    let mut measured = HashSet::new();
+    for stmt in executor.operations() {
+        match *stmt {
+            Statement::Apply(_, _, ref qargs)
+                if qargs.iter().any(|q| measured.contains(&regs.qubit_index(q))) => return false,
+            Statement::Measure(ref q, _) => {
+                measured.insert(regs.qubit_index(q));
+            }
+            Statement::Reset(..) | Statement::If(..) => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+/// Simulates the unitary part of the circuit once and draws every shot from the final
+/// probabilities.
+fn sample_terminal(executor: &Executor, shots: usize, rng: &mut Rng) -> SimulationResult<Counts> {
+    let regs = executor.registers();
+    let mut state = StateVector::new(regs.num_qubits());
+    let mut clbits = vec![false; regs.num_clbits()];
+    let mut measurements = vec![];
+    for stmt in executor.operations() {
+        match *stmt {
+            Statement::Measure(ref q, ref c) => measurements.push((regs.qubit_index(q), regs.clbit_index(c))),
+            _ => executor.run_statement(stmt, &mut state, &mut clbits, rng)?,
+        }
+    }
+    let cumulative: Vec<f64> = state.probabilities().iter().scan(0.0, |acc, p| {
+        *acc += p;
+        Some(*acc)
+    }).collect();
+    let total = cumulative[cumulative.len() - 1];
+    let mut counts = Counts::new();
+    for _ in 0..shots {
+        let r = rng.next_f64() * total;
+        let idx = cumulative.partition_point(|&c| c <= r).min(cumulative.len() - 1);
+        for &(q, c) in &measurements {
+            clbits[c] = idx & 1 << q != 0;
+        }
+        *counts.entry(bitstring(regs, &clbits)).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+/// Runs a program `shots` times on a state vector and counts the measured bitstrings. `seed`
+/// makes the counts reproducible.
+///
+/// If all measurements are terminal the circuit is simulated once and the shots are sampled from
+/// the final state; otherwise, e.g. with mid-circuit measurements, `reset` or `if`, the circuit
+/// is simulated again for every shot.
+pub fn sample(program: &Program, shots: usize, seed: Option<u64>) -> SimulationResult<Counts> {
+    let executor = Executor::new(program)?;
+    let n = executor.registers().num_qubits();
+    if n > MAX_QUBITS {
+        return Err(CircuitError::new(format!("{} qubits are too many to simulate.", n)));
+    }
+    let mut rng = seed.map(Rng::from_seed).unwrap_or_else(Rng::from_time);
+    if measurements_are_terminal(&executor) {
+        return sample_terminal(&executor, shots, &mut rng);
+    }
+    let mut counts = Counts::new();
+    for _ in 0..shots {
+        let mut state = StateVector::new(n);
+        let clbits = executor.run(&mut state, &mut rng)?;
+        *counts.entry(bitstring(executor.registers(), &clbits)).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+/// Runs a program `shots` times on any quantum state, simulating every shot from a fresh state
+/// made by `new_state`.
+pub fn sample_with<S, F>(program: &Program, shots: usize, rng: &mut Rng, new_state: F) -> SimulationResult<Counts>
+    where S: QuantumState, F: Fn(usize) -> S
+{
+    sample_executor(&Executor::new(program)?, shots, rng, new_state)
+}
+
+/// Like `sample_with`, for a program which is already lowered.
+pub fn sample_executor<S, F>(executor: &Executor, shots: usize, rng: &mut Rng, new_state: F) -> SimulationResult<Counts>
+    where S: QuantumState, F: Fn(usize) -> S
+{
+    let mut counts = Counts::new();
+    for _ in 0..shots {
+        let mut state = new_state(executor.registers().num_qubits());
+        let clbits = executor.run(&mut state, rng)?;
+        *counts.entry(bitstring(executor.registers(), &clbits)).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    #[test]
+    fn qiskit_bit_order() {
+        let prog = parse("include \"qelib1.inc\"; qreg q[3]; creg c[2]; creg d[1]; x q[0]; x q[2];\n\
+                          measure q[0] -> c[0]; measure q[1] -> c[1]; measure q[2] -> d[0];");
+        let counts = sample(&prog, 10, Some(0)).unwrap();
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), vec![("1 01".to_owned(), 10)]);
+    }
+
+    #[test]
+    fn terminal_sampling_is_seeded() {
+        let bell = parse("include \"qelib1.inc\"; qreg q[2]; creg c[2];\n\
+                          h q[0]; cx q[0], q[1]; measure q -> c;");
+        let counts = sample(&bell, 1000, Some(7)).unwrap();
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec!["00", "11"]);
+        assert!(counts["00"] > 400 && counts["11"] > 400);
+        assert_eq!(counts, sample(&bell, 1000, Some(7)).unwrap());
+        assert_ne!(counts, sample(&bell, 1000, Some(8)).unwrap());
+    }
+
+    #[test]
+    fn mid_circuit_measurement() {
+        // Feed-forward: q[1] is flipped exactly once whatever the outcome of q[0].
+        let prog = parse("include \"qelib1.inc\"; qreg q[2]; creg a[1]; creg b[1];\n\
+                          h q[0]; measure q[0] -> a[0]; if (a == 1) x q[1]; if (a == 0) x q[1];\n\
+                          measure q[1] -> b[0];");
+        let counts = sample(&prog, 200, Some(3)).unwrap();
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec!["1 0", "1 1"]);
+        assert_eq!(counts.values().sum::<usize>(), 200);
+        assert_eq!(counts, sample_with(&prog, 200, &mut Rng::from_seed(3), StateVector::new).unwrap());
+    }
+}
@@ -0,0 +1,224 @@
+//! Constant folding and algebraic simplification of `Expr`.
+//!
+//! Constant subtrees are folded into an exact `Angle`, so `pi/2 + pi/4` becomes `3*pi/4`.
+
+use std::collections::HashMap;
+use angle::Angle;
+use parser::{Expr, UnaryOp};
+
+enum Simplified {
+    Const(Angle),
+    Sym(Expr),
+}
+
+use self::Simplified::{Const as C, Sym};
+
+fn boxed(e: Expr) -> Box<Expr> {
+    Box::new(e)
+}
+
+fn is_num(a: &Angle, v: f64) -> bool {
+    *a == Angle::from_f64(v)
+}
+
+fn to_expr(s: Simplified) -> Expr {
+    match s {
+        C(c) => c.to_expr(),
+        Sym(e) => e,
+    }
+}
+
+/// Whether the expression is known to be non-negative for any value of its identifiers.
+fn is_nonnegative(e: &Expr) -> bool {
+    match *e {
+        Expr::NnInteger(_) | Expr::Pi => true,
+        Expr::Real(v) => v >= 0.0,
+        Expr::UnaryOp(UnaryOp::Exp, _) | Expr::UnaryOp(UnaryOp::Sqrt, _) => true,
+        Expr::Pow(_, ref exp) => match **exp {
+            Expr::NnInteger(n) => n % 2 == 0,
+            _ => false,
+        },
+        Expr::Mul(ref lhs, ref rhs) | Expr::Div(ref lhs, ref rhs) | Expr::Add(ref lhs, ref rhs) => {
+            is_nonnegative(lhs) && is_nonnegative(rhs)
+        }
+        _ => false,
+    }
+}
+
+fn neg(s: Simplified) -> Simplified {
+    match s {
+        C(c) => C(-c),
+        Sym(Expr::Neg(e)) => Sym(*e),
+        Sym(Expr::Sub(lhs, rhs)) => Sym(Expr::Sub(rhs, lhs)),
+        Sym(e) => Sym(Expr::Neg(boxed(e))),
+    }
+}
+
+fn add(lhs: Simplified, rhs: Simplified) -> Simplified {
+    match (lhs, rhs) {
+        (C(a), C(b)) => C(a + b),
+        (C(a), rhs) if is_num(&a, 0.0) => rhs,
+        (lhs, C(b)) if is_num(&b, 0.0) => lhs,
+        (lhs, Sym(Expr::Neg(e))) => sub(lhs, Sym(*e)),
+        (lhs, C(b)) if b.value() < 0.0 => sub(lhs, C(-b)),
+        (Sym(Expr::Neg(e)), rhs) => sub(rhs, Sym(*e)),
+        (lhs, rhs) => Sym(Expr::Add(boxed(to_expr(lhs)), boxed(to_expr(rhs)))),
+    }
+}
+
+fn sub(lhs: Simplified, rhs: Simplified) -> Simplified {
+    match (lhs, rhs) {
+        (C(a), C(b)) => C(a - b),
+        (C(a), rhs) if is_num(&a, 0.0) => neg(rhs),
+        (lhs, C(b)) if is_num(&b, 0.0) => lhs,
+        (lhs, Sym(Expr::Neg(e))) => add(lhs, Sym(*e)),
+        (Sym(a), Sym(b)) => {
+            if a == b {
+                C(Angle::from_f64(0.0))
+            } else {
+                Sym(Expr::Sub(boxed(a), boxed(b)))
+            }
+        }
+        (lhs, rhs) => Sym(Expr::Sub(boxed(to_expr(lhs)), boxed(to_expr(rhs)))),
+    }
+}
+
+fn mul(lhs: Simplified, rhs: Simplified) -> Simplified {
+    match (lhs, rhs) {
+        (C(a), C(b)) => C(a.mul(&b)),
+        (C(a), _) | (_, C(a)) if is_num(&a, 0.0) => C(Angle::from_f64(0.0)),
+        (C(a), other) | (other, C(a)) if is_num(&a, 1.0) => other,
+        (C(a), other) | (other, C(a)) if is_num(&a, -1.0) => neg(other),
+        (Sym(Expr::Neg(a)), Sym(Expr::Neg(b))) => mul(Sym(*a), Sym(*b)),
+        (lhs, rhs) => Sym(Expr::Mul(boxed(to_expr(lhs)), boxed(to_expr(rhs)))),
+    }
+}
+
+fn div(lhs: Simplified, rhs: Simplified) -> Simplified {
+    match (lhs, rhs) {
+        (C(a), C(b)) => C(a.div(&b)),
+        (lhs, C(b)) if is_num(&b, 1.0) => lhs,
+        (lhs, C(b)) if is_num(&b, -1.0) => neg(lhs),
+        (Sym(Expr::Neg(a)), Sym(Expr::Neg(b))) => div(Sym(*a), Sym(*b)),
+        (lhs, rhs) => Sym(Expr::Div(boxed(to_expr(lhs)), boxed(to_expr(rhs)))),
+    }
+}
+
+fn pow(lhs: Simplified, rhs: Simplified) -> Simplified {
+    match (lhs, rhs) {
+        (C(a), C(b)) => C(Angle::from_f64(a.value().powf(b.value()))),
+        (_, C(b)) if is_num(&b, 0.0) => C(Angle::from_f64(1.0)),
+        (lhs, C(b)) if is_num(&b, 1.0) => lhs,
+        (C(a), _) if is_num(&a, 1.0) => C(Angle::from_f64(1.0)),
+        (Sym(Expr::UnaryOp(UnaryOp::Sqrt, ref x)), C(b)) if is_num(&b, 2.0) && is_nonnegative(x) => {
+            Sym((**x).clone())
+        }
+        (lhs, rhs) => Sym(Expr::Pow(boxed(to_expr(lhs)), boxed(to_expr(rhs)))),
+    }
+}
+
+fn unary(op: UnaryOp, arg: Simplified) -> Simplified {
+    match (op, arg) {
+        (_, C(a)) => C(Angle::from_f64(op.apply(a.value()))),
+        (UnaryOp::Ln, Sym(Expr::UnaryOp(UnaryOp::Exp, x))) => Sym(*x),
+        (op, Sym(x)) => Sym(Expr::UnaryOp(op, boxed(x))),
+    }
+}
+
+fn simplify_rec(e: &Expr, values: &HashMap<String, f64>) -> Simplified {
+    match *e {
+        Expr::Real(v) => C(Angle::from_f64(v)),
+        Expr::NnInteger(v) => C(Angle::from_f64(v as f64)),
+        Expr::Pi => C(Angle::new(1, 1)),
+        Expr::Id(ref name) => match values.get(name) {
+            Some(&v) => C(Angle::from_f64(v)),
+            None => Sym(e.clone()),
+        },
+        Expr::Add(ref lhs, ref rhs) => add(simplify_rec(lhs, values), simplify_rec(rhs, values)),
+        Expr::Sub(ref lhs, ref rhs) => sub(simplify_rec(lhs, values), simplify_rec(rhs, values)),
+        Expr::Mul(ref lhs, ref rhs) => mul(simplify_rec(lhs, values), simplify_rec(rhs, values)),
+        Expr::Div(ref lhs, ref rhs) => div(simplify_rec(lhs, values), simplify_rec(rhs, values)),
+        Expr::Neg(ref v) => neg(simplify_rec(v, values)),
+        Expr::Pow(ref lhs, ref rhs) => pow(simplify_rec(lhs, values), simplify_rec(rhs, values)),
+        Expr::UnaryOp(op, ref v) => unary(op, simplify_rec(v, values)),
+    }
+}
+
+impl Expr {
+    /// Folds constant subtrees and applies algebraic identities. Identifiers are kept symbolic.
+    pub fn simplify(&self) -> Expr {
+        self.simplify_with(&HashMap::new())
+    }
+
+    /// Like `simplify`, but substitutes identifiers found in `values` first.
+    pub fn simplify_with(&self, values: &HashMap<String, f64>) -> Expr {
+        to_expr(simplify_rec(self, values))
+    }
+
+    /// Whether the expression contains no identifiers.
+    pub fn is_constant(&self) -> bool {
+        match *self {
+            Expr::Real(_) | Expr::NnInteger(_) | Expr::Pi => true,
+            Expr::Id(_) => false,
+            Expr::Add(ref lhs, ref rhs) | Expr::Sub(ref lhs, ref rhs) | Expr::Mul(ref lhs, ref rhs) |
+            Expr::Div(ref lhs, ref rhs) | Expr::Pow(ref lhs, ref rhs) => lhs.is_constant() && rhs.is_constant(),
+            Expr::Neg(ref v) | Expr::UnaryOp(_, ref v) => v.is_constant(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::expr;
+
+    #[test]
+    fn fold_pi_multiples() {
+        assert_eq!(expr("pi/2 + pi/4 - 0*theta").simplify(), expr("3*pi/4"));
+        assert_eq!(expr("-(pi/2) * 2").simplify(), expr("-pi"));
+        assert_eq!(expr("2*pi - pi").simplify(), expr("pi"));
+        assert_eq!(expr("pi/3 + 1").simplify(), expr("pi/3 + 1"));
+        assert_eq!(expr("(1 + 2) * 3").simplify(), expr("9"));
+        assert_eq!(expr("1.5 - 2").simplify(), expr("-0.5"));
+    }
+
+    #[test]
+    fn identities() {
+        assert_eq!(expr("-(-x)").simplify(), expr("x"));
+        assert_eq!(expr("x + 0").simplify(), expr("x"));
+        assert_eq!(expr("1 * x / 1").simplify(), expr("x"));
+        assert_eq!(expr("0 - x").simplify(), expr("-x"));
+        assert_eq!(expr("x - -y").simplify(), expr("x + y"));
+        assert_eq!(expr("x^1 + y^0").simplify(), expr("x + 1"));
+        assert_eq!(expr("sqrt(x^2)^2").simplify(), expr("x^2"));
+        assert_eq!(expr("sqrt(x)^2").simplify(), expr("sqrt(x)^2"));
+        assert_eq!(expr("theta - theta").simplify(), expr("0"));
+    }
+
+    #[test]
+    fn substitution() {
+        let mut values = HashMap::new();
+        values.insert("theta".to_owned(), 0.5);
+        assert_eq!(expr("pi * theta + phi").simplify_with(&values), expr("pi/2 + phi"));
+        assert!(!expr("pi * theta").is_constant());
+        assert!(expr("pi * theta").simplify_with(&values).is_constant());
+    }
+
+    #[test]
+    fn preserves_eval() {
+        let mut values = HashMap::new();
+        values.insert("x".to_owned(), 0.7);
+        values.insert("y".to_owned(), -1.3);
+        for s in &["pi/2 + pi/4 - 0*x", "-(-x) * 1 + 0", "sqrt(exp(x))^2 - y", "ln(exp(x - y)) / -1",
+                   "(x + pi) * (2 - pi/3) ^ 2", "sin(pi/6) * cos(x) + tan(y / 2)", "-(x - y) / (1 - -2)",
+                   "2 ^ 0.5 * 0 + x ^ 1 - pi * 0.25"] {
+            let e = expr(s);
+            let expected = e.eval_with(&values).unwrap();
+            let symbolic = e.simplify().eval_with(&values).unwrap();
+            let bound = e.simplify_with(&values);
+            assert!(bound.is_constant(), "{}", s);
+            assert!((symbolic - expected).abs() < 1e-12, "{}", s);
+            assert!((bound.eval() - expected).abs() < 1e-12, "{}", s);
+        }
+    }
+}
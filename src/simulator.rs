@@ -0,0 +1,379 @@
+//! State-vector simulator.
+//!
+//! Qubits are numbered by their global index (see `lower::Registers`), and qubit `k` is bit `k`
+//! of the index of an amplitude, so `q[0]` is the least significant bit. The same convention is
+//! used for the values of classical registers: `c[0]` is the least significant bit.
+//!
+//! `U`, `CX` and most gates of `qelib1.inc` are applied directly with their matrices; other
+//! gates are inlined from their declarations.
+
+use std::collections::HashMap;
+
+use complex::{Complex, ONE, ZERO};
+use errors::CircuitError;
+use gates::{self, GateMatrix};
+use inline::GateTable;
+use lower::{self, Registers};
+use parser::{Params, Program, QArgs, Statement};
+use rng::Rng;
+
+pub type SimulationResult<T> = Result<T, CircuitError>;
+
+/// The largest number of qubits the dense simulators accept.
+pub const MAX_QUBITS: usize = 28;
+
+/// A quantum state a circuit can be run on.
+pub trait QuantumState {
+    fn apply(&mut self, gate: &GateMatrix, qubits: &[usize]);
+    /// Measures a qubit in the computational basis and collapses the state.
+    fn measure(&mut self, qubit: usize, rng: &mut Rng) -> bool;
+    /// Resets a qubit to `|0>`.
+    fn reset(&mut self, qubit: usize, rng: &mut Rng);
+    /// Called after each gate application of the lowered circuit, with the name of the gate as
+    /// it is applied in the circuit, before any inlining. Noisy states apply gate errors here.
+    fn gate_applied(&mut self, _name: &str, _qubits: &[usize]) {}
+}
+
+/// A lowered program with its gate declarations and registers, ready to be run.
+#[derive(Debug, Clone)]
+pub struct Executor {
+    table: GateTable,
+    regs: Registers,
+    ops: Program,
+}
+
+fn eval_params(params: &Params) -> SimulationResult<Vec<f64>> {
+    params.iter().map(|p| {
+        p.eval_with(&HashMap::new())
+            .ok_or_else(|| CircuitError::new("The circuit has unbound parameters.".to_owned()))
+    }).collect()
+}
+
+impl Executor {
+    pub fn new(program: &Program) -> SimulationResult<Executor> {
+        let table = GateTable::from_program(program)?;
+        let ops = lower::lower(program)?;
+        let regs = Registers::from_program(&ops)?;
+        let ops = ops.into_iter().filter(|s| {
+            matches!(*s, Statement::Apply(..) | Statement::Measure(..) | Statement::Reset(..) |
+                         Statement::Barrier(..) | Statement::If(..))
+        }).collect();
+        Ok(Executor { table, regs, ops })
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.regs
+    }
+
+    pub fn gate_table(&self) -> &GateTable {
+        &self.table
+    }
+
+    /// The lowered operations, without declarations.
+    pub fn operations(&self) -> &Program {
+        &self.ops
+    }
+
+    /// The first of `measure`, `reset` or `if` in the circuit, if it has any.
+    pub fn non_unitary_operation(&self) -> Option<&'static str> {
+        self.ops.iter().filter_map(|stmt| match *stmt {
+            Statement::Measure(..) => Some("measure"),
+            Statement::Reset(..) => Some("reset"),
+            Statement::If(..) => Some("if"),
+            _ => None,
+        }).next()
+    }
+
+    /// Runs the circuit on `state` and returns the classical bits.
+    pub fn run<S: QuantumState>(&self, state: &mut S, rng: &mut Rng) -> SimulationResult<Vec<bool>> {
+        let mut clbits = vec![false; self.regs.num_clbits()];
+        for stmt in &self.ops {
+            self.run_statement(stmt, state, &mut clbits, rng)?;
+        }
+        Ok(clbits)
+    }
+
+    /// Runs a single lowered operation.
+    pub fn run_statement<S: QuantumState>(&self, stmt: &Statement, state: &mut S, clbits: &mut [bool],
+                                          rng: &mut Rng) -> SimulationResult<()> {
+        match *stmt {
+            Statement::Apply(ref name, ref params, ref qargs) => {
+                self.apply(name, params, qargs, state)?;
+                let qubits: Vec<usize> = qargs.iter().map(|q| self.regs.qubit_index(q)).collect();
+                state.gate_applied(name, &qubits);
+            }
+            Statement::Measure(ref q, ref c) => {
+                clbits[self.regs.clbit_index(c)] = state.measure(self.regs.qubit_index(q), rng);
+            }
+            Statement::Reset(ref q) => state.reset(self.regs.qubit_index(q), rng),
+            Statement::If(ref c, val, ref body)
+                if register_equals(clbits, self.regs.clbits(c)?, u64::from(val)) => {
+                for stmt in body {
+                    self.run_statement(stmt, state, clbits, rng)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn apply<S: QuantumState>(&self, name: &str, params: &Params, qargs: &QArgs, state: &mut S)
+                              -> SimulationResult<()> {
+        self.table.check_apply(name, params, qargs)?;
+        if self.table.is_standard(name) {
+            if let Some(gate) = gates::standard_gate(name, &eval_params(params)?) {
+                let qubits: Vec<usize> = qargs.iter().map(|q| self.regs.qubit_index(q)).collect();
+                state.apply(&gate, &qubits);
+                return Ok(());
+            }
+        }
+        match self.table.expand(name, params, qargs)? {
+            Some(body) => {
+                for stmt in &body {
+                    if let Statement::Apply(ref name, ref params, ref qargs) = *stmt {
+                        self.apply(name, params, qargs, state)?;
+                    }
+                }
+                Ok(())
+            }
+            None => Err(CircuitError::new(format!("Cannot simulate opaque gate '{}'.", name))),
+        }
+    }
+}
+
+/// The value of a classical register, with its first bit as the least significant bit. Returns
+/// `None` if the register has more than 64 bits.
+pub fn register_value(clbits: &[bool], bits: ::std::ops::Range<usize>) -> Option<u64> {
+    if bits.len() > 64 {
+        return None;
+    }
+    Some(bits.enumerate().fold(0, |v, (i, b)| if clbits[b] { v | 1 << i } else { v }))
+}
+
+/// Whether a classical register has the value `val`, as in `if (c == val)`. The register may
+/// have any number of bits.
+pub fn register_equals(clbits: &[bool], bits: ::std::ops::Range<usize>, val: u64) -> bool {
+    bits.enumerate().all(|(i, b)| clbits[b] == (i < 64 && (val >> i) & 1 == 1))
+}
+
+/// Applies a gate to a vector of `2^n` amplitudes. The matrix of the gate need not be unitary.
+pub fn apply_gate(amps: &mut [Complex], gate: &GateMatrix, qubits: &[usize]) {
+    match *gate {
+        GateMatrix::Unitary { controls, ref matrix } => {
+            let cmask = qubits[..controls].iter().fold(0, |m, &q| m | 1 << q);
+            let tmask = 1 << qubits[controls];
+            for i in 0..amps.len() {
+                if i & tmask == 0 && i & cmask == cmask {
+                    let j = i | tmask;
+                    let (a0, a1) = (amps[i], amps[j]);
+                    amps[i] = matrix[0][0] * a0 + matrix[0][1] * a1;
+                    amps[j] = matrix[1][0] * a0 + matrix[1][1] * a1;
+                }
+            }
+        }
+        GateMatrix::Swap { controls } => {
+            let cmask = qubits[..controls].iter().fold(0, |m, &q| m | 1 << q);
+            let (amask, bmask) = (1 << qubits[controls], 1 << qubits[controls + 1]);
+            for i in 0..amps.len() {
+                if i & amask != 0 && i & bmask == 0 && i & cmask == cmask {
+                    amps.swap(i, i ^ amask ^ bmask);
+                }
+            }
+        }
+    }
+}
+
+/// A dense state vector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateVector {
+    num_qubits: usize,
+    amps: Vec<Complex>,
+}
+
+impl StateVector {
+    /// `|0...0>` on `num_qubits` qubits.
+    pub fn new(num_qubits: usize) -> StateVector {
+        let mut amps = vec![ZERO; 1 << num_qubits];
+        amps[0] = ONE;
+        StateVector { num_qubits, amps }
+    }
+
+    pub fn from_amplitudes(amps: Vec<Complex>) -> StateVector {
+        assert!(amps.len().is_power_of_two());
+        StateVector { num_qubits: amps.len().trailing_zeros() as usize, amps }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn amplitudes(&self) -> &[Complex] {
+        &self.amps
+    }
+
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.amps.iter().map(|a| a.norm_sqr()).collect()
+    }
+
+    fn probability_one(&self, qubit: usize) -> f64 {
+        let mask = 1 << qubit;
+        self.amps.iter().enumerate().filter(|&(i, _)| i & mask != 0).map(|(_, a)| a.norm_sqr()).sum()
+    }
+
+    /// Projects a qubit onto `outcome` and renormalizes.
+    fn collapse(&mut self, qubit: usize, outcome: bool, prob: f64) {
+        let mask = 1 << qubit;
+        let norm = 1.0 / prob.sqrt();
+        for (i, a) in self.amps.iter_mut().enumerate() {
+            if (i & mask != 0) == outcome {
+                *a = a.scale(norm);
+            } else {
+                *a = ZERO;
+            }
+        }
+    }
+}
+
+impl QuantumState for StateVector {
+    fn apply(&mut self, gate: &GateMatrix, qubits: &[usize]) {
+        apply_gate(&mut self.amps, gate, qubits);
+    }
+
+    fn measure(&mut self, qubit: usize, rng: &mut Rng) -> bool {
+        let p1 = self.probability_one(qubit);
+        let outcome = rng.next_f64() < p1;
+        self.collapse(qubit, outcome, if outcome { p1 } else { 1.0 - p1 });
+        outcome
+    }
+
+    fn reset(&mut self, qubit: usize, rng: &mut Rng) {
+        if self.measure(qubit, rng) {
+            let x = gates::standard_gate("x", &[]).unwrap();
+            self.apply(&x, &[qubit]);
+        }
+    }
+}
+
+/// The final state and classical bits of a run.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub state: StateVector,
+    pub clbits: Vec<bool>,
+    regs: Registers,
+}
+
+impl Simulation {
+    pub fn registers(&self) -> &Registers {
+        &self.regs
+    }
+
+    /// The value of a classical register, or `None` if it is not declared or has more than 64
+    /// bits.
+    pub fn register_value(&self, name: &str) -> Option<u64> {
+        let creg = ::parser::CReg::Reg(name.to_owned());
+        self.regs.clbits(&creg).ok().and_then(|bits| register_value(&self.clbits, bits))
+    }
+
+    /// Values of all classical registers of at most 64 bits in declaration order.
+    pub fn register_values(&self) -> Vec<(String, u64)> {
+        self.regs.cregs().iter().filter_map(|(name, _)| Some((name.clone(), self.register_value(name)?))).collect()
+    }
+}
+
+/// Runs a program once on a state vector. `seed` makes measurement outcomes reproducible.
+pub fn simulate(program: &Program, seed: Option<u64>) -> SimulationResult<Simulation> {
+    let executor = Executor::new(program)?;
+    let n = executor.regs.num_qubits();
+    if n > MAX_QUBITS {
+        return Err(CircuitError::new(format!("{} qubits are too many to simulate.", n)));
+    }
+    let mut rng = seed.map(Rng::from_seed).unwrap_or_else(Rng::from_time);
+    let mut state = StateVector::new(n);
+    let clbits = executor.run(&mut state, &mut rng)?;
+    Ok(Simulation { state, clbits, regs: executor.regs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_1_SQRT_2;
+    use inline;
+    use test_util::parse;
+
+    fn assert_state(state: &StateVector, expected: &[Complex]) {
+        for (a, b) in state.amplitudes().iter().zip(expected) {
+            assert!(a.approx_eq(*b, 1e-12), "{:?} != {:?}", state.amplitudes(), expected);
+        }
+    }
+
+    #[test]
+    fn bell_state() {
+        let sim = simulate(&parse("include \"qelib1.inc\"; qreg q[2]; h q[0]; cx q[0], q[1];"), Some(1)).unwrap();
+        let h = Complex::real(FRAC_1_SQRT_2);
+        assert_state(&sim.state, &[h, ZERO, ZERO, h]);
+    }
+
+    #[test]
+    fn measurement_collapse_and_conditions() {
+        let code = "include \"qelib1.inc\"; qreg q[3]; creg c[2]; creg d[1];\n\
+                    h q[0]; cx q[0], q[1]; measure q[0] -> c[0]; measure q[1] -> c[1];\n\
+                    if (c == 3) x q[2]; measure q[2] -> d[0]; reset q;";
+        let mut seen = [false; 2];
+        for seed in 0..20 {
+            let sim = simulate(&parse(code), Some(seed)).unwrap();
+            let c = sim.register_value("c").unwrap();
+            assert!(c == 0 || c == 3);
+            assert_eq!(sim.register_value("d").unwrap(), c & 1);
+            assert_state(&sim.state, &[ONE]);
+            seen[(c & 1) as usize] = true;
+        }
+        assert_eq!(seen, [true, true]);
+    }
+
+    #[test]
+    fn standard_gates_match_definitions() {
+        let prepare = "include \"qelib1.inc\"; qreg q[5];\n\
+                       U(0.3, 0.2, 0.1) q[0]; U(1.1, 0.5, 0.7) q[1]; U(2.1, 0.3, 1.3) q[2];\n\
+                       U(0.9, 1.5, 0.4) q[3]; U(1.7, 0.6, 2.2) q[4]; cx q[0], q[2]; cx q[3], q[1];";
+        let gates = ["u3(0.1, 0.2, 0.3) q[1]", "u2(0.4, 0.5) q[2]", "u1(0.6) q[0]", "id q[1]", "u0(1) q[1]",
+                     "u(0.7, 0.8, 0.9) q[0]", "p(1.0) q[2]", "x q[0]", "y q[1]", "z q[2]", "h q[0]",
+                     "s q[1]", "sdg q[2]", "t q[0]", "tdg q[1]", "rx(1.1) q[2]", "ry(1.2) q[0]",
+                     "rz(1.3) q[1]", "sx q[2]", "sxdg q[0]", "cz q[0], q[1]", "cy q[1], q[2]",
+                     "swap q[2], q[0]", "ch q[0], q[2]", "ccx q[2], q[0], q[1]", "cswap q[1], q[2], q[0]",
+                     "crx(1.4) q[0], q[1]", "cry(1.5) q[2], q[1]", "crz(1.6) q[1], q[0]", "cu1(1.7) q[0], q[2]",
+                     "cp(1.8) q[2], q[1]", "cu3(1.9, 2.0, 2.1) q[1], q[2]", "csx q[0], q[1]",
+                     "cu(2.2, 2.3, 2.4, 2.5) q[2], q[0]", "rxx(2.6) q[0], q[1]", "rzz(2.7) q[1], q[2]",
+                     "rccx q[0], q[1], q[2]", "rc3x q[0], q[1], q[2], q[3]", "c3x q[3], q[1], q[0], q[2]",
+                     "c3sqrtx q[0], q[1], q[2], q[3]", "c4x q[4], q[0], q[1], q[2], q[3]"];
+        for gate in gates.iter() {
+            let prog = parse(&format!("{} {};", prepare, gate));
+            let direct = simulate(&prog, Some(0)).unwrap();
+            let inlined = simulate(&inline::inline(&prog).unwrap(), Some(0)).unwrap();
+            for (a, b) in direct.state.amplitudes().iter().zip(inlined.state.amplitudes()) {
+                assert!(a.approx_eq(*b, 1e-9), "{}", gate);
+            }
+        }
+    }
+
+    #[test]
+    fn simulation_errors() {
+        assert!(simulate(&parse("qreg q[1]; h q[0];"), Some(0)).is_err());
+        assert!(simulate(&parse("opaque g a; qreg q[1]; g q[0];"), Some(0)).is_err());
+        assert!(simulate(&parse("include \"qelib1.inc\"; qreg q[1]; rz(theta) q[0];"), Some(0)).is_err());
+    }
+
+    #[test]
+    fn wide_registers() {
+        let code = "qreg q[1]; creg c[70]; creg d[1];\n\
+                    U(pi, 0, pi) q[0]; measure q[0] -> c[69]; if (c == 1) U(pi, 0, pi) q[0]; measure q[0] -> d[0];";
+        let sim = simulate(&parse(code), Some(0)).unwrap();
+        assert_eq!(sim.register_value("c"), None);
+        assert_eq!(sim.register_value("d"), Some(1));
+        assert_eq!(sim.register_values(), vec![("d".to_owned(), 1)]);
+        let mut clbits = vec![false; 70];
+        clbits[0] = true;
+        assert!(register_equals(&clbits, 0..70, 1));
+        clbits[69] = true;
+        assert!(!register_equals(&clbits, 0..70, 1));
+    }
+}
@@ -0,0 +1,417 @@
+//! Stabilizer simulator for Clifford circuits.
+//!
+//! The state is kept as a tableau of stabilizer and destabilizer generators (Aaronson and
+//! Gottesman, "Improved simulation of stabilizer circuits", 2004), so circuits on hundreds or
+//! thousands of qubits can be simulated. Gates are reduced to `H`, `S` and `CX`. `U` and the
+//! gates of `qelib1.inc` are accepted if their angles are exact multiples of `pi/2`, e.g.
+//! `u3(pi/2, 0, pi)` or `rz(-pi/2)`; other gates are inlined from their declarations. Gates
+//! which are not Clifford gates are rejected.
+
+use std::ops::Range;
+
+use errors::CircuitError;
+use inline::GateTable;
+use lower::Registers;
+use parser::{Expr, Params, Program, QArgs, Statement};
+use rng::Rng;
+use shots::{self, Counts};
+use simulator::{self, Executor, SimulationResult};
+
+/// An elementary Clifford gate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clifford {
+    H(usize),
+    S(usize),
+    CX(usize, usize),
+}
+
+/// A Pauli product with a sign, as rows of the tableau.
+#[derive(Debug, Clone, PartialEq)]
+struct PauliRow {
+    x: Vec<u64>,
+    z: Vec<u64>,
+    /// Whether the sign is negative.
+    r: bool,
+}
+
+/// A stabilizer state on `n` qubits. Rows `0..n` are destabilizers and rows `n..2n` are
+/// stabilizers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tableau {
+    num_qubits: usize,
+    rows: Vec<PauliRow>,
+}
+
+fn bit(words: &[u64], q: usize) -> bool {
+    words[q / 64] >> (q % 64) & 1 != 0
+}
+
+fn flip(words: &mut [u64], q: usize) {
+    words[q / 64] ^= 1 << (q % 64);
+}
+
+impl PauliRow {
+    fn identity(num_qubits: usize) -> PauliRow {
+        let words = num_qubits.div_ceil(64);
+        PauliRow { x: vec![0; words], z: vec![0; words], r: false }
+    }
+
+    /// Replaces `self` by the product `other * self`, tracking the sign.
+    fn multiply_by(&mut self, other: &PauliRow) {
+        // Sum of the exponents of i over all qubits, see `g` in the paper.
+        let mut phase: i64 = 2 * (self.r as i64 + other.r as i64);
+        for w in 0..self.x.len() {
+            let (x1, z1, x2, z2) = (other.x[w], other.z[w], self.x[w], self.z[w]);
+            let plus = (x1 & z1 & z2 & !x2) | (x1 & !z1 & z2 & x2) | (!x1 & z1 & x2 & !z2);
+            let minus = (x1 & z1 & x2 & !z2) | (x1 & !z1 & z2 & !x2) | (!x1 & z1 & x2 & z2);
+            phase += plus.count_ones() as i64 - minus.count_ones() as i64;
+            self.x[w] ^= x1;
+            self.z[w] ^= z1;
+        }
+        self.r = phase.rem_euclid(4) == 2;
+    }
+}
+
+impl Tableau {
+    /// `|0...0>`.
+    pub fn new(num_qubits: usize) -> Tableau {
+        let mut rows = vec![PauliRow::identity(num_qubits); 2 * num_qubits];
+        for q in 0..num_qubits {
+            flip(&mut rows[q].x, q);
+            flip(&mut rows[num_qubits + q].z, q);
+        }
+        Tableau { num_qubits, rows }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn apply(&mut self, gate: Clifford) {
+        for row in &mut self.rows {
+            match gate {
+                Clifford::H(a) => {
+                    let (x, z) = (bit(&row.x, a), bit(&row.z, a));
+                    row.r ^= x && z;
+                    if x != z {
+                        flip(&mut row.x, a);
+                        flip(&mut row.z, a);
+                    }
+                }
+                Clifford::S(a) => {
+                    let (x, z) = (bit(&row.x, a), bit(&row.z, a));
+                    row.r ^= x && z;
+                    if x {
+                        flip(&mut row.z, a);
+                    }
+                }
+                Clifford::CX(a, b) => {
+                    let (xa, za, xb, zb) = (bit(&row.x, a), bit(&row.z, a), bit(&row.x, b), bit(&row.z, b));
+                    row.r ^= xa && zb && (xb == za);
+                    if xa {
+                        flip(&mut row.x, b);
+                    }
+                    if zb {
+                        flip(&mut row.z, a);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Measures a qubit in the computational basis.
+    pub fn measure(&mut self, qubit: usize, rng: &mut Rng) -> bool {
+        let n = self.num_qubits;
+        match (n..2 * n).find(|&p| bit(&self.rows[p].x, qubit)) {
+            Some(p) => {
+                let pivot = self.rows[p].clone();
+                for i in 0..2 * n {
+                    if i != p && bit(&self.rows[i].x, qubit) {
+                        self.rows[i].multiply_by(&pivot);
+                    }
+                }
+                let outcome = rng.next_f64() < 0.5;
+                self.rows[p - n] = pivot;
+                let mut row = PauliRow::identity(n);
+                flip(&mut row.z, qubit);
+                row.r = outcome;
+                self.rows[p] = row;
+                outcome
+            }
+            None => {
+                let mut scratch = PauliRow::identity(n);
+                for i in 0..n {
+                    if bit(&self.rows[i].x, qubit) {
+                        scratch.multiply_by(&self.rows[i + n]);
+                    }
+                }
+                scratch.r
+            }
+        }
+    }
+
+    /// Resets a qubit to `|0>`.
+    pub fn reset(&mut self, qubit: usize, rng: &mut Rng) {
+        if self.measure(qubit, rng) {
+            for &g in &[Clifford::H(qubit), Clifford::S(qubit), Clifford::S(qubit), Clifford::H(qubit)] {
+                self.apply(g);
+            }
+        }
+    }
+
+    /// The stabilizer generators, e.g. `["+XX", "+ZZ"]` for a Bell state. The first character
+    /// of a Pauli string is qubit 0.
+    pub fn stabilizers(&self) -> Vec<String> {
+        self.rows[self.num_qubits..].iter().map(|row| {
+            let mut s = String::from(if row.r { "-" } else { "+" });
+            for q in 0..self.num_qubits {
+                s.push(match (bit(&row.x, q), bit(&row.z, q)) {
+                    (false, false) => 'I',
+                    (true, false) => 'X',
+                    (true, true) => 'Y',
+                    (false, true) => 'Z',
+                });
+            }
+            s
+        }).collect()
+    }
+}
+
+/// A lowered operation reduced to elementary Clifford gates.
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    Gates(Vec<Clifford>),
+    Measure(usize, usize),
+    Reset(usize),
+    If(Range<usize>, u64, Vec<Op>),
+}
+
+/// `k` such that the parameter is `k * pi / 2` modulo `2*pi`.
+fn half_turns(param: &Expr) -> SimulationResult<Option<usize>> {
+    let angle = param.snapped_angle()
+        .ok_or_else(|| CircuitError::new("The circuit has unbound parameters.".to_owned()))?;
+    Ok(angle.quarter_turns().and_then(|k| if k % 2 == 0 { Some(k as usize / 2) } else { None }))
+}
+
+/// Reduces a gate application to elementary gates. Returns `false` if it is not a Clifford gate.
+fn decompose(table: &GateTable, regs: &Registers, name: &str, params: &Params, qargs: &QArgs,
+             out: &mut Vec<Clifford>) -> SimulationResult<bool> {
+    use self::Clifford::*;
+    table.check_apply(name, params, qargs)?;
+    let q: Vec<usize> = qargs.iter().map(|q| regs.qubit_index(q)).collect();
+    if table.is_standard(name) {
+        let fast = match name {
+            "id" => Some(vec![]),
+            "h" => Some(vec![H(q[0])]),
+            "s" => Some(vec![S(q[0])]),
+            "sdg" => Some(vec![S(q[0]), S(q[0]), S(q[0])]),
+            "z" => Some(vec![S(q[0]), S(q[0])]),
+            "x" => Some(vec![H(q[0]), S(q[0]), S(q[0]), H(q[0])]),
+            "y" => Some(vec![S(q[0]), S(q[0]), H(q[0]), S(q[0]), S(q[0]), H(q[0])]),
+            "cx" | "CX" => Some(vec![CX(q[0], q[1])]),
+            "cz" => Some(vec![H(q[1]), CX(q[0], q[1]), H(q[1])]),
+            "swap" => Some(vec![CX(q[0], q[1]), CX(q[1], q[0]), CX(q[0], q[1])]),
+            _ => None,
+        };
+        if let Some(gates) = fast {
+            out.extend(gates);
+            return Ok(true);
+        }
+    }
+    if name == "U" {
+        // U(theta, phi, lambda) = Rz(phi) Ry(theta) Rz(lambda) up to a global phase, with
+        // Rz(pi/2) = S and Ry(pi/2) = H Z.
+        let turns = [half_turns(&params[0])?, half_turns(&params[1])?, half_turns(&params[2])?];
+        let (theta, phi, lambda) = match turns {
+            [Some(theta), Some(phi), Some(lambda)] => (theta, phi, lambda),
+            _ => return Ok(false),
+        };
+        let q = q[0];
+        out.extend((0..lambda).map(|_| S(q)));
+        for _ in 0..theta {
+            out.extend(&[S(q), S(q), H(q)]);
+        }
+        out.extend((0..phi).map(|_| S(q)));
+        return Ok(true);
+    }
+    match table.expand(name, params, qargs)? {
+        Some(body) => {
+            for stmt in &body {
+                if let Statement::Apply(ref name, ref params, ref qargs) = *stmt {
+                    if !decompose(table, regs, name, params, qargs, out)? {
+                        return Ok(false);
+                    }
+                }
+            }
+            Ok(true)
+        }
+        None => Err(CircuitError::new(format!("Cannot simulate opaque gate '{}'.", name))),
+    }
+}
+
+/// Compiles lowered operations, collecting the operations which are not Clifford gates.
+fn compile(executor: &Executor, stmts: &[Statement], rejected: &mut Vec<Statement>) -> SimulationResult<Vec<Op>> {
+    let regs = executor.registers();
+    let mut ops = vec![];
+    for stmt in stmts {
+        match *stmt {
+            Statement::Apply(ref name, ref params, ref qargs) => {
+                let mut gates = vec![];
+                if decompose(executor.gate_table(), regs, name, params, qargs, &mut gates)? {
+                    ops.push(Op::Gates(gates));
+                } else {
+                    rejected.push(stmt.clone());
+                }
+            }
+            Statement::Measure(ref q, ref c) => ops.push(Op::Measure(regs.qubit_index(q), regs.clbit_index(c))),
+            Statement::Reset(ref q) => ops.push(Op::Reset(regs.qubit_index(q))),
+            Statement::If(ref c, val, ref body) => {
+                ops.push(Op::If(regs.clbits(c)?, u64::from(val), compile(executor, body, rejected)?));
+            }
+            _ => {}
+        }
+    }
+    Ok(ops)
+}
+
+/// The lowered gate applications of a program which are not Clifford gates, e.g. `t q[0];` or
+/// `rz(0.3) q[1];`.
+pub fn non_clifford_operations(program: &Program) -> SimulationResult<Vec<Statement>> {
+    let executor = Executor::new(program)?;
+    let mut rejected = vec![];
+    compile(&executor, executor.operations(), &mut rejected)?;
+    Ok(rejected)
+}
+
+/// A Clifford circuit compiled for the stabilizer simulator.
+#[derive(Debug, Clone)]
+pub struct CliffordCircuit {
+    regs: Registers,
+    ops: Vec<Op>,
+}
+
+impl CliffordCircuit {
+    /// Returns an error naming the first gate which is not a Clifford gate, if any.
+    pub fn new(program: &Program) -> SimulationResult<CliffordCircuit> {
+        let executor = Executor::new(program)?;
+        let mut rejected = vec![];
+        let ops = compile(&executor, executor.operations(), &mut rejected)?;
+        if let Some(stmt) = rejected.first() {
+            let name = match *stmt {
+                Statement::Apply(ref name, ..) => name.as_str(),
+                _ => "",
+            };
+            return Err(CircuitError::new(format!("'{}' is not a Clifford gate; {} operations are not Clifford.",
+                                                 name, rejected.len())));
+        }
+        Ok(CliffordCircuit { regs: executor.registers().clone(), ops })
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.regs
+    }
+
+    /// Runs the circuit on `tableau` and returns the classical bits.
+    pub fn run(&self, tableau: &mut Tableau, rng: &mut Rng) -> Vec<bool> {
+        let mut clbits = vec![false; self.regs.num_clbits()];
+        run_ops(&self.ops, tableau, &mut clbits, rng);
+        clbits
+    }
+}
+
+fn run_ops(ops: &[Op], tableau: &mut Tableau, clbits: &mut [bool], rng: &mut Rng) {
+    for op in ops {
+        match *op {
+            Op::Gates(ref gates) => {
+                for &g in gates {
+                    tableau.apply(g);
+                }
+            }
+            Op::Measure(q, c) => clbits[c] = tableau.measure(q, rng),
+            Op::Reset(q) => tableau.reset(q, rng),
+            Op::If(ref bits, val, ref body) => {
+                if simulator::register_equals(clbits, bits.clone(), val) {
+                    run_ops(body, tableau, clbits, rng);
+                }
+            }
+        }
+    }
+}
+
+/// Runs a Clifford program once and returns the final tableau and the classical bits.
+pub fn simulate(program: &Program, seed: Option<u64>) -> SimulationResult<(Tableau, Vec<bool>)> {
+    let circuit = CliffordCircuit::new(program)?;
+    let mut rng = seed.map(Rng::from_seed).unwrap_or_else(Rng::from_time);
+    let mut tableau = Tableau::new(circuit.regs.num_qubits());
+    let clbits = circuit.run(&mut tableau, &mut rng);
+    Ok((tableau, clbits))
+}
+
+/// Runs a Clifford program `shots` times and counts the measured bitstrings, keyed as in
+/// `shots::sample`.
+pub fn sample(program: &Program, shots: usize, seed: Option<u64>) -> SimulationResult<Counts> {
+    let circuit = CliffordCircuit::new(program)?;
+    let mut rng = seed.map(Rng::from_seed).unwrap_or_else(Rng::from_time);
+    let mut counts = Counts::new();
+    for _ in 0..shots {
+        let mut tableau = Tableau::new(circuit.regs.num_qubits());
+        let clbits = circuit.run(&mut tableau, &mut rng);
+        *counts.entry(shots::bitstring(&circuit.regs, &clbits)).or_insert(0) += 1;
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    #[test]
+    fn bell_and_ghz_states() {
+        let bell = parse("include \"qelib1.inc\"; qreg q[2]; h q[0]; cx q[0], q[1];");
+        let (tableau, _) = simulate(&bell, Some(0)).unwrap();
+        assert_eq!(tableau.stabilizers(), vec!["+XX", "+ZZ"]);
+
+        let mut code = String::from("include \"qelib1.inc\"; qreg q[300]; creg c[300]; h q[0];");
+        for i in 1..300 {
+            code += &format!("cx q[{}], q[{}];", i - 1, i);
+        }
+        code += "measure q -> c;";
+        let counts = sample(&parse(&code), 20, Some(1)).unwrap();
+        assert_eq!(counts.len(), 2);
+        assert!(counts.contains_key(&"0".repeat(300)) && counts.contains_key(&"1".repeat(300)));
+    }
+
+    #[test]
+    fn clifford_angles_match_state_vector() {
+        let code = "include \"qelib1.inc\"; qreg q[3]; creg c[3];\n\
+                    U(pi/2, 0, pi) q[0]; u3(pi, pi/2, -pi/2) q[1]; rx(pi/2) q[2]; cy q[0], q[2];\n\
+                    sx q[1]; ry(-pi/2) q[0]; swap q[0], q[1]; sdg q[2]; u2(1.5707963267948966, 0) q[2];\n\
+                    cz q[1], q[2]; y q[0]; measure q -> c;";
+        let counts = sample(&parse(code), 2000, Some(2)).unwrap();
+        let expected = shots::sample(&parse(code), 2000, Some(2)).unwrap();
+        assert_eq!(counts.keys().collect::<Vec<_>>(), expected.keys().collect::<Vec<_>>());
+        for (key, &n) in &counts {
+            assert!((n as i64 - expected[key] as i64).abs() < 150, "{:?} {:?}", counts, expected);
+        }
+    }
+
+    #[test]
+    fn mid_circuit_measurement_and_conditions() {
+        let code = "include \"qelib1.inc\"; qreg q[2]; creg a[1]; creg b[1];\n\
+                    h q[0]; measure q[0] -> a[0]; if (a == 1) x q[0]; if (a == 1) x q[1];\n\
+                    measure q[0] -> b[0]; reset q[1]; measure q[1] -> a[0];";
+        let counts = sample(&parse(code), 100, Some(3)).unwrap();
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec!["0 0"]);
+    }
+
+    #[test]
+    fn non_clifford_gates() {
+        let prog = parse("include \"qelib1.inc\"; qreg q[3]; h q[0]; t q[0]; rz(pi) q[1]; rz(0.3) q[1];\n\
+                          ccx q[0], q[1], q[2]; cz q[0], q[1];");
+        assert_eq!(non_clifford_operations(&prog).unwrap(),
+                   parse("t q[0]; rz(0.3) q[1]; ccx q[0], q[1], q[2];"));
+        assert!(simulate(&prog, Some(0)).is_err());
+        assert!(simulate(&parse("include \"qelib1.inc\"; qreg q[1]; rz(a) q[0];"), Some(0)).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Resource estimates of circuits: sizes, gate counts, depths, T-count and the critical path.
+//!
+//! Counts and depths are of the lowered program, so `h q;` on a register of two qubits counts
+//! as two `h`. Barriers appear in the gate counts, but like in Qiskit they add neither to the
+//! number of operations nor to the depth; they only order the operations on their qubits, so
+//! `h q[0]; barrier q; h q[1];` has depth 2.
+
+use std::collections::BTreeMap;
+
+use angle;
+use diagnostic::Span;
+use inline;
+use lower::{self, LowerResult, Registers};
+use parser::{Program, Statement};
+
+/// Statistics of a program, see `stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub num_qubits: usize,
+    pub num_clbits: usize,
+    /// The number of operations other than barriers.
+    pub num_operations: usize,
+    /// Operations by gate name, with `measure`, `reset` and `barrier` too.
+    pub gate_counts: BTreeMap<String, usize>,
+    /// `num_operations` after inlining all gates down to `U` and `CX`.
+    pub num_inlined_operations: usize,
+    pub inlined_gate_counts: BTreeMap<String, usize>,
+    /// The number of operations on the longest path through the circuit.
+    pub depth: usize,
+    /// The number of operations on two or more qubits on the path with the most of them.
+    pub two_qubit_depth: usize,
+    /// The number of T gates when the program is written with Clifford gates, T gates and
+    /// rotations. Rotations by angles other than multiples of pi/4 are not counted.
+    pub t_count: usize,
+    /// The spans of the statements on a longest path, in program order.
+    pub critical_path: Vec<Span>,
+}
+
+/// The name an operation is counted as.
+fn op_name(op: &Statement) -> Option<&str> {
+    match *op {
+        Statement::Apply(ref name, ..) => Some(name),
+        Statement::Measure(..) => Some("measure"),
+        Statement::Reset(_) => Some("reset"),
+        Statement::Barrier(_) => Some("barrier"),
+        Statement::If(_, _, ref body) => op_name(&body[0]),
+        _ => None,
+    }
+}
+
+fn count(ops: &[Statement]) -> (usize, BTreeMap<String, usize>) {
+    let mut counts = BTreeMap::new();
+    for name in ops.iter().filter_map(op_name) {
+        *counts.entry(name.to_owned()).or_insert(0) += 1;
+    }
+    let total = counts.iter().filter(|&(name, _)| name != "barrier").map(|(_, n)| n).sum();
+    (total, counts)
+}
+
+/// The wires an operation depends on: its qubits, the clbit it measures into and the clbits of
+/// its condition. Wires are numbered with the qubits first.
+fn op_wires(op: &Statement, regs: &Registers) -> Vec<usize> {
+    let nq = regs.num_qubits();
+    match *op {
+        Statement::Apply(_, _, ref qargs) => qargs.iter().map(|q| regs.qubit_index(q)).collect(),
+        Statement::Measure(ref q, ref c) => vec![regs.qubit_index(q), nq + regs.clbit_index(c)],
+        Statement::Reset(ref q) => vec![regs.qubit_index(q)],
+        Statement::Barrier(ref qargs) => qargs.iter().map(|q| regs.qubit_index(q)).collect(),
+        Statement::If(ref c, _, ref body) => {
+            let mut wires = op_wires(&body[0], regs);
+            wires.extend(regs.clbits(c).expect("The program is lowered.").map(|c| nq + c));
+            wires
+        }
+        _ => vec![],
+    }
+}
+
+fn num_qubit_args(op: &Statement) -> usize {
+    match *op {
+        Statement::Apply(_, _, ref qargs) => qargs.len(),
+        Statement::If(_, _, ref body) => num_qubit_args(&body[0]),
+        _ => 1,
+    }
+}
+
+/// Longest paths through operations, where an operation adds its weight to the longest path
+/// over its wires. Returns the length and the operations (as indices into `ops`) of a longest
+/// path, leaving out those of weight zero.
+fn longest_path<W: Fn(&Statement) -> usize>(ops: &[Statement], regs: &Registers, weight: W) -> (usize, Vec<usize>) {
+    let num_wires = regs.num_qubits() + regs.num_clbits();
+    // The length of the longest path ending on each wire, and its last operation.
+    let mut wires: Vec<(usize, Option<usize>)> = vec![(0, None); num_wires];
+    let mut prev: Vec<Option<usize>> = vec![None; ops.len()];
+    let mut lengths = vec![0; ops.len()];
+    for (i, op) in ops.iter().enumerate() {
+        let ws = op_wires(op, regs);
+        if ws.is_empty() {
+            continue;
+        }
+        let &(len, last) = ws.iter().map(|&w| &wires[w]).max_by_key(|&&(len, _)| len).unwrap();
+        prev[i] = last;
+        lengths[i] = len + weight(op);
+        for w in ws {
+            wires[w] = (lengths[i], Some(i));
+        }
+    }
+    let end = (0..ops.len()).filter(|&i| lengths[i] > 0).max_by_key(|&i| (lengths[i], ops.len() - i));
+    let mut path = vec![];
+    let mut cur = end;
+    while let Some(i) = cur {
+        if weight(&ops[i]) > 0 {
+            path.push(i);
+        }
+        cur = prev[i];
+    }
+    path.reverse();
+    (end.map(|i| lengths[i]).unwrap_or(0), path)
+}
+
+/// Gates kept when counting T gates.
+fn is_clifford_t(name: &str) -> bool {
+    matches!(name, "id" | "x" | "y" | "z" | "h" | "s" | "sdg" | "t" | "tdg" | "cx" | "cy" | "cz" | "swap" |
+                   "u1" | "rz" | "rx" | "ry")
+}
+
+fn t_count(ops: &[Statement]) -> usize {
+    ops.iter().filter(|op| {
+        let (name, params) = match **op {
+            Statement::Apply(ref name, ref params, _) => (name, params),
+            Statement::If(_, _, ref body) => match body[0] {
+                Statement::Apply(ref name, ref params, _) => (name, params),
+                _ => return false,
+            },
+            _ => return false,
+        };
+        let angle = match name.as_str() {
+            "t" | "tdg" => return true,
+            "u1" | "rz" | "rx" | "ry" => params[0].snapped_angle(),
+            "U" => angle::u_phase(params),
+            _ => None,
+        };
+        angle.map(|a| a.is_t()).unwrap_or(false)
+    }).count()
+}
+
+/// Computes statistics of a program. `spans` are the spans of its top-level statements, see
+/// `parser::parse_str_with_spans`.
+pub fn stats(program: &Program, spans: &[Span]) -> LowerResult<Stats> {
+    let regs = Registers::from_program(program)?;
+    let mut ops = vec![];
+    // The top-level statement of each lowered operation.
+    let mut sources = vec![];
+    for (i, stmt) in program.iter().enumerate() {
+        let before = ops.len();
+        lower::lower_statement(stmt, &regs, &mut ops)?;
+        sources.extend((before..ops.len()).map(|_| i));
+    }
+    let (num_operations, gate_counts) = count(&ops);
+    let (num_inlined_operations, inlined_gate_counts) = count(&inline::inline(program)?);
+    let (depth, path) = longest_path(&ops, &regs, |op| if let Statement::Barrier(_) = *op { 0 } else { 1 });
+    let (two_qubit_depth, _) = longest_path(&ops, &regs, |op| if num_qubit_args(op) >= 2 { 1 } else { 0 });
+
+    let mut critical_path: Vec<Span> = vec![];
+    for i in path {
+        if let Some(&span) = spans.get(sources[i]) {
+            if critical_path.last() != Some(&span) {
+                critical_path.push(span);
+            }
+        }
+    }
+    Ok(Stats {
+        num_qubits: regs.num_qubits(),
+        num_clbits: regs.num_clbits(),
+        num_operations,
+        gate_counts,
+        num_inlined_operations,
+        inlined_gate_counts,
+        depth,
+        two_qubit_depth,
+        t_count: t_count(&inline::inline_with(program, is_clifford_t)?),
+        critical_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use parser::parse_str_with_spans;
+
+    fn stats_str(code: &str) -> (Stats, Vec<String>) {
+        let (program, spans) = parse_str_with_spans(code, Path::new("test.qasm")).unwrap();
+        let stats = stats(&program, &spans).unwrap();
+        let path = stats.critical_path.iter().map(|s| code[s.pos..s.pos + s.len].to_owned()).collect();
+        (stats, path)
+    }
+
+    #[test]
+    fn counts_and_depths() {
+        let code = "include \"qelib1.inc\";\nqreg q[3];\ncreg c[3];\nh q;\ncx q[0], q[1];\nbarrier q;\n\
+                    cx q[1], q[2];\nx q[0];\nmeasure q -> c;\nif (c == 1) z q[2];";
+        let (stats, path) = stats_str(code);
+        assert_eq!((stats.num_qubits, stats.num_clbits), (3, 3));
+        assert_eq!(stats.num_operations, 10);
+        let counts: Vec<(&str, usize)> = stats.gate_counts.iter().map(|(k, &v)| (k.as_str(), v)).collect();
+        assert_eq!(counts, [("barrier", 1), ("cx", 2), ("h", 3), ("measure", 3), ("x", 1), ("z", 1)]);
+        assert_eq!(stats.num_inlined_operations, 10);
+        assert_eq!(stats.inlined_gate_counts["U"], 5);
+        assert_eq!(stats.inlined_gate_counts["CX"], 2);
+        // h, cx, cx, measure q[2], then the condition on all of c.
+        assert_eq!(stats.depth, 5);
+        assert_eq!(stats.two_qubit_depth, 2);
+        assert_eq!(path, ["h q;", "cx q[0], q[1];", "cx q[1], q[2];", "measure q -> c;", "if (c == 1) z q[2];"]);
+        // The barrier orders the gates on different qubits.
+        let (stats, path) = stats_str("include \"qelib1.inc\";\nqreg q[2];\nh q[0];\nbarrier q;\nh q[1];");
+        assert_eq!((stats.depth, stats.num_operations), (2, 2));
+        assert_eq!(path, ["h q[0];", "h q[1];"]);
+    }
+
+    #[test]
+    fn count_t_gates() {
+        let code = "include \"qelib1.inc\";\nqreg q[3];\nccx q[0], q[1], q[2];\nt q[0];\nrz(pi/4) q[1];\n\
+                    u1(3*pi/4) q[2];\nrz(pi/2) q[0];\nrz(0.1) q[0];\nU(0, pi/8, pi/8) q[1];";
+        let (stats, _) = stats_str(code);
+        assert_eq!(stats.t_count, 7 + 4);
+        let (stats, path) = stats_str("qreg q[1];");
+        assert_eq!((stats.depth, stats.t_count, stats.num_operations), (0, 0, 0));
+        assert!(path.is_empty());
+    }
+}
@@ -0,0 +1,18 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::Path;
+
+use parser::{parse_str, Expr, Program, Statement};
+
+/// Parses a program which is known to be valid.
+pub fn parse(code: &str) -> Program {
+    parse_str(code, Path::new("test.qasm")).unwrap()
+}
+
+/// Parses a single parameter expression, e.g. `pi/2 + a`.
+pub fn expr(s: &str) -> Expr {
+    match parse(&format!("g({}) q;", s))[0] {
+        Statement::Apply(_, ref params, _) => params[0].clone(),
+        _ => unreachable!(),
+    }
+}
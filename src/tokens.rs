@@ -10,7 +10,7 @@ pub struct Token {
     pub len: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     Real(f64),
     UInt(usize),
@@ -61,16 +61,10 @@ pub enum TokenType {
 
 impl Token {
     pub fn is_comment(&self) -> bool {
-        match self.token {
-            TokenType::Comment(_) => true,
-            _ => false
-        }
+        matches!(self.token, TokenType::Comment(_))
     }
     pub fn is_include(&self) -> bool {
-        match self.token {
-            TokenType::Include => true,
-            _ => false
-        }
+        matches!(self.token, TokenType::Include)
     }
 }
 
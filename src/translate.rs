@@ -0,0 +1,274 @@
+//! Translation of programs into a basis of gates, e.g. the native gates of a backend.
+//!
+//! An equivalence library holds rules, each a gate declaration whose body is equivalent to the
+//! gate up to a global phase. The same gate may have several rules. `translate` picks for each
+//! gate outside the basis the rule which leads to the fewest basis gates, and expands gate
+//! applications with their rules until only basis gates remain.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use errors::CircuitError;
+use inline::{GateDef, GateTable, QELIB1, QELIB1_FILENAME};
+use lower::{self, LowerResult};
+use parser::{self, Expr, Program, QReg, Statement};
+
+/// Rules besides the definitions in `qelib1.inc`, for the common bases `rz, sx, x, cx`,
+/// `rx, rz, cz` and `u3, cx`. `U` and `CX` are keywords, so their rules cannot be written here;
+/// `EquivalenceLibrary::standard` adds `U` as `u3` and `CX` as `cx`.
+pub const STANDARD_EQUIVALENCES: &str = "\
+gate u3(theta, phi, lambda) a { rz(lambda) a; sx a; rz(theta + pi) a; sx a; rz(phi + pi) a; }
+gate u3(theta, phi, lambda) a { rz(lambda - pi/2) a; rx(theta) a; rz(phi + pi/2) a; }
+gate u3(theta, phi, lambda) a { rz(lambda) a; ry(theta) a; rz(phi) a; }
+gate cx c, t { h t; cz c, t; h t; }
+gate u1(lambda) a { rz(lambda) a; }
+gate p(lambda) a { rz(lambda) a; }
+gate z a { rz(pi) a; }
+gate s a { rz(pi/2) a; }
+gate sdg a { rz(-pi/2) a; }
+gate t a { rz(pi/4) a; }
+gate tdg a { rz(-pi/4) a; }
+gate h a { rz(pi/2) a; sx a; rz(pi/2) a; }
+gate h a { rz(pi/2) a; rx(pi/2) a; rz(pi/2) a; }
+gate x a { rx(pi) a; }
+gate sx a { rx(pi/2) a; }
+gate rx(theta) a { h a; rz(theta) a; h a; }
+gate ry(theta) a { rz(-pi/2) a; rx(theta) a; rz(pi/2) a; }
+";
+
+/// A rule applying `target` for `name`, e.g. `gate U(theta, phi, lambda) a { u3(theta, phi, lambda) a; }`.
+fn alias(name: &str, target: &str, params: &[&str], qargs: &[&str]) -> Statement {
+    let params: Vec<Expr> = params.iter().map(|p| Expr::Id(p.to_string())).collect();
+    let qargs: Vec<QReg> = qargs.iter().map(|q| QReg::Reg(q.to_string())).collect();
+    let body = vec![Statement::Apply(target.to_owned(), params.clone(), qargs.clone())];
+    Statement::Gate(name.to_owned(), params, qargs, body)
+}
+
+/// Rules for rewriting gates in terms of other gates.
+#[derive(Debug, Clone, Default)]
+pub struct EquivalenceLibrary {
+    rules: BTreeMap<String, Vec<GateDef>>,
+}
+
+impl EquivalenceLibrary {
+    pub fn new() -> EquivalenceLibrary {
+        EquivalenceLibrary::default()
+    }
+
+    /// The definitions of the gates in `qelib1.inc` and `STANDARD_EQUIVALENCES`.
+    pub fn standard() -> EquivalenceLibrary {
+        let mut library = EquivalenceLibrary::new();
+        library.add_qasm(QELIB1).expect("qelib1.inc must be valid.");
+        library.add_qasm(STANDARD_EQUIVALENCES).expect("The standard equivalences must be valid.");
+        library.add(&alias("U", "u3", &["theta", "phi", "lambda"], &["a"])).unwrap();
+        library.add(&alias("CX", "cx", &[], &["c", "t"])).unwrap();
+        library
+    }
+
+    /// Adds a rule: a gate declaration whose body is equivalent to the gate. Rules for `U` and
+    /// `CX` can only be added this way.
+    pub fn add(&mut self, declaration: &Statement) -> LowerResult<()> {
+        match GateDef::from_declaration(declaration, false)? {
+            Some((name, ref def)) if def.body.is_none() => {
+                Err(CircuitError::new(format!("The rule for '{}' is an opaque gate.", name)))
+            }
+            Some((name, def)) => {
+                self.rules.entry(name).or_default().push(def);
+                Ok(())
+            }
+            None => Err(CircuitError::new("A rule must be a gate declaration.".to_owned())),
+        }
+    }
+
+    /// Adds the gate declarations of OpenQASM source as rules. Other statements are ignored.
+    pub fn add_qasm(&mut self, source: &str) -> LowerResult<()> {
+        let program = parser::parse_str(source, Path::new("<equivalences>"))
+            .map_err(|e| CircuitError::new(format!("Invalid equivalences: {}", e)))?;
+        for stmt in program.iter().filter(|s| matches!(**s, Statement::Gate(..))) {
+            self.add(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// The rules for a gate.
+    pub fn rules(&self, name: &str) -> &[GateDef] {
+        self.rules.get(name).map(|r| r.as_slice()).unwrap_or(&[])
+    }
+}
+
+/// The gates applied in the body of a rule.
+fn body_gates(def: &GateDef) -> Vec<&str> {
+    def.body.iter().flatten().filter_map(|stmt| match *stmt {
+        Statement::Apply(ref name, ..) => Some(name.as_str()),
+        _ => None,
+    }).collect()
+}
+
+/// For each gate which can be translated, the rule to use: the one leading to the fewest
+/// basis gates. Rules come from the declarations in the program for gates it declares, and
+/// from the library for `U`, `CX` and the gates in `qelib1.inc`.
+fn choose_rules<'a>(basis: &BTreeSet<&str>, library: &'a EquivalenceLibrary, table: &'a GateTable,
+                    declared: &'a [String]) -> BTreeMap<&'a str, &'a GateDef> {
+    let mut rules: Vec<(&str, &GateDef)> = vec![];
+    for (name, defs) in &library.rules {
+        if !declared.contains(name) {
+            rules.extend(defs.iter().map(|def| (name.as_str(), def)));
+        }
+    }
+    for name in declared {
+        if let Some(def) = table.get(name) {
+            if def.body.is_some() {
+                rules.push((name, def));
+            }
+        }
+    }
+    let mut costs: BTreeMap<&str, usize> = basis.iter().map(|&name| (name, 1)).collect();
+    let mut chosen = BTreeMap::new();
+    loop {
+        let mut changed = false;
+        for &(name, def) in &rules {
+            if basis.contains(name) {
+                continue;
+            }
+            let cost = body_gates(def).iter().map(|g| costs.get(g).cloned()).sum::<Option<usize>>();
+            if let Some(cost) = cost {
+                if costs.get(name).map(|&c| cost < c).unwrap_or(true) {
+                    costs.insert(name, cost);
+                    chosen.insert(name, def);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return chosen;
+        }
+    }
+}
+
+fn basis_list(basis: &BTreeSet<&str>) -> String {
+    basis.iter().cloned().collect::<Vec<_>>().join(", ")
+}
+
+fn translate_statement(stmt: &Statement, basis: &BTreeSet<&str>, rules: &BTreeMap<&str, &GateDef>,
+                       out: &mut Program) -> LowerResult<()> {
+    match *stmt {
+        Statement::Apply(ref name, ref params, ref qargs) if !basis.contains(name.as_str()) => {
+            let def = rules.get(name.as_str()).ok_or_else(|| {
+                CircuitError::new(format!("Gate '{}' cannot be translated into the basis {}.", name, basis_list(basis)))
+            })?;
+            for stmt in &def.instantiate(name, params, qargs)? {
+                translate_statement(stmt, basis, rules, out)?;
+            }
+        }
+        Statement::If(ref c, val, ref body) => {
+            let mut translated = vec![];
+            for stmt in body {
+                translate_statement(stmt, basis, rules, &mut translated)?;
+            }
+            out.extend(translated.into_iter().map(|s| Statement::If(c.clone(), val, vec![s])));
+        }
+        _ => out.push(stmt.clone()),
+    }
+    Ok(())
+}
+
+/// Lowers a program and rewrites it so that it only applies gates in `basis`, using the rules
+/// of `library` and the gate declarations of the program. The result equals the program up to
+/// a global phase. Declarations of gates outside the basis are removed, and `qelib1.inc` is
+/// included if the basis has gates from it. Fails naming the first gate without a way into the
+/// basis.
+pub fn translate(program: &Program, basis: &[&str], library: &EquivalenceLibrary) -> LowerResult<Program> {
+    let basis: BTreeSet<&str> = basis.iter().cloned().collect();
+    let table = GateTable::from_program(program)?;
+    table.check_program(program)?;
+    let declared: Vec<String> = program.iter().filter_map(|stmt| match *stmt {
+        Statement::Gate(ref name, ..) | Statement::Opaque(ref name, ..) => Some(name.clone()),
+        _ => None,
+    }).collect();
+    let rules = choose_rules(&basis, library, &table, &declared);
+
+    let qelib1 = GateTable::from_program(&vec![Statement::Include(QELIB1_FILENAME.to_owned())])?;
+    let needs_qelib1 = basis.iter().any(|&name| !declared.iter().any(|d| d == name) && qelib1.get(name).is_some());
+    let lowered = lower::lower(program)?;
+    let include = Statement::Include(QELIB1_FILENAME.to_owned());
+    let add_include = needs_qelib1 && !lowered.contains(&include);
+    let mut out = vec![];
+    if add_include && !matches!(lowered.first(), Some(&Statement::Openqasm(_))) {
+        out.push(include.clone());
+    }
+    for stmt in &lowered {
+        match *stmt {
+            Statement::Gate(ref name, ..) | Statement::Opaque(ref name, ..) if !basis.contains(name.as_str()) => {}
+            Statement::Openqasm(_) if add_include => {
+                out.push(stmt.clone());
+                out.push(include.clone());
+            }
+            _ => translate_statement(stmt, &basis, &rules, &mut out)?,
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+    use unitary::unitary;
+
+    /// The names of the applied gates.
+    fn gate_names(program: &Program) -> BTreeSet<String> {
+        program.iter().filter_map(|stmt| match *stmt {
+            Statement::Apply(ref name, ..) => Some(name.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn translate_into_common_bases() {
+        let program = parse("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[3];\ngate g(a) x, y { rzz(a) x, y; sdg y; }\n\
+                             h q[0];\nt q[1];\nccx q[0], q[1], q[2];\nrx(0.3) q[2];\nry(1.1) q[0];\nswap q[0], q[2];\n\
+                             cu1(0.7) q[1], q[0];\ng(0.2) q[2], q[1];\nU(0.1, 0.2, 0.3) q[1];\nCX q[2], q[0];\nsx q[1];");
+        let expected = unitary(&program).unwrap();
+        for basis in &[&["rz", "sx", "x", "cx"][..], &["rx", "rz", "cz"], &["u3", "cx"], &["U", "CX"]] {
+            let translated = translate(&program, basis, &EquivalenceLibrary::standard()).unwrap();
+            let names = gate_names(&translated);
+            assert!(names.iter().all(|n| basis.contains(&n.as_str())), "{:?} not in {:?}", names, basis);
+            assert!(unitary(&translated).unwrap().equiv_up_to_global_phase(&expected, 1e-9), "{:?}", basis);
+        }
+    }
+
+    #[test]
+    fn include_and_conditions() {
+        let program = parse("OPENQASM 2.0;\nqreg q[1];\ncreg c[1];\nU(pi/2, 0, pi) q[0];\nif (c == 0) U(pi, 0, pi) q[0];");
+        let translated = translate(&program, &["rz", "sx"], &EquivalenceLibrary::standard()).unwrap();
+        assert_eq!(translated[..2], [Statement::Openqasm(2.0), Statement::Include(QELIB1_FILENAME.to_owned())][..]);
+        assert_eq!(translated.iter().filter(|s| matches!(**s, Statement::If(..))).count(), 5);
+        assert!(!gate_names(&translated).contains("U"));
+    }
+
+    #[test]
+    fn untranslatable_gates_and_custom_rules() {
+        let program = parse("include \"qelib1.inc\";\nopaque magic a;\nqreg q[2];\nh q[0];\ncx q[0], q[1];\nmagic q[1];");
+        let library = EquivalenceLibrary::standard();
+        let err = translate(&program, &["rz", "sx", "cx"], &library).unwrap_err();
+        assert_eq!(err.to_string(), "Gate 'magic' cannot be translated into the basis cx, rz, sx.");
+        let err = translate(&program, &["rz", "sx", "magic"], &library).unwrap_err();
+        assert!(err.to_string().starts_with("Gate 'cx' cannot be translated"), "{}", err);
+
+        let mut library = EquivalenceLibrary::standard();
+        library.add_qasm("gate cx c, t { ry(pi/2) t; magic t; cnot c, t; }").unwrap();
+        let translated = translate(&program, &["U", "cnot", "magic"], &library).unwrap();
+        let names: Vec<String> = gate_names(&translated).into_iter().collect();
+        assert_eq!(names, ["U", "cnot", "magic"]);
+        assert!(library.add(&parse("opaque cnot a, b;")[0]).is_err());
+    }
+
+    #[test]
+    fn malformed_applications() {
+        let library = EquivalenceLibrary::standard();
+        for body in &["rz q[0];", "cx q[0];", "creg c[1];\nif (c == 0) h q[0], q[1];"] {
+            let program = parse(&format!("include \"qelib1.inc\";\nqreg q[2];\n{}", body));
+            assert!(translate(&program, &["rz", "sx", "cx", "h"], &library).is_err(), "{}", body);
+        }
+    }
+}
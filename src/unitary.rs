@@ -0,0 +1,165 @@
+//! Unitary matrices of measurement-free circuits and of gate declarations.
+//!
+//! The matrix of a circuit on `n` qubits has dimension `2^n`. Qubits are ordered as in the
+//! simulator: qubit `k` (by global index, see `lower::Registers`) is bit `k` of the row and
+//! column indices, so `q[0]` is the least significant bit. This is the ordering used by Qiskit.
+//! For a gate declaration, its first argument is the least significant bit. For example `cx a, b`
+//! maps column `0b01` (`a = 1`, `b = 0`) to row `0b11`.
+
+use std::ops::Index;
+
+use complex::{Complex, ONE, ZERO};
+use errors::CircuitError;
+use parser::{Expr, Program, QReg, Statement};
+use rng::Rng;
+use simulator::{Executor, SimulationResult, StateVector};
+
+/// The largest number of qubits a unitary is computed for.
+pub const MAX_UNITARY_QUBITS: usize = 12;
+
+/// A square matrix of dimension `2^num_qubits`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unitary {
+    num_qubits: usize,
+    /// Row-major entries.
+    data: Vec<Complex>,
+}
+
+impl Unitary {
+    pub fn identity(num_qubits: usize) -> Unitary {
+        let dim = 1 << num_qubits;
+        let mut data = vec![ZERO; dim * dim];
+        for i in 0..dim {
+            data[i * dim + i] = ONE;
+        }
+        Unitary { num_qubits, data }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    pub fn dim(&self) -> usize {
+        1 << self.num_qubits
+    }
+
+    /// Rows of the matrix.
+    pub fn rows(&self) -> ::std::slice::Chunks<'_, Complex> {
+        self.data.chunks(self.dim())
+    }
+
+    /// Whether all entries differ by at most `tol`.
+    pub fn approx_eq(&self, other: &Unitary, tol: f64) -> bool {
+        self.num_qubits == other.num_qubits &&
+            self.data.iter().zip(&other.data).all(|(a, b)| a.approx_eq(*b, tol))
+    }
+
+    /// Whether `other` equals this matrix times some phase `e^(i phi)`, up to `tol` per entry.
+    pub fn equiv_up_to_global_phase(&self, other: &Unitary, tol: f64) -> bool {
+        if self.num_qubits != other.num_qubits {
+            return false;
+        }
+        // The phase is taken from the largest entry, which is at least 1/sqrt(dim) for a unitary.
+        let (idx, _) = self.data.iter().enumerate()
+            .fold((0, 0.0), |(bi, bv), (i, a)| if a.abs() > bv { (i, a.abs()) } else { (bi, bv) });
+        let phase = Complex::expi(other.data[idx].arg() - self.data[idx].arg());
+        self.data.iter().zip(&other.data).all(|(a, b)| (*a * phase).approx_eq(*b, tol))
+    }
+}
+
+impl Index<(usize, usize)> for Unitary {
+    type Output = Complex;
+
+    /// The entry at `(row, column)`.
+    fn index(&self, (row, col): (usize, usize)) -> &Complex {
+        &self.data[row * self.dim() + col]
+    }
+}
+
+/// The unitary of a program without `measure`, `reset` and `if`. All parameters must be bound.
+pub fn unitary(program: &Program) -> SimulationResult<Unitary> {
+    let executor = Executor::new(program)?;
+    if let Some(op) = executor.non_unitary_operation() {
+        return Err(CircuitError::new(format!("Cannot compute the unitary of a circuit with '{}'.", op)));
+    }
+    let n = executor.registers().num_qubits();
+    if n > MAX_UNITARY_QUBITS {
+        return Err(CircuitError::new(format!("{} qubits are too many to compute a unitary.", n)));
+    }
+    let dim = 1 << n;
+    let mut data = vec![ZERO; dim * dim];
+    // There are no measurements, so the generator is never used.
+    let mut rng = Rng::from_seed(0);
+    for col in 0..dim {
+        let mut amps = vec![ZERO; dim];
+        amps[col] = ONE;
+        let mut state = StateVector::from_amplitudes(amps);
+        executor.run(&mut state, &mut rng)?;
+        for (row, a) in state.amplitudes().iter().enumerate() {
+            data[row * dim + col] = *a;
+        }
+    }
+    Ok(Unitary { num_qubits: n, data })
+}
+
+/// The unitary of a gate declaration with the given parameter values. `declarations` are the
+/// statements the gate body may refer to, e.g. `include "qelib1.inc";` or other gates.
+pub fn gate_unitary(declarations: &Program, gate: &Statement, params: &[f64]) -> SimulationResult<Unitary> {
+    let (name, nqargs) = match *gate {
+        Statement::Gate(ref name, _, ref qargs, _) => (name, qargs.len()),
+        _ => return Err(CircuitError::new("Expected a gate declaration.".to_owned())),
+    };
+    let mut program: Program = declarations.iter().filter(|s| match **s {
+        Statement::Include(..) | Statement::Opaque(..) => true,
+        Statement::Gate(ref other, ..) => other != name,
+        _ => false,
+    }).cloned().collect();
+    program.push(gate.clone());
+    program.push(Statement::Qreg("q".to_owned(), nqargs as u32));
+    let qargs = (0..nqargs).map(|i| QReg::Bit("q".to_owned(), i as u32)).collect();
+    program.push(Statement::Apply(name.clone(), params.iter().map(|&p| Expr::Real(p)).collect(), qargs));
+    unitary(&program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::parse;
+
+    #[test]
+    fn cx_ordering() {
+        let u = unitary(&parse("include \"qelib1.inc\"; qreg q[2]; cx q[0], q[1];")).unwrap();
+        assert_eq!(u.dim(), 4);
+        for &(row, col) in &[(0, 0), (3, 1), (2, 2), (1, 3)] {
+            assert_eq!(u[(row, col)], ONE);
+        }
+        assert_eq!(u[(1, 1)], ZERO);
+    }
+
+    #[test]
+    fn gate_declarations_up_to_global_phase() {
+        let lib = parse("include \"qelib1.inc\";");
+        let decl = &parse("gate myrz(a) q { U(0, 0, a) q; }")[0];
+        let rz = unitary(&parse("include \"qelib1.inc\"; qreg q[1]; rz(0.7) q[0];")).unwrap();
+        assert!(gate_unitary(&lib, decl, &[0.7]).unwrap().approx_eq(&rz, 1e-12));
+
+        let decl = &parse("gate mycz a, b { h b; CX a, b; h b; }")[0];
+        let cz = unitary(&parse("include \"qelib1.inc\"; qreg q[2]; cz q[0], q[1];")).unwrap();
+        assert!(gate_unitary(&lib, decl, &[]).unwrap().approx_eq(&cz, 1e-12));
+
+        // rx(pi) is -iX, so this is rz(0.7) with a global phase of -i.
+        let shifted = "include \"qelib1.inc\"; qreg q[1]; rx(pi) q[0]; x q[0]; rz(0.7) q[0];";
+        let shifted = unitary(&parse(shifted)).unwrap();
+        assert!(!rz.approx_eq(&shifted, 1e-9));
+        assert!(rz.equiv_up_to_global_phase(&shifted, 1e-9));
+        assert!(!rz.equiv_up_to_global_phase(&Unitary::identity(1), 1e-9));
+    }
+
+    #[test]
+    fn non_unitary_operations() {
+        assert!(unitary(&parse("qreg q[1]; creg c[1]; measure q[0] -> c[0];")).is_err());
+        assert!(unitary(&parse("qreg q[1]; reset q[0];")).is_err());
+        assert!(unitary(&parse("qreg q[1]; creg c[1]; if (c == 1) U(0, 0, 0) q[0];")).is_err());
+        assert!(gate_unitary(&vec![], &parse("qreg q[1];")[0], &[]).is_err());
+    }
+}